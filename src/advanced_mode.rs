@@ -0,0 +1,107 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Advanced mode of D400 devices, which realsense-rust doesn't wrap.
+
+use crate::presets::validate_preset;
+use anyhow::{bail, Result};
+use realsense_rust::device::Device;
+use std::ffi::CStr;
+use std::os::raw::c_int;
+
+/// Checks whether advanced mode is enabled. Fails for devices without it.
+pub fn is_enabled(device: &Device) -> Result<bool> {
+    let mut enabled: c_int = 0;
+    let mut err = std::ptr::null_mut();
+    unsafe {
+        realsense_sys::rs2_is_enabled(device.get_raw().as_ptr(), &mut enabled, &mut err);
+        check_error(err)?;
+    }
+    Ok(enabled != 0)
+}
+
+/// Enables or disables advanced mode. The device resets and re-enumerates.
+pub fn toggle(device: &Device, enable: bool) -> Result<()> {
+    let mut err = std::ptr::null_mut();
+    unsafe {
+        realsense_sys::rs2_toggle_advanced_mode(
+            device.get_raw().as_ptr(),
+            enable as c_int,
+            &mut err,
+        );
+        check_error(err)
+    }
+}
+
+/// Validates and applies a JSON preset
+pub fn load_json(device: &Device, json: &str) -> Result<()> {
+    validate_preset(json)?;
+    if !is_enabled(device)? {
+        bail!("Advanced mode is disabled");
+    }
+    let mut err = std::ptr::null_mut();
+    unsafe {
+        realsense_sys::rs2_load_json(
+            device.get_raw().as_ptr(),
+            json.as_ptr().cast(),
+            json.len() as u32,
+            &mut err,
+        );
+        check_error(err)
+    }
+}
+
+/// Gets the current parameters as a JSON preset
+pub fn serialize_json(device: &Device) -> Result<String> {
+    let mut err = std::ptr::null_mut();
+    unsafe {
+        let buffer = realsense_sys::rs2_serialize_json(device.get_raw().as_ptr(), &mut err);
+        check_error(err)?;
+        let result = read_raw_data(buffer);
+        realsense_sys::rs2_delete_raw_data(buffer);
+        result
+    }
+}
+
+unsafe fn read_raw_data(buffer: *const realsense_sys::rs2_raw_data_buffer) -> Result<String> {
+    let mut err = std::ptr::null_mut();
+    let size = realsense_sys::rs2_get_raw_data_size(buffer, &mut err);
+    check_error(err)?;
+    if size <= 0 {
+        return Ok(String::new());
+    }
+    let data = realsense_sys::rs2_get_raw_data(buffer, &mut err);
+    check_error(err)?;
+    if data.is_null() {
+        bail!("The device returned no data");
+    }
+    let bytes = std::slice::from_raw_parts(data, size as usize);
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Converts and frees a librealsense error
+pub(crate) unsafe fn check_error(err: *mut realsense_sys::rs2_error) -> Result<()> {
+    if err.is_null() {
+        return Ok(());
+    }
+    let message = CStr::from_ptr(realsense_sys::rs2_get_error_message(err))
+        .to_string_lossy()
+        .into_owned();
+    realsense_sys::rs2_free_error(err);
+    bail!(message)
+}
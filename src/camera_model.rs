@@ -0,0 +1,314 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Calibration of the streams, owned and serializable.
+
+use glam::{Mat3, Vec2, Vec3};
+use realsense_rust::base::{Rs2Extrinsics, Rs2Intrinsics, Rs2MotionDeviceIntrinsics};
+use realsense_rust::kind::Rs2DistortionModel;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum DistortionModel {
+    None,
+    ModifiedBrownConrady,
+    InverseBrownConrady,
+    FTheta,
+    BrownConrady,
+    KannalaBrandt4,
+}
+
+impl From<Rs2DistortionModel> for DistortionModel {
+    fn from(model: Rs2DistortionModel) -> Self {
+        match model {
+            Rs2DistortionModel::None => Self::None,
+            Rs2DistortionModel::BrownConradyModified => Self::ModifiedBrownConrady,
+            Rs2DistortionModel::BrownConradyInverse => Self::InverseBrownConrady,
+            Rs2DistortionModel::FThetaFisheye => Self::FTheta,
+            Rs2DistortionModel::BrownConrady => Self::BrownConrady,
+            Rs2DistortionModel::KannalaBrandt => Self::KannalaBrandt4,
+        }
+    }
+}
+
+/// Pinhole camera with distortion, as librealsense describes it
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Intrinsics {
+    pub width: usize,
+    pub height: usize,
+    pub ppx: f32,
+    pub ppy: f32,
+    pub fx: f32,
+    pub fy: f32,
+    pub model: DistortionModel,
+    pub coeffs: [f32; 5],
+}
+
+impl From<&Rs2Intrinsics> for Intrinsics {
+    fn from(intrinsics: &Rs2Intrinsics) -> Self {
+        let distortion = intrinsics.distortion();
+        Self {
+            width: intrinsics.width(),
+            height: intrinsics.height(),
+            ppx: intrinsics.ppx(),
+            ppy: intrinsics.ppy(),
+            fx: intrinsics.fx(),
+            fy: intrinsics.fy(),
+            model: distortion.model.into(),
+            coeffs: distortion.coeffs,
+        }
+    }
+}
+
+impl Intrinsics {
+    /// Point in meters, in the camera frame, seen at a pixel at some depth.
+    /// Same as `rs2_deproject_pixel_to_point`, which leaves the modified
+    /// model distorted. Here it is undone like the inverse one, as both
+    /// project the same way.
+    pub fn deproject(&self, pixel: Vec2, depth: f32) -> Vec3 {
+        let c = &self.coeffs;
+        let (xo, yo) = (
+            (pixel.x - self.ppx) / self.fx,
+            (pixel.y - self.ppy) / self.fy,
+        );
+        let (mut x, mut y) = (xo, yo);
+        match self.model {
+            DistortionModel::ModifiedBrownConrady | DistortionModel::InverseBrownConrady => {
+                // Iterate until convergence
+                for _ in 0..10 {
+                    let r2 = x * x + y * y;
+                    let icdist = 1.0 / (1.0 + ((c[4] * r2 + c[1]) * r2 + c[0]) * r2);
+                    let (xq, yq) = (x / icdist, y / icdist);
+                    let delta_x = 2.0 * c[2] * xq * yq + c[3] * (r2 + 2.0 * xq * xq);
+                    let delta_y = 2.0 * c[3] * xq * yq + c[2] * (r2 + 2.0 * yq * yq);
+                    x = (xo - delta_x) * icdist;
+                    y = (yo - delta_y) * icdist;
+                }
+            }
+            DistortionModel::BrownConrady => {
+                for _ in 0..10 {
+                    let r2 = x * x + y * y;
+                    let icdist = 1.0 / (1.0 + ((c[4] * r2 + c[1]) * r2 + c[0]) * r2);
+                    let delta_x = 2.0 * c[2] * x * y + c[3] * (r2 + 2.0 * x * x);
+                    let delta_y = 2.0 * c[3] * x * y + c[2] * (r2 + 2.0 * y * y);
+                    x = (xo - delta_x) * icdist;
+                    y = (yo - delta_y) * icdist;
+                }
+            }
+            DistortionModel::KannalaBrandt4 => {
+                let rd = (x * x + y * y).sqrt().max(f32::EPSILON);
+                let mut theta = rd;
+                let mut theta2 = rd * rd;
+                // Newton's method
+                for _ in 0..4 {
+                    let f = theta
+                        * (1.0
+                            + theta2 * (c[0] + theta2 * (c[1] + theta2 * (c[2] + theta2 * c[3]))))
+                        - rd;
+                    if f.abs() < f32::EPSILON {
+                        break;
+                    }
+                    let df = 1.0
+                        + theta2
+                            * (3.0 * c[0]
+                                + theta2
+                                    * (5.0 * c[1] + theta2 * (7.0 * c[2] + 9.0 * theta2 * c[3])));
+                    theta -= f / df;
+                    theta2 = theta * theta;
+                }
+                let r = theta.tan();
+                x *= r / rd;
+                y *= r / rd;
+            }
+            DistortionModel::FTheta => {
+                let rd = (x * x + y * y).sqrt().max(f32::EPSILON);
+                // librealsense takes the arctangent of the denominator, which
+                // doesn't invert the projection
+                let r = (c[0] * rd).tan() / (2.0 * (c[0] / 2.0).tan());
+                x *= r / rd;
+                y *= r / rd;
+            }
+            DistortionModel::None => (),
+        }
+        Vec3::new(depth * x, depth * y, depth)
+    }
+
+    /// Pixel where a point in the camera frame is seen.
+    /// Same as `rs2_project_point_to_pixel`.
+    pub fn project(&self, point: Vec3) -> Vec2 {
+        let c = &self.coeffs;
+        let (mut x, mut y) = (point.x / point.z, point.y / point.z);
+        match self.model {
+            DistortionModel::ModifiedBrownConrady
+            | DistortionModel::InverseBrownConrady
+            | DistortionModel::BrownConrady => {
+                let r2 = x * x + y * y;
+                let f = 1.0 + c[0] * r2 + c[1] * r2 * r2 + c[4] * r2 * r2 * r2;
+                let (xf, yf) = (x * f, y * f);
+                // The modified and inverse models apply the tangential terms
+                // after the radial ones
+                let (xt, yt) = if self.model == DistortionModel::BrownConrady {
+                    (x, y)
+                } else {
+                    (xf, yf)
+                };
+                let dx = xf + 2.0 * c[2] * xt * yt + c[3] * (r2 + 2.0 * xt * xt);
+                let dy = yf + 2.0 * c[3] * xt * yt + c[2] * (r2 + 2.0 * yt * yt);
+                x = dx;
+                y = dy;
+            }
+            DistortionModel::FTheta => {
+                let r = (x * x + y * y).sqrt().max(f32::EPSILON);
+                let rd = 1.0 / c[0] * (2.0 * r * (c[0] / 2.0).tan()).atan();
+                x *= rd / r;
+                y *= rd / r;
+            }
+            DistortionModel::KannalaBrandt4 => {
+                let r = (x * x + y * y).sqrt().max(f32::EPSILON);
+                let theta = r.atan();
+                let theta2 = theta * theta;
+                let series =
+                    1.0 + theta2 * (c[0] + theta2 * (c[1] + theta2 * (c[2] + theta2 * c[3])));
+                let rd = theta * series;
+                x *= rd / r;
+                y *= rd / r;
+            }
+            DistortionModel::None => (),
+        }
+        Vec2::new(x * self.fx + self.ppx, y * self.fy + self.ppy)
+    }
+}
+
+/// Scale and bias of the IMU axes
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct MotionIntrinsics {
+    pub data: [[f32; 4]; 3],
+    pub noise_variances: [f32; 3],
+    pub bias_variances: [f32; 3],
+}
+
+impl From<&Rs2MotionDeviceIntrinsics> for MotionIntrinsics {
+    fn from(intrinsics: &Rs2MotionDeviceIntrinsics) -> Self {
+        Self {
+            data: intrinsics.data(),
+            noise_variances: intrinsics.noise_variances(),
+            bias_variances: intrinsics.bias_variances(),
+        }
+    }
+}
+
+/// Rigid transform from a stream to another one
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Extrinsics {
+    /// Column-major 3x3 rotation
+    pub rotation: [f32; 9],
+    /// In meters
+    pub translation: [f32; 3],
+}
+
+impl Extrinsics {
+    pub const IDENTITY: Self = Self {
+        rotation: [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        translation: [0.0; 3],
+    };
+
+    /// Moves a point from the frame of a stream to the frame of the other one
+    pub fn transform(&self, point: Vec3) -> Vec3 {
+        Mat3::from_cols_array(&self.rotation) * point + Vec3::from(self.translation)
+    }
+}
+
+impl From<&Rs2Extrinsics> for Extrinsics {
+    fn from(extrinsics: &Rs2Extrinsics) -> Self {
+        Self {
+            rotation: extrinsics.rotation(),
+            translation: extrinsics.translation(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MODELS: [DistortionModel; 6] = [
+        DistortionModel::None,
+        DistortionModel::ModifiedBrownConrady,
+        DistortionModel::InverseBrownConrady,
+        DistortionModel::FTheta,
+        DistortionModel::BrownConrady,
+        DistortionModel::KannalaBrandt4,
+    ];
+
+    fn intrinsics(model: DistortionModel) -> Intrinsics {
+        let coeffs = match model {
+            DistortionModel::None => [0.0; 5],
+            DistortionModel::FTheta => [0.9, 0.0, 0.0, 0.0, 0.0],
+            DistortionModel::KannalaBrandt4 => [-0.01, 0.04, -0.04, 0.007, 0.0],
+            // A D400 color camera
+            _ => [-0.055, 0.065, -0.0006, 0.0008, -0.02],
+        };
+        Intrinsics {
+            width: 640,
+            height: 480,
+            ppx: 321.5,
+            ppy: 242.3,
+            fx: 610.0,
+            fy: 609.0,
+            model,
+            coeffs,
+        }
+    }
+
+    #[test]
+    fn project_deproject_round_trip() {
+        for model in MODELS {
+            let intrinsics = intrinsics(model);
+            for row in (0..480).step_by(40) {
+                for col in (0..640).step_by(40) {
+                    let pixel = Vec2::new(col as f32, row as f32);
+                    let point = intrinsics.deproject(pixel, 2.0);
+                    assert!((point.z - 2.0).abs() < 1e-6);
+                    let projected = intrinsics.project(point);
+                    assert!(
+                        projected.distance(pixel) < 0.01,
+                        "{model:?}: {pixel} projected to {projected}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn distortion_moves_the_corners() {
+        for model in MODELS {
+            let intrinsics = intrinsics(model);
+            let point = Vec3::new(0.5, 0.4, 1.0);
+            let pinhole = Vec2::new(
+                0.5 * intrinsics.fx + intrinsics.ppx,
+                0.4 * intrinsics.fy + intrinsics.ppy,
+            );
+            let projected = intrinsics.project(point);
+            if model == DistortionModel::None {
+                assert!(projected.distance(pinhole) < 1e-3);
+            } else {
+                assert!(projected.distance(pinhole) > 1.0, "{model:?}");
+            }
+        }
+    }
+}
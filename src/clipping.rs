@@ -0,0 +1,237 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Region of the points kept, between two depths and inside a box, to cull
+//! the background and crop exports.
+
+use glam::{EulerRot, Quat, Vec3};
+
+/// Closest depth kept by default, in meters
+pub const DEFAULT_NEAR: f32 = 0.0;
+
+/// Farthest depth kept by default, in meters
+pub const DEFAULT_FAR: f32 = 10.0;
+
+/// Box around `center`, rotated by `angles` in degrees around X, Y and Z.
+/// Without rotation it is aligned with the axes of the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipBox {
+    pub center: Vec3,
+    /// Edges along the axes of the box, in meters
+    pub size: Vec3,
+    pub angles: Vec3,
+}
+
+impl Default for ClipBox {
+    fn default() -> Self {
+        Self {
+            center: Vec3::new(0.0, 0.0, 1.0),
+            size: Vec3::ONE,
+            angles: Vec3::ZERO,
+        }
+    }
+}
+
+impl ClipBox {
+    /// Axis aligned box around the points, unless there are none
+    pub fn around(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let (min, max) = points.fold((first, first), |(min, max), point| {
+            (min.min(point), max.max(point))
+        });
+        Some(Self {
+            center: (min + max) / 2.0,
+            size: max - min,
+            angles: Vec3::ZERO,
+        })
+    }
+
+    pub fn rotation(&self) -> Quat {
+        let angles = self.angles * std::f32::consts::PI / 180.0;
+        Quat::from_euler(EulerRot::XYZ, angles.x, angles.y, angles.z)
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        let local = self.rotation().inverse() * (point - self.center);
+        local.abs().cmple(self.size / 2.0).all()
+    }
+
+    /// Corners, the bit `i` of the index telling the side along the axis `i`
+    pub fn corners(&self) -> [Vec3; 8] {
+        let rotation = self.rotation();
+        std::array::from_fn(|index| {
+            let side = Vec3::new(
+                (index & 1) as f32 - 0.5,
+                ((index >> 1) & 1) as f32 - 0.5,
+                ((index >> 2) & 1) as f32 - 0.5,
+            );
+            self.center + rotation * (side * self.size)
+        })
+    }
+
+    /// Pairs of corners joined by the 12 edges
+    pub fn edges(&self) -> [(Vec3, Vec3); 12] {
+        let corners = self.corners();
+        let mut edges = [(Vec3::ZERO, Vec3::ZERO); 12];
+        let mut count = 0;
+        for (index, corner) in corners.iter().enumerate() {
+            for axis in 0..3 {
+                if index & (1 << axis) == 0 {
+                    edges[count] = (*corner, corners[index | (1 << axis)]);
+                    count += 1;
+                }
+            }
+        }
+        edges
+    }
+}
+
+/// Points kept between the near and far depths, along the optical axis, and
+/// inside the box when there is one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clipping {
+    /// In meters
+    pub near: f32,
+    /// In meters
+    pub far: f32,
+    pub clip_box: Option<ClipBox>,
+}
+
+impl Default for Clipping {
+    fn default() -> Self {
+        Self {
+            near: DEFAULT_NEAR,
+            far: DEFAULT_FAR,
+            clip_box: None,
+        }
+    }
+}
+
+impl Clipping {
+    /// A point in the frame of the camera, Z forward
+    pub fn contains(&self, point: Vec3) -> bool {
+        (self.near..=self.far).contains(&point.z)
+            && self
+                .clip_box
+                .as_ref()
+                .is_none_or(|clip_box| clip_box.contains(point))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-5), "{a} != {b}");
+    }
+
+    #[test]
+    fn rotated_box() {
+        // Long along X, then turned to lie along Z
+        let clip_box = ClipBox {
+            center: Vec3::new(0.0, 0.0, 2.0),
+            size: Vec3::new(2.0, 0.2, 0.2),
+            angles: Vec3::new(0.0, 90.0, 0.0),
+        };
+        assert!(clip_box.contains(Vec3::new(0.0, 0.0, 2.9)));
+        assert!(clip_box.contains(Vec3::new(0.05, -0.05, 1.1)));
+        assert!(!clip_box.contains(Vec3::new(0.5, 0.0, 2.0)));
+        assert!(!clip_box.contains(Vec3::new(0.0, 0.0, 3.1)));
+        assert!(!clip_box.contains(Vec3::new(0.0, 0.2, 2.0)));
+
+        let unrotated = ClipBox {
+            angles: Vec3::ZERO,
+            ..clip_box
+        };
+        assert!(unrotated.contains(Vec3::new(0.9, 0.0, 2.0)));
+        assert!(!unrotated.contains(Vec3::new(0.0, 0.0, 2.9)));
+    }
+
+    #[test]
+    fn corners_and_edges() {
+        let clip_box = ClipBox {
+            center: Vec3::new(1.0, 2.0, 3.0),
+            size: Vec3::new(2.0, 4.0, 6.0),
+            angles: Vec3::new(30.0, -20.0, 45.0),
+        };
+        let corners = clip_box.corners();
+        let rotation = clip_box.rotation();
+        let to_local = |corner: Vec3| rotation.inverse() * (corner - clip_box.center);
+        assert_close(to_local(corners[0]), Vec3::new(-1.0, -2.0, -3.0));
+        assert_close(to_local(corners[0b011]), Vec3::new(1.0, 2.0, -3.0));
+        assert_close(to_local(corners[0b111]), Vec3::new(1.0, 2.0, 3.0));
+        let sum: Vec3 = corners.iter().sum();
+        assert_close(sum / 8.0, clip_box.center);
+        for corner in corners {
+            // Borders are inside, give or take the rounding
+            assert!(clip_box.contains(clip_box.center.lerp(corner, 0.999)));
+            assert!(!clip_box.contains(clip_box.center.lerp(corner, 1.001)));
+        }
+
+        // 4 edges along every axis, each as long as the box along it
+        let edges = clip_box.edges();
+        let mut lengths: Vec<f32> = edges.iter().map(|(a, b)| a.distance(*b)).collect();
+        lengths.sort_by(f32::total_cmp);
+        for (index, length) in lengths.iter().enumerate() {
+            let expected = clip_box.size[index / 4];
+            assert!((length - expected).abs() < 1e-5, "{length} != {expected}");
+        }
+        for (a, b) in edges {
+            assert!(corners.contains(&a) && corners.contains(&b));
+        }
+    }
+
+    #[test]
+    fn box_around_points() {
+        assert_eq!(ClipBox::around([]), None);
+        let points = [
+            Vec3::new(1.0, -1.0, 0.5),
+            Vec3::new(-1.0, 2.0, 1.5),
+            Vec3::new(0.0, 0.0, 3.0),
+        ];
+        let clip_box = ClipBox::around(points).unwrap();
+        assert_close(clip_box.center, Vec3::new(0.0, 0.5, 1.75));
+        assert_close(clip_box.size, Vec3::new(2.0, 3.0, 2.5));
+        assert_eq!(clip_box.angles, Vec3::ZERO);
+        assert!(points.iter().all(|point| clip_box.contains(*point)));
+    }
+
+    #[test]
+    fn clipping_between_depths_and_in_the_box() {
+        let mut clipping = Clipping {
+            near: 0.5,
+            far: 2.0,
+            clip_box: None,
+        };
+        assert!(clipping.contains(Vec3::new(5.0, 5.0, 0.5)));
+        assert!(clipping.contains(Vec3::new(0.0, 0.0, 2.0)));
+        assert!(!clipping.contains(Vec3::new(0.0, 0.0, 0.4)));
+        assert!(!clipping.contains(Vec3::new(0.0, 0.0, 2.1)));
+
+        clipping.clip_box = Some(ClipBox::default());
+        assert!(clipping.contains(Vec3::new(0.0, 0.0, 1.0)));
+        assert!(!clipping.contains(Vec3::new(5.0, 5.0, 1.0)));
+        // Inside the box, beyond the far depth
+        clipping.far = 1.2;
+        assert!(!clipping.contains(Vec3::new(0.0, 0.0, 1.4)));
+
+        assert!(Clipping::default().contains(Vec3::new(0.0, 0.0, DEFAULT_FAR)));
+    }
+}
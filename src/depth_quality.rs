@@ -0,0 +1,441 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Accuracy of the depth of a flat target, as measured by Intel's Depth
+//! Quality Tool, and its history to export as CSV.
+
+use crate::frame_snapshot::{FrameSnapshot, SnapshotData};
+use crate::planes::fit_plane;
+use anyhow::{anyhow, bail, Result};
+use glam::{Vec2, Vec3};
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::Path;
+
+/// Share of the image width and height taken by the default ROI
+pub const DEFAULT_ROI_SHARE: f32 = 0.4;
+
+/// Number of the latest measurements drawn in the sparklines. The history
+/// keeps them all, for the CSV.
+pub const HISTORY_SIZE: usize = 300;
+
+/// Rectangle of pixels, the region of interest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Roi {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Roi {
+    /// In the middle of an image, taking a share of its width and height
+    pub fn centered(width: usize, height: usize, share: f32) -> Self {
+        let (roi_width, roi_height) = (
+            (width as f32 * share) as usize,
+            (height as f32 * share) as usize,
+        );
+        Self {
+            x: (width - roi_width) / 2,
+            y: (height - roi_height) / 2,
+            width: roi_width,
+            height: roi_height,
+        }
+    }
+
+    /// Between two opposite corners, in any order, both included
+    pub fn from_corners(a: (usize, usize), b: (usize, usize)) -> Self {
+        Self {
+            x: a.0.min(b.0),
+            y: a.1.min(b.1),
+            width: a.0.abs_diff(b.0) + 1,
+            height: a.1.abs_diff(b.1) + 1,
+        }
+    }
+
+    /// Same region, cropped to the image
+    fn clamped(&self, width: usize, height: usize) -> Self {
+        let (x, y) = (self.x.min(width), self.y.min(height));
+        Self {
+            x,
+            y,
+            width: self.width.min(width - x),
+            height: self.height.min(height - y),
+        }
+    }
+}
+
+/// Metrics of the depth of a flat target, in a region of interest
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthQuality {
+    /// Of the depth frame, in milliseconds
+    pub timestamp: f64,
+    /// Share of the pixels with depth, in percent
+    pub fill_rate: f32,
+    /// Distance to the target along the optical axis, in meters
+    pub distance: f32,
+    /// Angle between the normal of the target and the optical axis, in
+    /// degrees
+    pub angle: f32,
+    /// RMS distance of the points to the plane fit, in meters
+    pub plane_fit_rms: f32,
+    /// RMS difference between the measured disparities and the ones of the
+    /// plane fit, in pixels. Needs the stereo baseline.
+    pub subpixel_rms: Option<f32>,
+    /// Median depth error to the ground truth, in percent of it
+    pub z_accuracy: Option<f32>,
+}
+
+impl DepthQuality {
+    /// Fits a plane to the depth of the ROI. The baseline and the ground
+    /// truth distance along the optical axis are in meters.
+    pub fn measure(
+        depth: &FrameSnapshot,
+        roi: &Roi,
+        baseline: Option<f32>,
+        ground_truth: Option<f32>,
+    ) -> Result<Self> {
+        let SnapshotData::Depth {
+            width,
+            height,
+            depth_units,
+            data,
+        } = &depth.data
+        else {
+            bail!("{} is not a depth frame", depth.stream);
+        };
+        let intrinsics = depth
+            .intrinsics
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing intrinsics of {}", depth.stream))?;
+        let roi = roi.clamped(*width, *height);
+        let total = roi.width * roi.height;
+        if total == 0 {
+            bail!("The ROI is empty");
+        }
+
+        let mut points = Vec::with_capacity(total);
+        for row in roi.y..roi.y + roi.height {
+            for col in roi.x..roi.x + roi.width {
+                let value = data[row * width + col];
+                if value != 0 {
+                    let pixel = Vec2::new(col as f32, row as f32);
+                    points.push(intrinsics.deproject(pixel, value as f32 * depth_units));
+                }
+            }
+        }
+        let plane = fit_plane(points.iter().copied())
+            .ok_or_else(|| anyhow!("Not enough depth in the ROI"))?;
+        if plane.normal.z > -f32::EPSILON {
+            bail!("The target is parallel to the optical axis");
+        }
+        // Depth where the ray of a point meets a plane through the optical
+        // axis at some distance
+        let depth_on_plane = |point: &Vec3, distance: f32| {
+            let ray = *point / point.z;
+            distance * plane.normal.z / plane.normal.dot(ray)
+        };
+        let distance = -plane.distance / plane.normal.z;
+
+        let rms = |errors: &mut dyn Iterator<Item = f32>| {
+            let (sum, count) = errors.fold((0.0, 0), |(sum, count), e| (sum + e * e, count + 1));
+            (sum / count as f32).sqrt()
+        };
+        let plane_fit_rms = rms(&mut points.iter().map(|p| plane.signed_distance(*p)));
+        let subpixel_rms = baseline.map(|baseline| {
+            let focal_baseline = intrinsics.fx * baseline;
+            rms(&mut points.iter().map(|point| {
+                focal_baseline * (1.0 / point.z - 1.0 / depth_on_plane(point, distance))
+            }))
+        });
+        let z_accuracy = ground_truth.map(|ground_truth| {
+            let mut errors: Vec<f32> = points
+                .iter()
+                .map(|point| point.z - depth_on_plane(point, ground_truth))
+                .collect();
+            errors.sort_by(f32::total_cmp);
+            errors[errors.len() / 2] / ground_truth * 100.0
+        });
+
+        Ok(Self {
+            timestamp: depth.timestamp,
+            fill_rate: points.len() as f32 / total as f32 * 100.0,
+            distance,
+            angle: plane.angle_to(Vec3::NEG_Z),
+            plane_fit_rms,
+            subpixel_rms,
+            z_accuracy,
+        })
+    }
+}
+
+/// Measurements since the last reset
+#[derive(Debug, Default)]
+pub struct DepthQualityHistory {
+    pub measurements: VecDeque<DepthQuality>,
+}
+
+impl DepthQualityHistory {
+    pub fn push(&mut self, measurement: DepthQuality) {
+        self.measurements.push_back(measurement);
+    }
+
+    pub fn clear(&mut self) {
+        self.measurements.clear();
+    }
+
+    pub fn latest(&self) -> Option<&DepthQuality> {
+        self.measurements.back()
+    }
+
+    /// Last values of a metric, for the sparklines
+    pub fn recent(&self, metric: impl Fn(&DepthQuality) -> Option<f32>) -> Vec<f32> {
+        let skip = self.measurements.len().saturating_sub(HISTORY_SIZE);
+        self.measurements
+            .iter()
+            .skip(skip)
+            .filter_map(metric)
+            .collect()
+    }
+
+    /// One row per measurement, missing metrics left empty
+    pub fn write_csv(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writeln!(
+            writer,
+            "timestamp_ms,fill_rate_percent,distance_m,angle_deg,plane_fit_rms_mm,\
+             subpixel_rms_px,z_accuracy_percent"
+        )?;
+        let optional = |value: Option<f32>| value.map_or(String::new(), |v| v.to_string());
+        for m in &self.measurements {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{}",
+                m.timestamp,
+                m.fill_rate,
+                m.distance,
+                m.angle,
+                m.plane_fit_rms * 1000.0,
+                optional(m.subpixel_rms),
+                optional(m.z_accuracy),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn save_csv(&self, path: &Path) -> Result<()> {
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_csv(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_model::{DistortionModel, Intrinsics};
+
+    const WIDTH: usize = 160;
+    const HEIGHT: usize = 120;
+    const DEPTH_UNITS: f32 = 0.001;
+
+    /// Depth frame of a wall at `distance` along the optical axis, turned
+    /// `angle` degrees around Y, with a millimeter of noise and a hole every
+    /// `hole_every` pixels
+    fn wall(distance: f32, angle: f32, hole_every: usize) -> FrameSnapshot {
+        let intrinsics = Intrinsics {
+            width: WIDTH,
+            height: HEIGHT,
+            ppx: 79.5,
+            ppy: 59.5,
+            fx: 120.0,
+            fy: 120.0,
+            model: DistortionModel::None,
+            coeffs: [0.0; 5],
+        };
+        let normal = Vec3::new(angle.to_radians().sin(), 0.0, -angle.to_radians().cos());
+        let mut data = Vec::with_capacity(WIDTH * HEIGHT);
+        for row in 0..HEIGHT {
+            for col in 0..WIDTH {
+                if (row * WIDTH + col).is_multiple_of(hole_every) {
+                    data.push(0);
+                    continue;
+                }
+                let ray = intrinsics.deproject(Vec2::new(col as f32, row as f32), 1.0);
+                let noise = ((col * 7 + row * 13) % 3) as f32 - 1.0;
+                let depth = distance * normal.z / normal.dot(ray) / DEPTH_UNITS + noise;
+                data.push(depth.round() as u16);
+            }
+        }
+        FrameSnapshot {
+            stream: "Depth:0".to_string(),
+            frame_number: 0,
+            timestamp: 1000.0,
+            timestamp_domain: "Global Time".to_string(),
+            metadata: Vec::new(),
+            intrinsics: Some(intrinsics),
+            motion_intrinsics: None,
+            extrinsics: Vec::new(),
+            data: SnapshotData::Depth {
+                width: WIDTH,
+                height: HEIGHT,
+                depth_units: DEPTH_UNITS,
+                data,
+            },
+        }
+    }
+
+    fn measurement(timestamp: f64, z_accuracy: Option<f32>) -> DepthQuality {
+        DepthQuality {
+            timestamp,
+            fill_rate: 75.0,
+            distance: 1.5,
+            angle: 10.0,
+            plane_fit_rms: 0.002,
+            subpixel_rms: None,
+            z_accuracy,
+        }
+    }
+
+    #[test]
+    fn measure_a_tilted_wall() {
+        let depth = wall(1.0, 20.0, 4);
+        let roi = Roi::centered(WIDTH, HEIGHT, 0.5);
+        let quality = DepthQuality::measure(&depth, &roi, Some(0.05), Some(1.0)).unwrap();
+        assert_eq!(quality.timestamp, 1000.0);
+        assert!((quality.distance - 1.0).abs() < 0.002, "{quality:?}");
+        assert!((quality.angle - 20.0).abs() < 0.2, "{quality:?}");
+        // A hole every 4 pixels, and the rows of the ROI are a multiple of 4
+        assert_eq!(quality.fill_rate, 75.0);
+        assert!(quality.plane_fit_rms > 0.0 && quality.plane_fit_rms < 0.002);
+        let subpixel_rms = quality.subpixel_rms.unwrap();
+        assert!(subpixel_rms > 0.0 && subpixel_rms < 0.05, "{quality:?}");
+        assert!(quality.z_accuracy.unwrap().abs() < 0.2, "{quality:?}");
+
+        // The wall is 2% closer than the ground truth
+        let quality = DepthQuality::measure(&depth, &roi, None, Some(1.02)).unwrap();
+        let expected = -2.0 / 1.02;
+        let z_accuracy = quality.z_accuracy.unwrap();
+        assert!((z_accuracy - expected).abs() < 0.1, "{z_accuracy}%");
+        assert_eq!(quality.subpixel_rms, None);
+    }
+
+    #[test]
+    fn measure_fails_without_depth() {
+        let roi = Roi::centered(WIDTH, HEIGHT, 0.5);
+        let empty = wall(1.0, 0.0, 1);
+        assert!(DepthQuality::measure(&empty, &roi, None, None).is_err());
+
+        let depth = wall(1.0, 0.0, 4);
+        let outside = Roi {
+            x: WIDTH,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        assert!(DepthQuality::measure(&depth, &outside, None, None).is_err());
+
+        let mut no_intrinsics = depth;
+        no_intrinsics.intrinsics = None;
+        assert!(DepthQuality::measure(&no_intrinsics, &roi, None, None).is_err());
+    }
+
+    #[test]
+    fn rois() {
+        let roi = Roi::centered(640, 480, DEFAULT_ROI_SHARE);
+        assert_eq!(
+            roi,
+            Roi {
+                x: 192,
+                y: 144,
+                width: 256,
+                height: 192,
+            }
+        );
+        assert_eq!(Roi::centered(640, 480, 1.0).width, 640);
+
+        let roi = Roi::from_corners((10, 20), (5, 30));
+        assert_eq!(roi, Roi::from_corners((5, 30), (10, 20)));
+        assert_eq!(
+            roi,
+            Roi {
+                x: 5,
+                y: 20,
+                width: 6,
+                height: 11,
+            }
+        );
+        assert_eq!(Roi::from_corners((3, 3), (3, 3)).width, 1);
+
+        let roi = Roi {
+            x: 600,
+            y: 400,
+            width: 100,
+            height: 50,
+        };
+        let clamped = roi.clamped(640, 480);
+        assert_eq!((clamped.x, clamped.y), (600, 400));
+        assert_eq!((clamped.width, clamped.height), (40, 50));
+        let clamped = roi.clamped(320, 240);
+        assert_eq!((clamped.x, clamped.y), (320, 240));
+        assert_eq!(clamped.width * clamped.height, 0);
+    }
+
+    #[test]
+    fn csv() {
+        let mut history = DepthQualityHistory::default();
+        history.push(measurement(1000.0, Some(-0.5)));
+        history.push(DepthQuality {
+            subpixel_rms: Some(0.1),
+            ..measurement(1033.5, None)
+        });
+        let mut csv = Vec::new();
+        history.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "timestamp_ms,fill_rate_percent,distance_m,angle_deg,plane_fit_rms_mm,\
+                 subpixel_rms_px,z_accuracy_percent",
+                "1000,75,1.5,10,2,,-0.5",
+                "1033.5,75,1.5,10,2,0.1,",
+            ]
+        );
+    }
+
+    #[test]
+    fn history_keeps_every_measurement() {
+        let mut history = DepthQualityHistory::default();
+        for i in 0..HISTORY_SIZE + 10 {
+            history.push(measurement(i as f64, None));
+        }
+        assert_eq!(history.measurements.len(), HISTORY_SIZE + 10);
+        let recent = history.recent(|m| Some(m.timestamp as f32));
+        assert_eq!(recent.len(), HISTORY_SIZE);
+        assert_eq!(recent[0], 10.0);
+        assert!(history.recent(|m| m.z_accuracy).is_empty());
+        assert_eq!(
+            history.latest().unwrap().timestamp,
+            (HISTORY_SIZE + 9) as f64
+        );
+
+        history.clear();
+        assert!(history.latest().is_none());
+    }
+}
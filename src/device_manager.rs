@@ -0,0 +1,179 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Device hotplug, driven by the librealsense devices changed callback.
+
+use crate::advanced_mode::check_error;
+use anyhow::Result;
+use realsense_rust::context::Context;
+use realsense_rust::device::Device;
+use realsense_rust::kind::Rs2CameraInfo;
+use std::collections::{HashSet, VecDeque};
+use std::os::raw::c_void;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+
+/// Number of events kept in the log
+const LOG_SIZE: usize = 100;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceEventKind {
+    Connected,
+    Disconnected,
+    Reconnected,
+}
+
+impl std::fmt::Display for DeviceEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            Self::Connected => "Connected",
+            Self::Disconnected => "Disconnected",
+            Self::Reconnected => "Reconnected",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DeviceEvent {
+    /// Seconds since the manager was created
+    pub time: f64,
+    pub kind: DeviceEventKind,
+    pub serial_number: String,
+    pub name: String,
+}
+
+/// Keeps the list of connected devices up to date without querying them on
+/// every frame. librealsense notifies the changes from its own thread, so the
+/// callback only raises a flag and the list is queried again on the next poll.
+pub struct DeviceManager {
+    // Dropped first, so the callback never sees a dangling flag
+    context: Context,
+    changed: Box<AtomicBool>,
+    devices: Vec<Device>,
+    events: VecDeque<DeviceEvent>,
+    start: Instant,
+}
+
+impl DeviceManager {
+    pub fn new(context: Context) -> Result<Self> {
+        let changed = Box::new(AtomicBool::new(false));
+        let mut err = std::ptr::null_mut();
+        unsafe {
+            realsense_sys::rs2_set_devices_changed_callback(
+                context.get_raw().as_ptr(),
+                Some(devices_changed),
+                &*changed as *const AtomicBool as *mut c_void,
+                &mut err,
+            );
+            check_error(err)?;
+        }
+        let mut manager = Self {
+            context,
+            changed,
+            devices: Vec::new(),
+            events: VecDeque::new(),
+            start: Instant::now(),
+        };
+        manager.refresh();
+        Ok(manager)
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    /// Cached list of connected devices
+    pub fn devices(&self) -> &[Device] {
+        &self.devices
+    }
+
+    pub fn events(&self) -> &VecDeque<DeviceEvent> {
+        &self.events
+    }
+
+    /// Queries the devices again if they changed and returns the new events
+    pub fn poll(&mut self) -> Vec<DeviceEvent> {
+        if self.changed.swap(false, Ordering::AcqRel) {
+            self.refresh()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Adds an event to the log, e.g. when a device is reconnected
+    pub fn log(&mut self, kind: DeviceEventKind, serial_number: &str, name: &str) -> DeviceEvent {
+        let event = DeviceEvent {
+            time: self.start.elapsed().as_secs_f64(),
+            kind,
+            serial_number: serial_number.to_string(),
+            name: name.to_string(),
+        };
+        self.events.push_back(event.clone());
+        if self.events.len() > LOG_SIZE {
+            self.events.pop_front();
+        }
+        event
+    }
+
+    /// Queries the devices now and returns the new events
+    pub fn refresh(&mut self) -> Vec<DeviceEvent> {
+        let devices = self.context.query_devices(HashSet::new());
+        let old: Vec<(String, String)> = self.devices.iter().map(identity).collect();
+        let new: Vec<(String, String)> = devices.iter().map(identity).collect();
+        self.devices = devices;
+
+        let (added, removed) = diff(&old, &new);
+        let mut events = Vec::new();
+        for (serial_number, name) in removed {
+            events.push(self.log(DeviceEventKind::Disconnected, serial_number, name));
+        }
+        for (serial_number, name) in added {
+            events.push(self.log(DeviceEventKind::Connected, serial_number, name));
+        }
+        events
+    }
+}
+
+/// Devices present only in the new list and only in the old one
+fn diff<'a, T: PartialEq>(old: &'a [T], new: &'a [T]) -> (Vec<&'a T>, Vec<&'a T>) {
+    let added = new.iter().filter(|item| !old.contains(item)).collect();
+    let removed = old.iter().filter(|item| !new.contains(item)).collect();
+    (added, removed)
+}
+
+fn identity(device: &Device) -> (String, String) {
+    let info = |param| {
+        device
+            .info(param)
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| String::from("N/A"))
+    };
+    (info(Rs2CameraInfo::SerialNumber), info(Rs2CameraInfo::Name))
+}
+
+unsafe extern "C" fn devices_changed(
+    removed: *mut realsense_sys::rs2_device_list,
+    added: *mut realsense_sys::rs2_device_list,
+    user: *mut c_void,
+) {
+    // The lists are owned by the callback
+    realsense_sys::rs2_delete_device_list(removed);
+    realsense_sys::rs2_delete_device_list(added);
+    (*(user as *const AtomicBool)).store(true, Ordering::Release);
+}
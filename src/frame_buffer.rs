@@ -0,0 +1,261 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Ring buffer of the most recent frames, to step back in time.
+
+use crate::frame_snapshot::CompositeSnapshot;
+use anyhow::Result;
+use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::Arc;
+
+pub const DEFAULT_DURATION: f64 = 10.0;
+
+/// Memory taken by the frames of a buffer by default, in bytes
+pub const DEFAULT_MAX_BYTES: usize = 1 << 30;
+
+/// Keeps the snapshots received in the last seconds, as long as they fit in
+/// the memory budget. Snapshots are shared, so the panels can hold them while
+/// the buffer moves on.
+#[derive(Debug)]
+pub struct FrameBuffer {
+    /// Length of the window, in seconds
+    duration: f64,
+    max_bytes: usize,
+    /// Size of the snapshots kept
+    bytes: usize,
+    snapshots: VecDeque<Arc<CompositeSnapshot>>,
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new(DEFAULT_DURATION)
+    }
+}
+
+impl FrameBuffer {
+    pub fn new(duration: f64) -> Self {
+        Self {
+            duration,
+            max_bytes: DEFAULT_MAX_BYTES,
+            bytes: 0,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.duration
+    }
+
+    pub fn set_duration(&mut self, duration: f64) {
+        self.duration = duration;
+        self.evict();
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    /// The newest snapshot is always kept, even if larger
+    pub fn set_max_bytes(&mut self, max_bytes: usize) {
+        self.max_bytes = max_bytes;
+        self.evict();
+    }
+
+    pub fn push(&mut self, snapshot: CompositeSnapshot) {
+        self.bytes += snapshot.size_bytes();
+        self.snapshots.push_back(Arc::new(snapshot));
+        self.evict();
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.bytes = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn latest(&self) -> Option<Arc<CompositeSnapshot>> {
+        self.snapshots.back().cloned()
+    }
+
+    /// Host times of the oldest and newest snapshots, in milliseconds
+    pub fn range(&self) -> Option<(f64, f64)> {
+        let first = self.snapshots.front()?;
+        let last = self.snapshots.back()?;
+        Some((first.host_time, last.host_time))
+    }
+
+    /// Snapshot shown at a host time: the last one received by then, or the
+    /// oldest one if the time is before the buffer
+    pub fn at(&self, host_time: f64) -> Option<Arc<CompositeSnapshot>> {
+        let index = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.host_time <= host_time);
+        self.snapshots.get(index.saturating_sub(1)).cloned()
+    }
+
+    /// Host time of the snapshot before the one shown at a host time
+    pub fn previous(&self, host_time: f64) -> Option<f64> {
+        let index = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.host_time < host_time);
+        let snapshot = self.snapshots.get(index.checked_sub(1)?)?;
+        Some(snapshot.host_time)
+    }
+
+    /// Host time of the snapshot after the one shown at a host time
+    pub fn next(&self, host_time: f64) -> Option<f64> {
+        let index = self
+            .snapshots
+            .partition_point(|snapshot| snapshot.host_time <= host_time);
+        Some(self.snapshots.get(index)?.host_time)
+    }
+
+    /// Writes the whole window in a directory, with a `frames.json` index,
+    /// and returns the number of snapshots saved
+    pub fn save(&self, dir: &Path) -> Result<usize> {
+        std::fs::create_dir_all(dir)?;
+        let mut index = Vec::with_capacity(self.snapshots.len());
+        for (i, snapshot) in self.snapshots.iter().enumerate() {
+            index.push(snapshot.save(dir, &format!("{i:05}"), false)?);
+        }
+        let index = serde_json::to_string_pretty(&index)?;
+        std::fs::write(dir.join("frames.json"), index)?;
+        Ok(self.snapshots.len())
+    }
+
+    fn evict(&mut self) {
+        let newest = match self.snapshots.back() {
+            Some(snapshot) => snapshot.host_time,
+            None => return,
+        };
+        let oldest = newest - self.duration * 1000.0;
+        while let Some(snapshot) = self.snapshots.front() {
+            let over_budget = self.bytes > self.max_bytes && self.snapshots.len() > 1;
+            if snapshot.host_time >= oldest && !over_budget {
+                break;
+            }
+            self.bytes -= snapshot.size_bytes();
+            self.snapshots.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame_snapshot::{FrameSnapshot, SnapshotData};
+
+    /// Snapshot with a color frame of the given size
+    fn snapshot(host_time: f64, bytes: usize) -> CompositeSnapshot {
+        let frame = FrameSnapshot {
+            stream: "Color:0".to_string(),
+            frame_number: 0,
+            timestamp: host_time,
+            timestamp_domain: "System Time".to_string(),
+            metadata: Vec::new(),
+            intrinsics: None,
+            motion_intrinsics: None,
+            extrinsics: Vec::new(),
+            data: SnapshotData::Color {
+                width: bytes / 3,
+                height: 1,
+                data: vec![0; bytes],
+            },
+        };
+        CompositeSnapshot {
+            host_time,
+            frames: vec![frame],
+        }
+    }
+
+    fn buffer(times: &[f64]) -> FrameBuffer {
+        let mut buffer = FrameBuffer::new(DEFAULT_DURATION);
+        for time in times {
+            buffer.push(snapshot(*time, 3));
+        }
+        buffer
+    }
+
+    #[test]
+    fn at() {
+        let buffer = buffer(&[100.0, 200.0, 300.0]);
+        let at = |time| buffer.at(time).map(|snapshot| snapshot.host_time);
+        // Before the buffer, the oldest one
+        assert_eq!(at(50.0), Some(100.0));
+        assert_eq!(at(100.0), Some(100.0));
+        assert_eq!(at(250.0), Some(200.0));
+        assert_eq!(at(1000.0), Some(300.0));
+        assert!(FrameBuffer::default().at(0.0).is_none());
+    }
+
+    #[test]
+    fn previous_and_next() {
+        let buffer = buffer(&[100.0, 200.0, 300.0]);
+        assert_eq!(buffer.previous(200.0), Some(100.0));
+        assert_eq!(buffer.previous(250.0), Some(200.0));
+        assert_eq!(buffer.previous(100.0), None);
+        assert_eq!(buffer.next(200.0), Some(300.0));
+        assert_eq!(buffer.next(50.0), Some(100.0));
+        assert_eq!(buffer.next(300.0), None);
+    }
+
+    #[test]
+    fn trims_to_duration() {
+        let mut buffer = FrameBuffer::new(1.0);
+        for i in 0..30 {
+            buffer.push(snapshot(i as f64 * 100.0, 3));
+        }
+        // The last second, both ends included
+        assert_eq!(buffer.range(), Some((1900.0, 2900.0)));
+        assert_eq!(buffer.len(), 11);
+        assert_eq!(buffer.size_bytes(), 33);
+        buffer.set_duration(0.5);
+        assert_eq!(buffer.range(), Some((2400.0, 2900.0)));
+        assert_eq!(buffer.size_bytes(), 18);
+    }
+
+    #[test]
+    fn trims_to_memory_budget() {
+        let mut buffer = FrameBuffer::new(DEFAULT_DURATION);
+        buffer.set_max_bytes(1000);
+        for i in 0..10 {
+            buffer.push(snapshot(i as f64, 300));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.size_bytes(), 900);
+        assert_eq!(buffer.range(), Some((7.0, 9.0)));
+        // Larger than the budget, still shown
+        buffer.push(snapshot(10.0, 3000));
+        assert_eq!(buffer.len(), 1);
+        assert_eq!(buffer.size_bytes(), 3000);
+        buffer.clear();
+        assert_eq!(buffer.size_bytes(), 0);
+    }
+}
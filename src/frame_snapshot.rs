@@ -0,0 +1,518 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Owned copies of frames, which outlive the ones recycled by librealsense.
+
+use crate::camera_model::{Extrinsics, Intrinsics, MotionIntrinsics};
+use anyhow::Result;
+use realsense_rust::frame::{
+    AccelFrame, ColorFrame, CompositeFrame, DepthFrame, FrameEx, GyroFrame, InfraredFrame,
+    PixelKind,
+};
+use realsense_rust::kind::Rs2FrameMetadata;
+use realsense_rust::stream_profile::StreamProfile;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Metadata copied along with every frame, when supported
+const METADATA: [Rs2FrameMetadata; 11] = [
+    Rs2FrameMetadata::FrameCounter,
+    Rs2FrameMetadata::FrameTimestamp,
+    Rs2FrameMetadata::SensorTimestamp,
+    Rs2FrameMetadata::ActualExposure,
+    Rs2FrameMetadata::GainLevel,
+    Rs2FrameMetadata::AutoExposure,
+    Rs2FrameMetadata::TimeOfArrival,
+    Rs2FrameMetadata::BackendTimestamp,
+    Rs2FrameMetadata::ActualFps,
+    Rs2FrameMetadata::FrameLaserPower,
+    Rs2FrameMetadata::FrameEmitterMode,
+];
+
+/// Raw depth shown as the farthest color of the colormap
+const MAX_DEPTH_VALUE: f32 = 4000.0; // 4m
+
+#[derive(Clone, Debug)]
+pub enum SnapshotData {
+    /// Raw Z16 values, to be multiplied by the depth units to get meters
+    Depth {
+        width: usize,
+        height: usize,
+        depth_units: f32,
+        data: Vec<u16>,
+    },
+    /// RGB8
+    Color {
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+    },
+    /// Y8
+    Infrared {
+        width: usize,
+        height: usize,
+        data: Vec<u8>,
+    },
+    /// Y16
+    Infrared16 {
+        width: usize,
+        height: usize,
+        data: Vec<u16>,
+    },
+    /// Rotational velocity in radians/s
+    Gyro([f32; 3]),
+    /// Acceleration in m/s²
+    Accel([f32; 3]),
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameSnapshot {
+    /// Stream name, e.g. "Infrared:1"
+    pub stream: String,
+    pub frame_number: u64,
+    pub timestamp: f64,
+    pub timestamp_domain: String,
+    pub metadata: Vec<(String, i64)>,
+    pub intrinsics: Option<Intrinsics>,
+    pub motion_intrinsics: Option<MotionIntrinsics>,
+    /// Transforms to the other streams of the same composite frame
+    pub extrinsics: Vec<(String, Extrinsics)>,
+    pub data: SnapshotData,
+}
+
+impl FrameSnapshot {
+    fn new<T: FrameEx>(frame: &T, data: SnapshotData) -> Self {
+        let profile = frame.stream_profile();
+        let metadata = METADATA
+            .iter()
+            .filter(|kind| frame.supports_metadata(**kind))
+            .filter_map(|kind| Some((format!("{kind:?}"), frame.metadata(*kind)?)))
+            .collect();
+        let is_motion = matches!(data, SnapshotData::Gyro(_) | SnapshotData::Accel(_));
+        let (intrinsics, motion_intrinsics) = if is_motion {
+            let intrinsics = profile.motion_intrinsics().ok();
+            (None, intrinsics.as_ref().map(MotionIntrinsics::from))
+        } else {
+            let intrinsics = profile.intrinsics().ok();
+            (intrinsics.as_ref().map(Intrinsics::from), None)
+        };
+        Self {
+            stream: format!("{}:{}", profile.kind(), profile.index()),
+            frame_number: frame.frame_number(),
+            timestamp: frame.timestamp(),
+            timestamp_domain: frame.timestamp_domain().as_str().to_string(),
+            metadata,
+            intrinsics,
+            motion_intrinsics,
+            extrinsics: Vec::new(),
+            data,
+        }
+    }
+
+    pub fn from_depth(frame: &DepthFrame) -> Self {
+        let (width, height) = (frame.width(), frame.height());
+        let mut data = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for col in 0..width {
+                match frame.get_unchecked(col, row) {
+                    PixelKind::Z16 { depth } => data.push(*depth),
+                    _ => panic!("Depth type is wrong!"),
+                }
+            }
+        }
+        let depth_units = frame.depth_units().unwrap_or(0.001);
+        let data = SnapshotData::Depth {
+            width,
+            height,
+            depth_units,
+            data,
+        };
+        Self::new(frame, data)
+    }
+
+    pub fn from_color(frame: &ColorFrame) -> Self {
+        let (width, height) = (frame.width(), frame.height());
+        let mut data = Vec::with_capacity(width * height * 3);
+        for row in 0..height {
+            for col in 0..width {
+                match frame.get_unchecked(col, row) {
+                    PixelKind::Bgr8 { b, g, r } | PixelKind::Rgb8 { r, g, b } => {
+                        data.extend_from_slice(&[*r, *g, *b]);
+                    }
+                    _ => panic!("Color type is wrong!"),
+                }
+            }
+        }
+        Self::new(
+            frame,
+            SnapshotData::Color {
+                width,
+                height,
+                data,
+            },
+        )
+    }
+
+    pub fn from_infrared(frame: &InfraredFrame) -> Self {
+        let (width, height) = (frame.width(), frame.height());
+        let mut data = Vec::with_capacity(width * height);
+        let mut data16 = Vec::new();
+        for row in 0..height {
+            for col in 0..width {
+                match frame.get_unchecked(col, row) {
+                    PixelKind::Y8 { y } => data.push(*y),
+                    PixelKind::Y16 { y } => data16.push(*y),
+                    _ => panic!("Color type is wrong!"),
+                }
+            }
+        }
+        let data = if data16.is_empty() {
+            SnapshotData::Infrared {
+                width,
+                height,
+                data,
+            }
+        } else {
+            SnapshotData::Infrared16 {
+                width,
+                height,
+                data: data16,
+            }
+        };
+        Self::new(frame, data)
+    }
+
+    pub fn from_gyro(frame: &GyroFrame) -> Self {
+        Self::new(frame, SnapshotData::Gyro(*frame.rotational_velocity()))
+    }
+
+    pub fn from_accel(frame: &AccelFrame) -> Self {
+        Self::new(frame, SnapshotData::Accel(*frame.acceleration()))
+    }
+
+    /// Width and height of images
+    pub fn image_size(&self) -> Option<(usize, usize)> {
+        match &self.data {
+            SnapshotData::Depth { width, height, .. }
+            | SnapshotData::Color { width, height, .. }
+            | SnapshotData::Infrared { width, height, .. }
+            | SnapshotData::Infrared16 { width, height, .. } => Some((*width, *height)),
+            SnapshotData::Gyro(_) | SnapshotData::Accel(_) => None,
+        }
+    }
+
+    /// Approximate memory used by the pixels
+    pub fn size_bytes(&self) -> usize {
+        match &self.data {
+            SnapshotData::Depth { data, .. } | SnapshotData::Infrared16 { data, .. } => {
+                data.len() * 2
+            }
+            SnapshotData::Color { data, .. } | SnapshotData::Infrared { data, .. } => data.len(),
+            SnapshotData::Gyro(_) | SnapshotData::Accel(_) => 12,
+        }
+    }
+
+    /// Converts the pixels to show them, depth is color mapped
+    pub fn to_rgb_image(&self) -> Option<image::RgbImage> {
+        let (width, height) = self.image_size()?;
+        let mut img = image::RgbImage::new(width as u32, height as u32);
+        match &self.data {
+            SnapshotData::Depth { data, .. } => {
+                for (pixel, depth) in img.pixels_mut().zip(data) {
+                    *pixel = jet_colormap(*depth as f32 / MAX_DEPTH_VALUE);
+                }
+            }
+            SnapshotData::Color { data, .. } => {
+                img.copy_from_slice(data);
+            }
+            SnapshotData::Infrared { data, .. } => {
+                for (pixel, y) in img.pixels_mut().zip(data) {
+                    *pixel = image::Rgb([*y, *y, *y]);
+                }
+            }
+            SnapshotData::Infrared16 { data, .. } => {
+                for (pixel, y) in img.pixels_mut().zip(data) {
+                    let y = (*y >> 8) as u8;
+                    *pixel = image::Rgb([y, y, y]);
+                }
+            }
+            SnapshotData::Gyro(_) | SnapshotData::Accel(_) => return None,
+        }
+        Some(img)
+    }
+
+    /// Stream name usable in file names, e.g. "infrared-1"
+    pub fn file_stem(&self) -> String {
+        self.stream.to_lowercase().replace(':', "-")
+    }
+
+    /// Writes images as PNG in their native bit depth, and motion samples as
+    /// JSON. Returns the path of the file, with the extension added.
+    pub fn save_file(&self, path: &Path) -> Result<PathBuf> {
+        let png = path.with_extension("png");
+        match &self.data {
+            SnapshotData::Depth {
+                width,
+                height,
+                data,
+                ..
+            }
+            | SnapshotData::Infrared16 {
+                width,
+                height,
+                data,
+            } => {
+                let img = image::ImageBuffer::<image::Luma<u16>, _>::from_raw(
+                    *width as u32,
+                    *height as u32,
+                    data.clone(),
+                );
+                img.expect("Image buffer size is wrong").save(&png)?;
+            }
+            SnapshotData::Color {
+                width,
+                height,
+                data,
+            } => {
+                let img = image::RgbImage::from_raw(*width as u32, *height as u32, data.clone());
+                img.expect("Image buffer size is wrong").save(&png)?;
+            }
+            SnapshotData::Infrared {
+                width,
+                height,
+                data,
+            } => {
+                let img = image::GrayImage::from_raw(*width as u32, *height as u32, data.clone());
+                img.expect("Image buffer size is wrong").save(&png)?;
+            }
+            SnapshotData::Gyro(_) | SnapshotData::Accel(_) => {
+                let path = path.with_extension("json");
+                let sample = json!({
+                    "stream": self.stream,
+                    "timestamp": self.timestamp,
+                    "values": self.motion_values(),
+                });
+                std::fs::write(&path, serde_json::to_string_pretty(&sample)?)?;
+                return Ok(path);
+            }
+        }
+        Ok(png)
+    }
+
+    fn motion_values(&self) -> Option<[f32; 3]> {
+        match &self.data {
+            SnapshotData::Gyro(values) | SnapshotData::Accel(values) => Some(*values),
+            _ => None,
+        }
+    }
+
+    /// Description of the frame, saved next to the files
+    pub fn sidecar(&self) -> Value {
+        let metadata: serde_json::Map<String, Value> = self
+            .metadata
+            .iter()
+            .map(|(key, value)| (key.clone(), json!(value)))
+            .collect();
+        let extrinsics: serde_json::Map<String, Value> = self
+            .extrinsics
+            .iter()
+            .map(|(stream, extrinsics)| (stream.clone(), json!(extrinsics)))
+            .collect();
+        let mut sidecar = json!({
+            "stream": self.stream,
+            "frame_number": self.frame_number,
+            "timestamp": self.timestamp,
+            "timestamp_domain": self.timestamp_domain,
+            "metadata": metadata,
+            "extrinsics": extrinsics,
+        });
+        if let Some((width, height)) = self.image_size() {
+            sidecar["width"] = json!(width);
+            sidecar["height"] = json!(height);
+        }
+        if let Some(intrinsics) = &self.intrinsics {
+            sidecar["intrinsics"] = json!(intrinsics);
+        }
+        if let Some(intrinsics) = &self.motion_intrinsics {
+            sidecar["motion_intrinsics"] = json!(intrinsics);
+        }
+        match &self.data {
+            SnapshotData::Depth { depth_units, .. } => {
+                sidecar["depth_units"] = json!(depth_units);
+            }
+            SnapshotData::Gyro(values) => {
+                sidecar["rotational_velocity"] = json!(values);
+            }
+            SnapshotData::Accel(values) => {
+                sidecar["acceleration"] = json!(values);
+            }
+            _ => (),
+        }
+        sidecar
+    }
+}
+
+/// Calibration between pairs of stream profiles, by their unique ids. It
+/// only changes with the profiles, so it is queried once per pair.
+#[derive(Debug, Default)]
+pub struct ExtrinsicsCache {
+    extrinsics: HashMap<(i32, i32), Option<Extrinsics>>,
+}
+
+impl ExtrinsicsCache {
+    pub fn get(&mut self, from: &StreamProfile, to: &StreamProfile) -> Option<Extrinsics> {
+        *self
+            .extrinsics
+            .entry((from.unique_id(), to.unique_id()))
+            .or_insert_with(|| from.extrinsics(to).ok().as_ref().map(Extrinsics::from))
+    }
+
+    /// Forgets the profiles, e.g. when the pipeline starts again
+    pub fn clear(&mut self) {
+        self.extrinsics.clear();
+    }
+}
+
+/// All the frames of a device received at once
+#[derive(Clone, Debug, Default)]
+pub struct CompositeSnapshot {
+    /// Reception time in the host, in milliseconds since the epoch
+    pub host_time: f64,
+    pub frames: Vec<FrameSnapshot>,
+}
+
+impl CompositeSnapshot {
+    /// Copies the frames, in the order the viewer shows them
+    pub fn new(frames: &CompositeFrame, host_time: f64, cache: &mut ExtrinsicsCache) -> Self {
+        let depth = frames.frames_of_type::<DepthFrame>();
+        let color = frames.frames_of_type::<ColorFrame>();
+        let infrared = frames.frames_of_type::<InfraredFrame>();
+        let gyro = frames.frames_of_type::<GyroFrame>();
+        let accel = frames.frames_of_type::<AccelFrame>();
+
+        let mut snapshots: Vec<FrameSnapshot> = depth
+            .iter()
+            .map(FrameSnapshot::from_depth)
+            .chain(color.iter().map(FrameSnapshot::from_color))
+            .chain(infrared.iter().map(FrameSnapshot::from_infrared))
+            .chain(gyro.iter().map(FrameSnapshot::from_gyro))
+            .chain(accel.iter().map(FrameSnapshot::from_accel))
+            .collect();
+
+        // Calibration between every pair of streams
+        let profiles: Vec<&StreamProfile> = depth
+            .iter()
+            .map(|frame| frame.stream_profile())
+            .chain(color.iter().map(|frame| frame.stream_profile()))
+            .chain(infrared.iter().map(|frame| frame.stream_profile()))
+            .chain(gyro.iter().map(|frame| frame.stream_profile()))
+            .chain(accel.iter().map(|frame| frame.stream_profile()))
+            .collect();
+        let streams: Vec<String> = snapshots.iter().map(|s| s.stream.clone()).collect();
+        for (from, snapshot) in snapshots.iter_mut().enumerate() {
+            for (to, stream) in streams.iter().enumerate() {
+                if from == to {
+                    continue;
+                }
+                if let Some(extrinsics) = cache.get(profiles[from], profiles[to]) {
+                    snapshot.extrinsics.push((stream.clone(), extrinsics));
+                }
+            }
+        }
+
+        Self {
+            host_time,
+            frames: snapshots,
+        }
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.frames.iter().map(FrameSnapshot::size_bytes).sum()
+    }
+
+    /// Frame of a stream, e.g. "Depth:0"
+    pub fn frame(&self, stream: &str) -> Option<&FrameSnapshot> {
+        self.frames.iter().find(|frame| frame.stream == stream)
+    }
+
+    /// Writes every frame as `<prefix>_<stream>.png`, or `.json` for motion,
+    /// in the directory and returns the sidecar of the whole set. Depth can
+    /// also be written as seen in the viewer.
+    pub fn save(&self, dir: &Path, prefix: &str, colorized: bool) -> Result<Value> {
+        let mut frames = Vec::new();
+        for frame in &self.frames {
+            let path = dir.join(format!("{prefix}_{}", frame.file_stem()));
+            let mut sidecar = frame.sidecar();
+            sidecar["file"] = json!(file_name(&frame.save_file(&path)?));
+            if colorized && matches!(frame.data, SnapshotData::Depth { .. }) {
+                let colorized_path =
+                    dir.join(format!("{prefix}_{}_colorized.png", frame.file_stem()));
+                if let Some(img) = frame.to_rgb_image() {
+                    img.save(&colorized_path)?;
+                    sidecar["colorized_file"] = json!(file_name(&colorized_path));
+                }
+            }
+            frames.push(sidecar);
+        }
+        Ok(json!({
+            "host_time": self.host_time,
+            "frames": frames,
+        }))
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Implement the classic jet color map
+/// Blue -> Cyan -> Yellow -> Red -> Black
+pub fn jet_colormap(value: f32) -> image::Rgb<u8> {
+    let v = value.clamp(0.0, 1.0);
+
+    let (r, g, b) = if v < 0.25 {
+        lerp_color(v, 0.00, (0, 0, 255), 0.25, (0, 255, 255)) // Blue → Cyan
+    } else if v < 0.5 {
+        lerp_color(v, 0.25, (0, 255, 255), 0.5, (255, 255, 0)) // Cyan → Yellow
+    } else if v < 0.75 {
+        lerp_color(v, 0.5, (255, 255, 0), 0.75, (255, 0, 0)) // Green → Yellow
+    } else {
+        lerp_color(v, 0.8, (255, 0, 0), 1.00, (0, 0, 0)) // Dark Red → Black
+    };
+
+    image::Rgb([r, g, b])
+}
+
+/// Linearly interpolates between two colors based on value position.
+fn lerp_color(
+    value: f32,
+    v_min: f32,
+    c_min: (u8, u8, u8),
+    v_max: f32,
+    c_max: (u8, u8, u8),
+) -> (u8, u8, u8) {
+    let t = ((value - v_min) / (v_max - v_min)).clamp(0.0, 1.0);
+    (
+        (c_min.0 as f32 + t * (c_max.0 as f32 - c_min.0 as f32)) as u8,
+        (c_min.1 as f32 + t * (c_max.1 as f32 - c_min.1 as f32)) as u8,
+        (c_min.2 as f32 + t * (c_max.2 as f32 - c_min.2 as f32)) as u8,
+    )
+}
@@ -0,0 +1,129 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Direction of gravity estimated from the accelerometer, to level the scene,
+//! and the height of the floor once level.
+
+use glam::Vec3;
+
+/// Weight of every new sample in the estimate, smoothing out the vibrations
+/// and the motion of the camera
+const SMOOTHING: f32 = 0.05;
+
+/// Height of the levels of the floor histogram, in meters
+const FLOOR_BIN: f32 = 0.02;
+
+/// Share of the points on a level to take it as the floor. Vertical surfaces
+/// spread over many levels, horizontal ones gather in a few.
+const MIN_FLOOR_SHARE: f32 = 0.05;
+
+/// Up direction, in the frame of the depth camera, averaged from the
+/// accelerometer. At rest it measures the reaction to gravity, pointing up.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GravityEstimator {
+    up: Option<Vec3>,
+}
+
+impl GravityEstimator {
+    /// Adds an acceleration, in m/s², already rotated to the depth camera
+    pub fn update(&mut self, acceleration: Vec3) {
+        let Some(sample) = acceleration.try_normalize() else {
+            return;
+        };
+        self.up = Some(match self.up {
+            Some(up) => up.lerp(sample, SMOOTHING).normalize(),
+            None => sample,
+        });
+    }
+
+    pub fn reset(&mut self) {
+        self.up = None;
+    }
+
+    /// Unit vector pointing up, once there has been a sample
+    pub fn up(&self) -> Option<Vec3> {
+        self.up
+    }
+}
+
+/// Lowest level with a large share of the points, measuring heights up from
+/// anywhere, or `None` if there are no points
+pub fn floor_height(heights: impl IntoIterator<Item = f32>) -> Option<f32> {
+    let heights: Vec<f32> = heights.into_iter().filter(|h| h.is_finite()).collect();
+    let lowest = heights.iter().copied().reduce(f32::min)?;
+    let highest = heights.iter().copied().reduce(f32::max)?;
+    let mut bins = vec![0usize; ((highest - lowest) / FLOOR_BIN) as usize + 1];
+    for height in &heights {
+        bins[((height - lowest) / FLOOR_BIN) as usize] += 1;
+    }
+    let min_count = (heights.len() as f32 * MIN_FLOOR_SHARE).ceil() as usize;
+    let bin = bins.iter().position(|count| *count >= min_count)?;
+    Some(lowest + (bin as f32 + 0.5) * FLOOR_BIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Quat;
+
+    #[test]
+    fn up_converges_to_the_tilt() {
+        // The camera pitched down 30°, at rest, with Y down
+        let tilt = Quat::from_rotation_x(30f32.to_radians());
+        let expected = tilt * -Vec3::Y;
+        let mut gravity = GravityEstimator::default();
+        assert_eq!(gravity.up(), None);
+        // Started off level, and shaken while it settles
+        gravity.update(Vec3::new(0.0, -9.81, 0.0));
+        for i in 0..200 {
+            let shake = Vec3::new(0.0, 0.0, if i % 2 == 0 { 0.5 } else { -0.5 });
+            gravity.update(expected * 9.81 + shake);
+        }
+        let up = gravity.up().unwrap();
+        assert!((up.length() - 1.0).abs() < 1e-5);
+        assert!(up.angle_between(expected) < 0.02, "{up} != {expected}");
+
+        gravity.reset();
+        assert_eq!(gravity.up(), None);
+    }
+
+    #[test]
+    fn zero_acceleration_is_ignored() {
+        let mut gravity = GravityEstimator::default();
+        gravity.update(Vec3::ZERO);
+        assert_eq!(gravity.up(), None);
+        gravity.update(Vec3::new(0.0, 0.0, 2.0));
+        gravity.update(Vec3::ZERO);
+        assert_eq!(gravity.up(), Some(Vec3::Z));
+    }
+
+    #[test]
+    fn floor_under_the_walls() {
+        let floor = 0.3;
+        // A flat floor, a wall standing on it and a few points below it
+        let floor_points = (0..1000).map(|i| floor + (i % 11) as f32 * 0.001 - 0.005);
+        let wall_points = (0..1000).map(|i| floor + i as f32 * 0.002);
+        let outliers = [-0.5, -0.2, 0.1];
+        let heights = floor_points.chain(wall_points).chain(outliers);
+        let height = floor_height(heights).unwrap();
+        assert!((height - floor).abs() <= FLOOR_BIN, "Floor at {height}");
+
+        assert_eq!(floor_height([]), None);
+        assert_eq!(floor_height([f32::NAN]), None);
+    }
+}
@@ -0,0 +1,274 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Hardware synchronization between several cameras.
+
+use crate::stream_stats::RunningStats;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Number of skew samples kept per device for the plots
+const HISTORY_SIZE: usize = 300;
+
+/// Frames kept per device to pair them by timestamp
+const PAIRING_WINDOW: usize = 30;
+
+/// Values of the inter-camera sync mode option
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum InterCamSyncMode {
+    #[default]
+    Default,
+    Master,
+    Slave,
+    FullSlave,
+    Genlock,
+}
+
+impl InterCamSyncMode {
+    pub const ALL: [Self; 5] = [
+        Self::Default,
+        Self::Master,
+        Self::Slave,
+        Self::FullSlave,
+        Self::Genlock,
+    ];
+
+    /// Value of the option, genlock uses a single frame per trigger
+    pub fn value(&self) -> f32 {
+        match self {
+            Self::Default => 0.0,
+            Self::Master => 1.0,
+            Self::Slave => 2.0,
+            Self::FullSlave => 3.0,
+            Self::Genlock => 4.0,
+        }
+    }
+
+    pub fn from_value(value: f32) -> Self {
+        match value as u32 {
+            0 => Self::Default,
+            1 => Self::Master,
+            2 => Self::Slave,
+            3 => Self::FullSlave,
+            _ => Self::Genlock,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Default => "default",
+            Self::Master => "master",
+            Self::Slave => "slave",
+            Self::FullSlave => "full-slave",
+            Self::Genlock => "genlock",
+        }
+    }
+}
+
+impl std::str::FromStr for InterCamSyncMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.as_str() == s)
+            .ok_or_else(|| format!("Unknown sync mode '{s}'"))
+    }
+}
+
+/// Skew of a device against the reference device
+#[derive(Clone, Debug, Default)]
+pub struct DeviceSkew {
+    /// Timestamp difference in milliseconds
+    pub skew: RunningStats,
+    pub history: VecDeque<f64>,
+    /// Frame counter difference, constant while the devices are locked
+    pub counter_offset: Option<i64>,
+    /// Number of times the counter difference changed
+    pub counter_jumps: u64,
+    /// Timestamp of the last reference frame paired
+    last_paired: Option<f64>,
+}
+
+impl DeviceSkew {
+    fn push(&mut self, skew: f64, counter_offset: i64) {
+        self.skew.push(skew);
+        self.history.push_back(skew);
+        if self.history.len() > HISTORY_SIZE {
+            self.history.pop_front();
+        }
+        if self
+            .counter_offset
+            .is_some_and(|offset| offset != counter_offset)
+        {
+            self.counter_jumps += 1;
+        }
+        self.counter_offset = Some(counter_offset);
+    }
+}
+
+/// Compares frame timestamps and counters of several devices against a
+/// reference one, usually the master. Every frame of the reference is paired
+/// with the frame of each device closest in time, as the latest frames of the
+/// devices aren't always from the same capture.
+#[derive(Debug, Default)]
+pub struct SyncVerifier {
+    pub devices: BTreeMap<String, DeviceSkew>,
+    /// Recent (frame counter, timestamp in ms) of every device
+    recent: BTreeMap<String, VecDeque<(u64, f64)>>,
+}
+
+impl SyncVerifier {
+    /// Records the latest (frame counter, timestamp in ms) of each device,
+    /// keyed by serial number. The reference frames are measured once the
+    /// device has a later frame, or when they are about to be forgotten.
+    pub fn record(&mut self, reference: &str, latest: &BTreeMap<String, (u64, f64)>) {
+        for (serial_number, frame) in latest {
+            let frames = self.recent.entry(serial_number.clone()).or_default();
+            if frames.back().is_some_and(|last| last == frame) {
+                continue;
+            }
+            frames.push_back(*frame);
+            if frames.len() > PAIRING_WINDOW {
+                frames.pop_front();
+            }
+        }
+        let Some(reference_frames) = self.recent.get(reference) else {
+            return;
+        };
+        for (serial_number, frames) in &self.recent {
+            let Some(&(_, newest)) = frames.back() else {
+                continue;
+            };
+            if serial_number == reference {
+                continue;
+            }
+            let device = self.devices.entry(serial_number.clone()).or_default();
+            for (index, &(reference_counter, reference_timestamp)) in
+                reference_frames.iter().enumerate()
+            {
+                let paired = device
+                    .last_paired
+                    .is_some_and(|last| reference_timestamp <= last);
+                let leaving = index == 0 && reference_frames.len() == PAIRING_WINDOW;
+                if paired || (reference_timestamp > newest && !leaving) {
+                    continue;
+                }
+                let distance = |timestamp: f64| (timestamp - reference_timestamp).abs();
+                let Some(&(counter, timestamp)) = frames
+                    .iter()
+                    .min_by(|a, b| distance(a.1).total_cmp(&distance(b.1)))
+                else {
+                    continue;
+                };
+                let counter_offset = counter as i64 - reference_counter as i64;
+                device.push(timestamp - reference_timestamp, counter_offset);
+                device.last_paired = Some(reference_timestamp);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.devices.clear();
+        self.recent.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latest(frames: &[(&str, u64, f64)]) -> BTreeMap<String, (u64, f64)> {
+        frames
+            .iter()
+            .map(|(serial_number, counter, timestamp)| {
+                (serial_number.to_string(), (*counter, *timestamp))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pairs_frames_of_the_same_capture() {
+        let mut verifier = SyncVerifier::default();
+        // The slave frames arrive one repaint late, 0.1 ms after the master
+        for frame in 0..50u64 {
+            let t = frame as f64 * 33.0;
+            let slave = frame.saturating_sub(1);
+            let frames = latest(&[
+                ("master", frame, t),
+                ("slave", slave + 7, slave as f64 * 33.0 + 0.1),
+            ]);
+            verifier.record("master", &frames);
+        }
+        let device = &verifier.devices["slave"];
+        assert!((device.skew.mean - 0.1).abs() < 1e-9);
+        assert!((device.skew.max - 0.1).abs() < 1e-9);
+        assert_eq!(device.counter_offset, Some(7));
+        assert_eq!(device.counter_jumps, 0);
+        // Every master frame but the last, waiting for its slave frame
+        assert_eq!(device.skew.count, 49);
+    }
+
+    #[test]
+    fn repeated_frames_are_measured_once() {
+        let mut verifier = SyncVerifier::default();
+        let frames = latest(&[("master", 1, 10.0), ("slave", 1, 10.5)]);
+        for _ in 0..5 {
+            verifier.record("master", &frames);
+        }
+        assert_eq!(verifier.devices["slave"].skew.count, 1);
+    }
+
+    #[test]
+    fn counts_counter_jumps() {
+        let mut verifier = SyncVerifier::default();
+        for frame in 0..10u64 {
+            let t = frame as f64 * 33.0;
+            // A frame lost by the slave
+            let counter = if frame < 5 { frame } else { frame + 1 };
+            verifier.record(
+                "master",
+                &latest(&[("master", frame, t), ("slave", counter, t)]),
+            );
+        }
+        let device = &verifier.devices["slave"];
+        assert_eq!(device.counter_offset, Some(1));
+        assert_eq!(device.counter_jumps, 1);
+    }
+
+    #[test]
+    fn unrelated_clocks_are_still_measured() {
+        let mut verifier = SyncVerifier::default();
+        for frame in 0..100u64 {
+            let t = frame as f64 * 33.0;
+            let frames = latest(&[("master", frame, t + 1e6), ("slave", frame, t)]);
+            verifier.record("master", &frames);
+        }
+        let device = &verifier.devices["slave"];
+        assert!(device.skew.count > 0);
+        assert!(device.skew.max < -1e5);
+    }
+
+    #[test]
+    fn sync_mode_names() {
+        for mode in InterCamSyncMode::ALL {
+            assert_eq!(mode.as_str().parse(), Ok(mode));
+            assert_eq!(InterCamSyncMode::from_value(mode.value()), mode);
+        }
+        assert!("nope".parse::<InterCamSyncMode>().is_err());
+    }
+}
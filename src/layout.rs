@@ -0,0 +1,297 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Placement of the stream tiles in the viewer.
+
+use glam::Vec2;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LayoutMode {
+    /// Rows and columns of tiles of the same size
+    #[default]
+    Grid,
+    /// The first tile big, the rest in a column by its side
+    OnePlusN,
+    /// One tile at a time
+    Tabs,
+}
+
+impl LayoutMode {
+    pub const ALL: [Self; 3] = [Self::Grid, Self::OnePlusN, Self::Tabs];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Grid => "Grid",
+            Self::OnePlusN => "1+N",
+            Self::Tabs => "Tabs",
+        }
+    }
+}
+
+/// Area of a tile, relative to the top-left corner of the panel
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub min: Vec2,
+    pub size: Vec2,
+}
+
+/// Layout settings kept between sessions
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Layout {
+    pub mode: LayoutMode,
+    /// Tile ids in the order chosen by the user. Tiles not seen yet go last.
+    pub order: Vec<String>,
+    /// Tile shown in tabs mode
+    pub selected_tab: Option<String>,
+}
+
+impl Layout {
+    /// Sorts the tile ids by the user order, remembering the new ones
+    pub fn sort(&mut self, ids: &mut [String]) {
+        for id in ids.iter() {
+            if !self.order.contains(id) {
+                self.order.push(id.clone());
+            }
+        }
+        ids.sort_by_key(|id| self.order.iter().position(|other| other == id));
+    }
+
+    /// Moves a tile to the position of another one
+    pub fn move_tile(&mut self, from: &str, to: &str) {
+        let from = self.order.iter().position(|id| id == from);
+        let to = self.order.iter().position(|id| id == to);
+        if let (Some(from), Some(to)) = (from, to) {
+            let id = self.order.remove(from);
+            self.order.insert(to, id);
+        }
+    }
+
+    /// Areas of the tiles, in order. In tabs mode only the selected one.
+    pub fn cells(&self, count: usize, area: Vec2, spacing: f32, aspect_ratio: f32) -> Vec<Cell> {
+        match self.mode {
+            LayoutMode::Grid => grid_cells(count, area, spacing, aspect_ratio),
+            LayoutMode::OnePlusN => one_plus_n_cells(count, area, spacing),
+            LayoutMode::Tabs => vec![Cell {
+                min: Vec2::ZERO,
+                size: area,
+            }],
+        }
+    }
+}
+
+/// Number of columns that makes the tiles biggest, keeping their aspect ratio
+pub fn grid_columns(count: usize, area: Vec2, spacing: f32, aspect_ratio: f32) -> usize {
+    (1..=count.max(1))
+        .max_by(|a, b| {
+            let a = fitted_area(*a, count, area, spacing, aspect_ratio);
+            let b = fitted_area(*b, count, area, spacing, aspect_ratio);
+            a.total_cmp(&b)
+        })
+        .unwrap_or(1)
+}
+
+/// Area of an image fitted in a cell of a grid with some columns
+fn fitted_area(columns: usize, count: usize, area: Vec2, spacing: f32, aspect_ratio: f32) -> f32 {
+    let cell = cell_size(columns, count.div_ceil(columns), area, spacing);
+    let width = cell.x.min(cell.y * aspect_ratio);
+    width * width / aspect_ratio
+}
+
+fn cell_size(columns: usize, rows: usize, area: Vec2, spacing: f32) -> Vec2 {
+    let (columns, rows) = (columns.max(1) as f32, rows.max(1) as f32);
+    let width = (area.x - spacing * (columns - 1.0)) / columns;
+    let height = (area.y - spacing * (rows - 1.0)) / rows;
+    Vec2::new(width, height).max(Vec2::ZERO)
+}
+
+pub fn grid_cells(count: usize, area: Vec2, spacing: f32, aspect_ratio: f32) -> Vec<Cell> {
+    let columns = grid_columns(count, area, spacing, aspect_ratio);
+    let size = cell_size(columns, count.div_ceil(columns), area, spacing);
+    (0..count)
+        .map(|index| {
+            let (column, row) = ((index % columns) as f32, (index / columns) as f32);
+            Cell {
+                min: Vec2::new(column, row) * (size + spacing),
+                size,
+            }
+        })
+        .collect()
+}
+
+/// The first tile takes two thirds of the width
+pub fn one_plus_n_cells(count: usize, area: Vec2, spacing: f32) -> Vec<Cell> {
+    if count <= 1 {
+        return grid_cells(count, area, spacing, 1.0);
+    }
+    let main_width = ((area.x - spacing) * 2.0 / 3.0).max(0.0);
+    let mut cells = vec![Cell {
+        min: Vec2::ZERO,
+        size: Vec2::new(main_width, area.y),
+    }];
+    let side = Vec2::new(area.x - main_width - spacing, area.y);
+    let size = cell_size(1, count - 1, side, spacing);
+    for index in 0..count - 1 {
+        cells.push(Cell {
+            min: Vec2::new(main_width + spacing, index as f32 * (size.y + spacing)),
+            size,
+        });
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    /// Checks that no cell leaves the area or overlaps another one
+    fn assert_tiled(cells: &[Cell], area: Vec2) {
+        for (index, cell) in cells.iter().enumerate() {
+            let max = cell.min + cell.size;
+            assert!(
+                cell.min.cmpge(Vec2::ZERO).all() && max.cmple(area + 1e-3).all(),
+                "Cell {index} {cell:?} out of {area}"
+            );
+            for other in &cells[index + 1..] {
+                let other_max = other.min + other.size;
+                let overlap = max.cmpgt(other.min).all() && other_max.cmpgt(cell.min).all();
+                assert!(!overlap, "{cell:?} overlaps {other:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn grid_columns_follow_the_area() {
+        let area = Vec2::new(1000.0, 500.0);
+        assert_eq!(grid_columns(0, area, 0.0, 2.0), 1);
+        assert_eq!(grid_columns(1, area, 0.0, 2.0), 1);
+        // Side by side in a wide area, one above the other in a tall one
+        assert_eq!(grid_columns(2, Vec2::new(1000.0, 200.0), 0.0, 1.0), 2);
+        assert_eq!(grid_columns(2, Vec2::new(200.0, 1000.0), 0.0, 1.0), 1);
+        assert_eq!(grid_columns(4, Vec2::new(1000.0, 1000.0), 0.0, 1.0), 2);
+    }
+
+    #[test]
+    fn grid_cells_are_rows_of_columns() {
+        let area = Vec2::new(1010.0, 1010.0);
+        let cells = grid_cells(3, area, 10.0, 1.0);
+        assert_eq!(cells.len(), 3);
+        let size = Vec2::new(500.0, 500.0);
+        assert_eq!(
+            cells[0],
+            Cell {
+                min: Vec2::ZERO,
+                size
+            }
+        );
+        assert_eq!(
+            cells[1],
+            Cell {
+                min: Vec2::new(510.0, 0.0),
+                size
+            }
+        );
+        assert_eq!(
+            cells[2],
+            Cell {
+                min: Vec2::new(0.0, 510.0),
+                size
+            }
+        );
+        assert_tiled(&cells, area);
+        assert!(grid_cells(0, area, 10.0, 1.0).is_empty());
+    }
+
+    #[test]
+    fn one_plus_n_has_a_big_tile() {
+        let area = Vec2::new(910.0, 610.0);
+        let cells = one_plus_n_cells(3, area, 10.0);
+        assert_eq!(cells.len(), 3);
+        assert_eq!(
+            cells[0],
+            Cell {
+                min: Vec2::ZERO,
+                size: Vec2::new(600.0, 610.0)
+            }
+        );
+        let size = Vec2::new(300.0, 300.0);
+        assert_eq!(
+            cells[1],
+            Cell {
+                min: Vec2::new(610.0, 0.0),
+                size
+            }
+        );
+        assert_eq!(
+            cells[2],
+            Cell {
+                min: Vec2::new(610.0, 310.0),
+                size
+            }
+        );
+        assert_tiled(&cells, area);
+
+        // A single tile takes the whole area
+        let cells = one_plus_n_cells(1, area, 10.0);
+        assert_eq!(
+            cells,
+            vec![Cell {
+                min: Vec2::ZERO,
+                size: area
+            }]
+        );
+    }
+
+    #[test]
+    fn tabs_show_one_tile() {
+        let layout = Layout {
+            mode: LayoutMode::Tabs,
+            ..Default::default()
+        };
+        let area = Vec2::new(640.0, 480.0);
+        assert_eq!(
+            layout.cells(4, area, 10.0, 1.5),
+            vec![Cell {
+                min: Vec2::ZERO,
+                size: area
+            }]
+        );
+    }
+
+    #[test]
+    fn user_order_is_kept() {
+        let mut layout = Layout::default();
+        let mut tiles = ids(&["Depth", "Color"]);
+        layout.sort(&mut tiles);
+        assert_eq!(tiles, ids(&["Depth", "Color"]));
+
+        layout.move_tile("Color", "Depth");
+        let mut tiles = ids(&["Infrared", "Depth", "Color"]);
+        layout.sort(&mut tiles);
+        assert_eq!(tiles, ids(&["Color", "Depth", "Infrared"]));
+        assert_eq!(layout.order, tiles);
+
+        // Unknown tiles are not moved
+        layout.move_tile("Gyro", "Color");
+        assert_eq!(layout.order, tiles);
+    }
+}
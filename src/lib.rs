@@ -0,0 +1,40 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Building blocks shared by the RealSense tools.
+
+pub mod advanced_mode;
+pub mod camera_model;
+pub mod clipping;
+pub mod depth_quality;
+pub mod device_manager;
+pub mod frame_buffer;
+pub mod frame_snapshot;
+pub mod gravity;
+pub mod inter_cam_sync;
+pub mod layout;
+pub mod mesh;
+pub mod odometry;
+pub mod orbit_camera;
+pub mod planes;
+pub mod point_cloud;
+pub mod presets;
+pub mod stream_stats;
+pub mod sync_analysis;
+pub mod tile_view;
+pub mod tsdf;
@@ -0,0 +1,559 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Surface triangulated from the depth grid, and the files to export it.
+
+use crate::frame_snapshot::{CompositeSnapshot, SnapshotData};
+use crate::point_cloud::{PointCloud, TextureSource};
+use anyhow::{anyhow, ensure, Result};
+use glam::Vec3;
+use serde_json::json;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Longest edge kept by default, in meters. Longer ones join surfaces at
+/// different depths.
+pub const DEFAULT_MAX_EDGE: f32 = 0.05;
+
+const MATERIAL_NAME: &str = "scan";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeshFormat {
+    #[default]
+    Obj,
+    Ply,
+    Glb,
+}
+
+impl MeshFormat {
+    pub const ALL: [Self; 3] = [Self::Obj, Self::Ply, Self::Glb];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Obj => "OBJ + MTL",
+            Self::Ply => "PLY",
+            Self::Glb => "glTF (binary)",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Obj => "obj",
+            Self::Ply => "ply",
+            Self::Glb => "glb",
+        }
+    }
+}
+
+/// Triangles in meters, in the frame of the depth camera
+#[derive(Clone, Debug, Default)]
+pub struct Mesh {
+    pub vertices: Vec<Vec3>,
+    /// RGB of every vertex, when textured
+    pub colors: Option<Vec<[u8; 3]>>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl Mesh {
+    /// Joins the neighbor pixels of the depth grid with two triangles per
+    /// square, skipping the ones with an edge longer than `max_edge`
+    pub fn from_composite(
+        snapshot: &CompositeSnapshot,
+        texture: TextureSource,
+        max_edge: f32,
+    ) -> Result<Self> {
+        let cloud = PointCloud::from_composite(snapshot, texture)?;
+        let (width, height, data) = snapshot
+            .frames
+            .iter()
+            .find_map(|frame| match &frame.data {
+                SnapshotData::Depth {
+                    width,
+                    height,
+                    data,
+                    ..
+                } => Some((*width, *height, data)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("There is no depth frame"))?;
+        Ok(Self::from_grid(cloud, width, height, data, max_edge))
+    }
+
+    /// Triangulates a point cloud deprojected from a depth grid, which has
+    /// a point for every valid pixel, in order
+    pub fn from_grid(
+        cloud: PointCloud,
+        width: usize,
+        height: usize,
+        depth: &[u16],
+        max_edge: f32,
+    ) -> Self {
+        let mut next = 0;
+        let vertex_of: Vec<Option<u32>> = depth
+            .iter()
+            .map(|depth| {
+                (*depth != 0).then(|| {
+                    next += 1;
+                    next - 1
+                })
+            })
+            .collect();
+
+        let vertices = cloud.points;
+        let max_edge2 = max_edge * max_edge;
+        let is_short = |a: u32, b: u32| {
+            vertices[a as usize].distance_squared(vertices[b as usize]) <= max_edge2
+        };
+        let mut triangles = Vec::new();
+        for row in 0..height.saturating_sub(1) {
+            for col in 0..width.saturating_sub(1) {
+                let index = row * width + col;
+                let top_left = vertex_of[index];
+                let top_right = vertex_of[index + 1];
+                let bottom_left = vertex_of[index + width];
+                let bottom_right = vertex_of[index + width + 1];
+                // Facing the camera, which looks along +Z with Y down
+                for triangle in [
+                    [top_left, bottom_left, top_right],
+                    [top_right, bottom_left, bottom_right],
+                ] {
+                    if let [Some(a), Some(b), Some(c)] = triangle {
+                        if is_short(a, b) && is_short(b, c) && is_short(c, a) {
+                            triangles.push([a, b, c]);
+                        }
+                    }
+                }
+            }
+        }
+
+        Self {
+            vertices,
+            colors: cloud.colors,
+            triangles,
+        }
+    }
+
+    /// Keeps the vertices passing the test, and the triangles between them
+    pub fn crop(&self, keep: impl Fn(Vec3) -> bool) -> Self {
+        let mut indexes = vec![None; self.vertices.len()];
+        let mut vertices = Vec::new();
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            if keep(*vertex) {
+                indexes[index] = Some(vertices.len() as u32);
+                vertices.push(*vertex);
+            }
+        }
+        let colors = self.colors.as_ref().map(|colors| {
+            colors
+                .iter()
+                .zip(&indexes)
+                .filter(|(_, index)| index.is_some())
+                .map(|(color, _)| *color)
+                .collect()
+        });
+        let triangles = self
+            .triangles
+            .iter()
+            .filter_map(|triangle| {
+                let [a, b, c] = triangle.map(|index| indexes[index as usize]);
+                Some([a?, b?, c?])
+            })
+            .collect();
+        Self {
+            vertices,
+            colors,
+            triangles,
+        }
+    }
+
+    /// Writes the file, adding the extension of the format. OBJ gets its
+    /// material in a `.mtl` file next to it.
+    pub fn save(&self, path: &Path, format: MeshFormat) -> Result<PathBuf> {
+        ensure!(!self.triangles.is_empty(), "The mesh has no triangles");
+        let path = path.with_extension(format.extension());
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        match format {
+            MeshFormat::Obj => {
+                let mtl_path = path.with_extension("mtl");
+                let mtl_name = mtl_path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default();
+                self.write_obj(&mut writer, Some(&mtl_name))?;
+                let mut mtl_writer = std::fs::File::create(&mtl_path)?;
+                self.write_mtl(&mut mtl_writer)?;
+            }
+            MeshFormat::Ply => self.write_ply(&mut writer)?,
+            MeshFormat::Glb => self.write_glb(&mut writer)?,
+        }
+        writer.flush()?;
+        Ok(path)
+    }
+
+    /// Vertex colors follow the positions, as read by Blender and MeshLab
+    pub fn write_obj(&self, writer: &mut impl Write, mtl_file: Option<&str>) -> Result<()> {
+        writeln!(writer, "# Written by realsense-tools-rs")?;
+        if let Some(mtl_file) = mtl_file {
+            writeln!(writer, "mtllib {mtl_file}")?;
+        }
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            write!(writer, "v {} {} {}", vertex.x, vertex.y, vertex.z)?;
+            if let Some(colors) = &self.colors {
+                let [r, g, b] = colors[index].map(|c| c as f32 / 255.0);
+                write!(writer, " {r} {g} {b}")?;
+            }
+            writeln!(writer)?;
+        }
+        if mtl_file.is_some() {
+            writeln!(writer, "usemtl {MATERIAL_NAME}")?;
+        }
+        for [a, b, c] in &self.triangles {
+            // Indices start at 1
+            writeln!(writer, "f {} {} {}", a + 1, b + 1, c + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Plain white material, so the vertex colors show as they are
+    pub fn write_mtl(&self, writer: &mut impl Write) -> Result<()> {
+        writeln!(writer, "# Written by realsense-tools-rs")?;
+        writeln!(writer, "newmtl {MATERIAL_NAME}")?;
+        writeln!(writer, "Ka 0 0 0")?;
+        writeln!(writer, "Kd 1 1 1")?;
+        writeln!(writer, "Ks 0 0 0")?;
+        writeln!(writer, "d 1")?;
+        writeln!(writer, "illum 1")?;
+        Ok(())
+    }
+
+    /// Binary little endian PLY
+    pub fn write_ply(&self, writer: &mut impl Write) -> Result<()> {
+        writeln!(writer, "ply")?;
+        writeln!(writer, "format binary_little_endian 1.0")?;
+        writeln!(writer, "comment Written by realsense-tools-rs")?;
+        writeln!(writer, "element vertex {}", self.vertices.len())?;
+        writeln!(writer, "property float x")?;
+        writeln!(writer, "property float y")?;
+        writeln!(writer, "property float z")?;
+        if self.colors.is_some() {
+            writeln!(writer, "property uchar red")?;
+            writeln!(writer, "property uchar green")?;
+            writeln!(writer, "property uchar blue")?;
+        }
+        writeln!(writer, "element face {}", self.triangles.len())?;
+        writeln!(writer, "property list uchar int vertex_indices")?;
+        writeln!(writer, "end_header")?;
+        for (index, vertex) in self.vertices.iter().enumerate() {
+            for value in vertex.to_array() {
+                writer.write_all(&value.to_le_bytes())?;
+            }
+            if let Some(colors) = &self.colors {
+                writer.write_all(&colors[index])?;
+            }
+        }
+        for triangle in &self.triangles {
+            writer.write_all(&[3])?;
+            for index in triangle {
+                writer.write_all(&(*index as i32).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Binary glTF 2.0 with a single primitive. glTF is Y up and looks along
+    /// -Z, so the vertices are turned half a turn around X. glTF forbids
+    /// empty accessors and buffers, so a mesh without triangles fails.
+    pub fn write_glb(&self, writer: &mut impl Write) -> Result<()> {
+        ensure!(!self.triangles.is_empty(), "The mesh has no triangles");
+        let vertices: Vec<Vec3> = self
+            .vertices
+            .iter()
+            .map(|vertex| Vec3::new(vertex.x, -vertex.y, -vertex.z))
+            .collect();
+        let (min, max) = vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), vertex| (min.min(*vertex), max.max(*vertex)),
+        );
+
+        // Binary buffer: positions, colors and indices, all 4-byte aligned
+        let mut buffer: Vec<u8> = Vec::new();
+        for vertex in &vertices {
+            for value in vertex.to_array() {
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        let positions_length = buffer.len();
+        if let Some(colors) = &self.colors {
+            for [r, g, b] in colors {
+                buffer.extend_from_slice(&[*r, *g, *b, 255]);
+            }
+        }
+        let colors_length = buffer.len() - positions_length;
+        for index in self.triangles.iter().flatten() {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        let indices_length = buffer.len() - positions_length - colors_length;
+
+        let mut buffer_views = vec![json!({
+            "buffer": 0,
+            "byteOffset": 0,
+            "byteLength": positions_length,
+            "target": 34962, // ARRAY_BUFFER
+        })];
+        let mut accessors = vec![json!({
+            "bufferView": 0,
+            "componentType": 5126, // FLOAT
+            "count": vertices.len(),
+            "type": "VEC3",
+            "min": min.to_array(),
+            "max": max.to_array(),
+        })];
+        let mut attributes = json!({ "POSITION": 0 });
+        if self.colors.is_some() {
+            attributes["COLOR_0"] = json!(accessors.len());
+            accessors.push(json!({
+                "bufferView": buffer_views.len(),
+                "componentType": 5121, // UNSIGNED_BYTE
+                "normalized": true,
+                "count": vertices.len(),
+                "type": "VEC4",
+            }));
+            buffer_views.push(json!({
+                "buffer": 0,
+                "byteOffset": positions_length,
+                "byteLength": colors_length,
+                "target": 34962, // ARRAY_BUFFER
+            }));
+        }
+        let indices = accessors.len();
+        accessors.push(json!({
+            "bufferView": buffer_views.len(),
+            "componentType": 5125, // UNSIGNED_INT
+            "count": self.triangles.len() * 3,
+            "type": "SCALAR",
+        }));
+        buffer_views.push(json!({
+            "buffer": 0,
+            "byteOffset": positions_length + colors_length,
+            "byteLength": indices_length,
+            "target": 34963, // ELEMENT_ARRAY_BUFFER
+        }));
+
+        let gltf = json!({
+            "asset": { "version": "2.0", "generator": "realsense-tools-rs" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0, "name": MATERIAL_NAME }],
+            "meshes": [{
+                "primitives": [{
+                    "attributes": attributes,
+                    "indices": indices,
+                    "mode": 4, // TRIANGLES
+                }],
+            }],
+            "accessors": accessors,
+            "bufferViews": buffer_views,
+            "buffers": [{ "byteLength": buffer.len() }],
+        });
+
+        // Chunks are padded to 4 bytes, JSON with spaces
+        let mut json = serde_json::to_vec(&gltf)?;
+        json.resize(json.len().next_multiple_of(4), b' ');
+        buffer.resize(buffer.len().next_multiple_of(4), 0);
+        let length = 12 + 8 + json.len() + 8 + buffer.len();
+
+        writer.write_all(b"glTF")?;
+        writer.write_all(&2u32.to_le_bytes())?;
+        writer.write_all(&(length as u32).to_le_bytes())?;
+        writer.write_all(&(json.len() as u32).to_le_bytes())?;
+        writer.write_all(b"JSON")?;
+        writer.write_all(&json)?;
+        writer.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        writer.write_all(b"BIN\0")?;
+        writer.write_all(&buffer)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two triangles of a square one meter away, optionally colored
+    fn square(colored: bool) -> Mesh {
+        Mesh {
+            vertices: vec![
+                Vec3::new(0.0, 0.0, 1.0),
+                Vec3::new(0.1, 0.0, 1.0),
+                Vec3::new(0.0, 0.1, 1.0),
+                Vec3::new(0.1, 0.1, 1.5),
+            ],
+            colors: colored.then(|| vec![[255, 0, 0], [0, 255, 0], [0, 0, 255], [10, 20, 30]]),
+            triangles: vec![[0, 2, 1], [1, 2, 3]],
+        }
+    }
+
+    fn parse_obj(text: &str) -> Mesh {
+        let mut mesh = Mesh::default();
+        let mut colors = Vec::new();
+        for line in text.lines() {
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("v") => {
+                    let values: Vec<f32> = fields.map(|field| field.parse().unwrap()).collect();
+                    mesh.vertices
+                        .push(Vec3::new(values[0], values[1], values[2]));
+                    if values.len() == 6 {
+                        colors.push([3, 4, 5].map(|i| (values[i] * 255.0).round() as u8));
+                    }
+                }
+                Some("f") => {
+                    let indices: Vec<u32> = fields
+                        .map(|field| field.parse::<u32>().unwrap() - 1)
+                        .collect();
+                    mesh.triangles.push([indices[0], indices[1], indices[2]]);
+                }
+                _ => (),
+            }
+        }
+        mesh.colors = (!colors.is_empty()).then_some(colors);
+        mesh
+    }
+
+    fn parse_ply(bytes: &[u8]) -> Mesh {
+        let end = b"end_header\n";
+        let header_length = bytes
+            .windows(end.len())
+            .position(|window| window == end)
+            .unwrap()
+            + end.len();
+        let header = std::str::from_utf8(&bytes[..header_length]).unwrap();
+        assert!(header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+        let count = |element: &str| -> usize {
+            header
+                .lines()
+                .find_map(|line| line.strip_prefix(element))
+                .unwrap()
+                .trim()
+                .parse()
+                .unwrap()
+        };
+        let colored = header.contains("property uchar red");
+        let mut body = &bytes[header_length..];
+        let mut take = |length: usize| {
+            let (head, tail) = body.split_at(length);
+            body = tail;
+            head
+        };
+        let mut mesh = Mesh::default();
+        let mut colors = Vec::new();
+        for _ in 0..count("element vertex") {
+            let values = [0, 1, 2].map(|_| f32::from_le_bytes(take(4).try_into().unwrap()));
+            mesh.vertices.push(Vec3::from_array(values));
+            if colored {
+                colors.push(take(3).try_into().unwrap());
+            }
+        }
+        for _ in 0..count("element face") {
+            assert_eq!(take(1), [3]);
+            let indices = [0, 1, 2].map(|_| i32::from_le_bytes(take(4).try_into().unwrap()) as u32);
+            mesh.triangles.push(indices);
+        }
+        assert!(body.is_empty());
+        mesh.colors = colored.then_some(colors);
+        mesh
+    }
+
+    fn assert_same(parsed: &Mesh, mesh: &Mesh, format: &str) {
+        assert_eq!(parsed.vertices.len(), mesh.vertices.len(), "{format}");
+        for (a, b) in parsed.vertices.iter().zip(&mesh.vertices) {
+            assert!(a.distance(*b) < 1e-6, "{format}: {a} != {b}");
+        }
+        assert_eq!(parsed.colors, mesh.colors, "{format}");
+        assert_eq!(parsed.triangles, mesh.triangles, "{format}");
+    }
+
+    #[test]
+    fn writers_round_trip() {
+        for colored in [false, true] {
+            let mesh = square(colored);
+
+            let mut obj = Vec::new();
+            mesh.write_obj(&mut obj, Some("square.mtl")).unwrap();
+            let obj = String::from_utf8(obj).unwrap();
+            assert!(obj.contains("mtllib square.mtl\n"));
+            assert!(obj.contains(&format!("usemtl {MATERIAL_NAME}\n")));
+            assert_same(&parse_obj(&obj), &mesh, "OBJ");
+
+            let mut ply = Vec::new();
+            mesh.write_ply(&mut ply).unwrap();
+            assert_same(&parse_ply(&ply), &mesh, "PLY");
+        }
+    }
+
+    #[test]
+    fn glb_layout() {
+        for colored in [false, true] {
+            let mesh = square(colored);
+            let mut glb = Vec::new();
+            mesh.write_glb(&mut glb).unwrap();
+            let word =
+                |offset: usize| u32::from_le_bytes(glb[offset..offset + 4].try_into().unwrap());
+            assert_eq!(&glb[..4], b"glTF");
+            assert_eq!(word(4), 2);
+            assert_eq!(word(8) as usize, glb.len());
+            let json_length = word(12) as usize;
+            assert_eq!(&glb[16..20], b"JSON");
+            let gltf: serde_json::Value =
+                serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+            let bin = 20 + json_length;
+            assert_eq!(&glb[bin + 4..bin + 8], b"BIN\0");
+            assert_eq!(word(bin) as usize, glb.len() - bin - 8);
+            assert_eq!(
+                gltf["buffers"][0]["byteLength"],
+                4 * 12 + colored as usize * 16 + 24
+            );
+
+            let accessors = gltf["accessors"].as_array().unwrap();
+            assert_eq!(accessors.len(), 2 + colored as usize);
+            assert_eq!(accessors[0]["count"], 4);
+            // Half a turn around X
+            assert_eq!(accessors[0]["min"], json!([0.0, -0.1f32, -1.5]));
+            assert_eq!(accessors[0]["max"], json!([0.1f32, 0.0, -1.0]));
+            let indices = gltf["meshes"][0]["primitives"][0]["indices"]
+                .as_u64()
+                .unwrap();
+            assert_eq!(accessors[indices as usize]["count"], 6);
+        }
+    }
+
+    #[test]
+    fn empty_mesh() {
+        let mesh = Mesh::default();
+        let error = mesh.write_glb(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "The mesh has no triangles");
+        let path = std::env::temp_dir().join("realsense-tools-empty-mesh");
+        assert!(mesh.save(&path, MeshFormat::Ply).is_err());
+        assert!(!path.with_extension("ply").exists());
+
+        // Vertices without triangles are still a valid OBJ or PLY
+        let mut ply = Vec::new();
+        mesh.write_ply(&mut ply).unwrap();
+        assert_same(&parse_ply(&ply), &mesh, "PLY");
+    }
+}
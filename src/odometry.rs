@@ -0,0 +1,591 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Motion of the camera tracked between depth frames with point-to-plane ICP,
+//! and the trajectory in the TUM format.
+
+use crate::camera_model::Intrinsics;
+use crate::frame_snapshot::{FrameSnapshot, SnapshotData};
+use anyhow::{anyhow, bail, Result};
+use glam::{Affine3A, Quat, Vec2, Vec3};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Farthest correspondence kept by default, in meters
+pub const DEFAULT_MAX_DISTANCE: f32 = 0.05;
+
+const ITERATIONS: usize = 20;
+
+/// Pixels skipped between the points aligned, in both directions
+const STRIDE: usize = 4;
+
+/// Fewer correspondences than this lose the tracking
+const MIN_CORRESPONDENCES: usize = 100;
+
+/// Updates smaller than this have converged
+const CONVERGED: f32 = 1e-6;
+
+/// Smallest eigenvalue of the normal equations, relative to the largest one.
+/// Below it, some motion isn't held by the surfaces seen, e.g. sliding
+/// along a wall.
+const MIN_EIGENVALUE_RATIO: f64 = 1e-3;
+
+/// Depth frame deprojected on its pixel grid, with the normals of the
+/// surface facing the camera
+struct DepthGrid {
+    intrinsics: Intrinsics,
+    points: Vec<Option<Vec3>>,
+    normals: Vec<Option<Vec3>>,
+}
+
+impl DepthGrid {
+    fn new(depth: &[u16], depth_units: f32, intrinsics: &Intrinsics, max_distance: f32) -> Self {
+        let (width, height) = (intrinsics.width, intrinsics.height);
+        let points: Vec<_> = depth
+            .iter()
+            .enumerate()
+            .map(|(index, depth)| {
+                let pixel = Vec2::new((index % width) as f32, (index / width) as f32);
+                (*depth != 0).then(|| intrinsics.deproject(pixel, *depth as f32 * depth_units))
+            })
+            .collect();
+        // From the neighbors to the right and below, when on the same surface
+        let normals = (0..points.len())
+            .map(|index| {
+                let (col, row) = (index % width, index / width);
+                if col + 1 >= width || row + 1 >= height {
+                    return None;
+                }
+                let point = points[index]?;
+                let right = points[index + 1]? - point;
+                let down = points[index + width]? - point;
+                if right.length() > max_distance || down.length() > max_distance {
+                    return None;
+                }
+                let normal = right.cross(down).try_normalize()?;
+                Some(if normal.dot(point) > 0.0 {
+                    -normal
+                } else {
+                    normal
+                })
+            })
+            .collect();
+        Self {
+            intrinsics: *intrinsics,
+            points,
+            normals,
+        }
+    }
+
+    /// Point and normal seen at the pixel where a point projects
+    fn at(&self, point: Vec3) -> Option<(Vec3, Vec3)> {
+        if point.z <= 0.0 {
+            return None;
+        }
+        let pixel = self.intrinsics.project(point).round();
+        if pixel.x < 0.0 || pixel.y < 0.0 {
+            return None;
+        }
+        let (col, row) = (pixel.x as usize, pixel.y as usize);
+        if col >= self.intrinsics.width || row >= self.intrinsics.height {
+            return None;
+        }
+        let index = row * self.intrinsics.width + col;
+        Some((self.points[index]?, self.normals[index]?))
+    }
+}
+
+/// Pose of the camera in the frame of the first depth frame tracked, moved
+/// by aligning every depth frame with the previous one
+pub struct Odometry {
+    /// Farthest correspondence kept, in meters
+    pub max_distance: f32,
+    pose: Affine3A,
+    previous: Option<DepthGrid>,
+    /// Timestamps, in milliseconds, and poses of the frames tracked
+    trajectory: Vec<(f64, Affine3A)>,
+}
+
+impl Default for Odometry {
+    fn default() -> Self {
+        Self {
+            max_distance: DEFAULT_MAX_DISTANCE,
+            pose: Affine3A::IDENTITY,
+            previous: None,
+            trajectory: Vec::new(),
+        }
+    }
+}
+
+impl Odometry {
+    /// Moves points from the camera to the frame of the first depth frame
+    pub fn pose(&self) -> Affine3A {
+        self.pose
+    }
+
+    pub fn trajectory(&self) -> &[(f64, Affine3A)] {
+        &self.trajectory
+    }
+
+    /// Starts again from the next frame
+    pub fn reset(&mut self) {
+        self.pose = Affine3A::IDENTITY;
+        self.previous = None;
+        self.trajectory.clear();
+    }
+
+    /// Aligns a depth frame with the previous one and returns the new pose.
+    /// `rotation`, usually integrated from the gyro, seeds the rotation of
+    /// the camera since the previous frame. When the alignment fails, the
+    /// pose doesn't move and the next frame is aligned with this one.
+    pub fn track(
+        &mut self,
+        timestamp: f64,
+        depth: &[u16],
+        depth_units: f32,
+        intrinsics: &Intrinsics,
+        rotation: Option<Quat>,
+    ) -> Result<Affine3A> {
+        let grid = DepthGrid::new(depth, depth_units, intrinsics, self.max_distance);
+        let motion = match &self.previous {
+            Some(previous) => {
+                let initial = Affine3A::from_quat(rotation.unwrap_or(Quat::IDENTITY));
+                align(&grid, previous, initial, self.max_distance)
+            }
+            None => Ok(Affine3A::IDENTITY),
+        };
+        self.previous = Some(grid);
+        self.pose *= motion?;
+        self.trajectory.push((timestamp, self.pose));
+        Ok(self.pose)
+    }
+
+    /// Tracks a depth frame of a snapshot
+    pub fn track_snapshot(
+        &mut self,
+        depth: &FrameSnapshot,
+        rotation: Option<Quat>,
+    ) -> Result<Affine3A> {
+        let SnapshotData::Depth {
+            depth_units, data, ..
+        } = &depth.data
+        else {
+            bail!("{} is not a depth frame", depth.stream);
+        };
+        let intrinsics = depth
+            .intrinsics
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing intrinsics of {}", depth.stream))?;
+        self.track(depth.timestamp, data, *depth_units, intrinsics, rotation)
+    }
+
+    /// One line per pose: timestamp in seconds, translation and the rotation
+    /// quaternion, as in the TUM RGB-D benchmark
+    pub fn write_tum(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writeln!(writer, "# timestamp tx ty tz qx qy qz qw")?;
+        for (timestamp, pose) in &self.trajectory {
+            let (_, rotation, translation) = pose.to_scale_rotation_translation();
+            let [tx, ty, tz] = translation.to_array();
+            let [qx, qy, qz, qw] = rotation.to_array();
+            let seconds = timestamp / 1000.0;
+            writeln!(writer, "{seconds:.6} {tx} {ty} {tz} {qx} {qy} {qz} {qw}")?;
+        }
+        Ok(())
+    }
+
+    /// Writes the trajectory in the TUM format, adding the extension
+    pub fn save_tum(&self, path: &Path) -> Result<PathBuf> {
+        let path = path.with_extension("txt");
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        self.write_tum(&mut writer)?;
+        writer.flush()?;
+        Ok(path)
+    }
+}
+
+/// Rigid transform moving the source points onto the target surface,
+/// minimizing the distances along the target normals. Correspondences are
+/// found by projecting into the target.
+fn align(
+    source: &DepthGrid,
+    target: &DepthGrid,
+    initial: Affine3A,
+    max_distance: f32,
+) -> Result<Affine3A> {
+    let width = source.intrinsics.width;
+    let points: Vec<Vec3> = source
+        .points
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| {
+            (index % width).is_multiple_of(STRIDE) && (index / width).is_multiple_of(STRIDE)
+        })
+        .filter_map(|(_, point)| *point)
+        .collect();
+
+    let mut transform = initial;
+    for _ in 0..ITERATIONS {
+        // Normal equations of the point-to-plane distances, linearized for
+        // small rotations
+        let mut a = [[0.0f64; 6]; 6];
+        let mut b = [0.0f64; 6];
+        let mut correspondences = 0;
+        for point in &points {
+            let moved = transform.transform_point3(*point);
+            let Some((target_point, normal)) = target.at(moved) else {
+                continue;
+            };
+            if moved.distance(target_point) > max_distance {
+                continue;
+            }
+            let residual = (moved - target_point).dot(normal) as f64;
+            let rotation = moved.cross(normal);
+            let jacobian = [
+                rotation.x, rotation.y, rotation.z, normal.x, normal.y, normal.z,
+            ]
+            .map(|value| value as f64);
+            for row in 0..6 {
+                for col in 0..6 {
+                    a[row][col] += jacobian[row] * jacobian[col];
+                }
+                b[row] -= jacobian[row] * residual;
+            }
+            correspondences += 1;
+        }
+        if correspondences < MIN_CORRESPONDENCES {
+            bail!("Only {correspondences} correspondences, lost tracking");
+        }
+        let eigenvalues = eigenvalues(a);
+        let (min, max) = eigenvalues
+            .iter()
+            .fold((f64::MAX, 0.0f64), |(min, max), value| {
+                (min.min(*value), max.max(*value))
+            });
+        if min < MIN_EIGENVALUE_RATIO * max {
+            bail!("Not enough structure to align, lost tracking");
+        }
+        let x = solve(a, b).ok_or_else(|| anyhow!("Degenerate alignment, lost tracking"))?;
+        let x = x.map(|value| value as f32);
+        let rotation = Vec3::new(x[0], x[1], x[2]);
+        let translation = Vec3::new(x[3], x[4], x[5]);
+        let update =
+            Affine3A::from_rotation_translation(Quat::from_scaled_axis(rotation), translation);
+        transform = update * transform;
+        if rotation.length_squared() + translation.length_squared() < CONVERGED * CONVERGED {
+            break;
+        }
+    }
+    Ok(transform)
+}
+
+/// Eigenvalues of a symmetric matrix, with the cyclic Jacobi method
+fn eigenvalues(mut a: [[f64; 6]; 6]) -> [f64; 6] {
+    for _ in 0..50 {
+        let off_diagonal: f64 = (0..6)
+            .flat_map(|row| {
+                (0..6)
+                    .filter(move |col| *col != row)
+                    .map(move |col| (row, col))
+            })
+            .map(|(row, col)| a[row][col] * a[row][col])
+            .sum();
+        let diagonal: f64 = (0..6).map(|i| a[i][i] * a[i][i]).sum();
+        if off_diagonal <= 1e-24 * diagonal {
+            break;
+        }
+        for p in 0..6 {
+            for q in p + 1..6 {
+                if a[p][q] == 0.0 {
+                    continue;
+                }
+                // Rotation zeroing a[p][q]
+                let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+                for row in a.iter_mut() {
+                    let (kp, kq) = (row[p], row[q]);
+                    row[p] = c * kp - s * kq;
+                    row[q] = s * kp + c * kq;
+                }
+                let (row_p, row_q) = (a[p], a[q]);
+                for k in 0..6 {
+                    a[p][k] = c * row_p[k] - s * row_q[k];
+                    a[q][k] = s * row_p[k] + c * row_q[k];
+                }
+            }
+        }
+    }
+    std::array::from_fn(|i| a[i][i])
+}
+
+/// Solves a linear system with Gaussian elimination, or `None` if singular
+fn solve(mut a: [[f64; 6]; 6], mut b: [f64; 6]) -> Option<[f64; 6]> {
+    for col in 0..6 {
+        let pivot = (col..6).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        let pivot_row = a[col];
+        for row in col + 1..6 {
+            let factor = a[row][col] / pivot_row[col];
+            for (value, pivot) in a[row][col..].iter_mut().zip(&pivot_row[col..]) {
+                *value -= factor * pivot;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = [0.0; 6];
+    for row in (0..6).rev() {
+        let sum: f64 = (row + 1..6).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_model::DistortionModel;
+
+    /// Finer than the millimeters of the D400, so the errors left are of
+    /// the alignment rather than of the rounding of the depth
+    const DEPTH_UNITS: f32 = 0.0001;
+
+    fn intrinsics() -> Intrinsics {
+        Intrinsics {
+            width: 320,
+            height: 240,
+            ppx: 159.5,
+            ppy: 119.5,
+            fx: 240.0,
+            fy: 240.0,
+            model: DistortionModel::None,
+            coeffs: [0.0; 5],
+        }
+    }
+
+    /// Planes as their normal and offset, `normal.dot(point) == offset`
+    type Scene = [(Vec3, f32)];
+
+    /// End of a corridor: the back wall, the floor below the camera, which
+    /// looks along +Z with Y down, the side walls, and a ramp
+    const ROOM: [(Vec3, f32); 5] = [
+        (Vec3::Z, 2.0),
+        (Vec3::Y, 0.6),
+        (Vec3::X, -0.9),
+        (Vec3::X, 1.1),
+        (Vec3::new(0.0, 0.6, 0.8), 1.2),
+    ];
+
+    /// Depth map seen from a pose, which moves points from the camera to
+    /// the scene
+    fn render(scene: &Scene, pose: &Affine3A) -> Vec<u16> {
+        let intrinsics = intrinsics();
+        let mut depth = Vec::new();
+        for row in 0..intrinsics.height {
+            for col in 0..intrinsics.width {
+                // With a Z of 1, so the distance along it is the depth
+                let ray = intrinsics.deproject(Vec2::new(col as f32, row as f32), 1.0);
+                let (origin, direction) = (pose.translation.into(), pose.transform_vector3(ray));
+                let z = scene
+                    .iter()
+                    .filter_map(|(normal, offset)| {
+                        let z = (offset - normal.dot(origin)) / normal.dot(direction);
+                        (z > 0.0).then_some(z)
+                    })
+                    .fold(f32::MAX, f32::min);
+                depth.push(if z < 5.0 {
+                    (z / DEPTH_UNITS).round() as u16
+                } else {
+                    0
+                });
+            }
+        }
+        depth
+    }
+
+    /// Angle between two rotations, and distance between two positions
+    fn pose_error(a: &Affine3A, b: &Affine3A) -> (f32, f32) {
+        let (_, a_rotation, a_translation) = a.to_scale_rotation_translation();
+        let (_, b_rotation, b_translation) = b.to_scale_rotation_translation();
+        // The sine of half the angle, which is precise for small angles
+        let difference = a_rotation.inverse() * b_rotation;
+        (
+            2.0 * difference.xyz().length().asin(),
+            a_translation.distance(b_translation),
+        )
+    }
+
+    /// Poses of a camera moving and turning a bit every frame
+    fn sequence(frames: usize, step: Vec3, turn: Quat) -> Vec<Affine3A> {
+        let motion = Affine3A::from_rotation_translation(turn, step);
+        std::iter::successors(Some(Affine3A::IDENTITY), |pose| Some(*pose * motion))
+            .take(frames)
+            .collect()
+    }
+
+    #[test]
+    fn follows_the_ground_truth() {
+        let turn = Quat::from_euler(glam::EulerRot::YXZ, 0.01, -0.005, 0.004);
+        let poses = sequence(15, Vec3::new(0.01, -0.003, 0.015), turn);
+        let mut odometry = Odometry::default();
+        let mut previous = Affine3A::IDENTITY;
+        for (frame, pose) in poses.iter().enumerate() {
+            let depth = render(&ROOM, pose);
+            let timestamp = frame as f64 * 33.3;
+            let tracked = odometry
+                .track(timestamp, &depth, DEPTH_UNITS, &intrinsics(), None)
+                .unwrap();
+            // Every motion is close, and their errors add up slowly
+            let truth = poses[frame.saturating_sub(1)].inverse() * *pose;
+            let (angle, distance) = pose_error(&(previous.inverse() * tracked), &truth);
+            assert!(
+                angle < 0.02f32.to_radians(),
+                "Frame {frame}: {angle} rad off"
+            );
+            assert!(distance < 0.0005, "Frame {frame}: {distance} m off");
+            previous = tracked;
+        }
+        let (angle, distance) = pose_error(&previous, poses.last().unwrap());
+        assert!(angle < 0.05f32.to_radians(), "{angle} rad off");
+        assert!(distance < 0.002, "{distance} m off");
+        assert_eq!(odometry.trajectory().len(), poses.len());
+    }
+
+    #[test]
+    fn gyro_seeds_fast_turns() {
+        // Too fast for the correspondences found without a seed
+        let turn = Quat::from_rotation_y(4.0f32.to_radians());
+        let poses = sequence(5, Vec3::ZERO, turn);
+        let mut odometry = Odometry::default();
+        for (frame, pose) in poses.iter().enumerate() {
+            let depth = render(&ROOM, pose);
+            let rotation = (frame > 0).then_some(turn);
+            let tracked = odometry
+                .track(0.0, &depth, DEPTH_UNITS, &intrinsics(), rotation)
+                .unwrap();
+            let (angle, distance) = pose_error(&tracked, pose);
+            assert!(
+                angle < 0.02f32.to_radians(),
+                "Frame {frame}: {angle} rad off"
+            );
+            assert!(distance < 0.0005, "Frame {frame}: {distance} m off");
+        }
+    }
+
+    #[test]
+    fn a_wall_alone_is_not_enough() {
+        // Sliding along it changes nothing in the depth. It's turned, so the
+        // normal equations aren't exactly singular.
+        let wall = [(Vec3::new(0.3, -0.2, 1.0).normalize(), 1.5)];
+        let poses = sequence(2, Vec3::new(0.02, 0.03, 0.0), Quat::IDENTITY);
+        let mut odometry = Odometry::default();
+        let depth = render(&wall, &poses[0]);
+        odometry
+            .track(0.0, &depth, DEPTH_UNITS, &intrinsics(), None)
+            .unwrap();
+        let depth = render(&wall, &poses[1]);
+        let error = odometry
+            .track(33.3, &depth, DEPTH_UNITS, &intrinsics(), None)
+            .unwrap_err();
+        assert!(error.to_string().contains("lost tracking"), "{error}");
+        // The pose doesn't slide
+        assert_eq!(odometry.pose(), Affine3A::IDENTITY);
+        assert_eq!(odometry.trajectory().len(), 1);
+    }
+
+    #[test]
+    fn lost_without_depth() {
+        let mut odometry = Odometry::default();
+        let room = render(&ROOM, &Affine3A::IDENTITY);
+        odometry
+            .track(0.0, &room, DEPTH_UNITS, &intrinsics(), None)
+            .unwrap();
+        let empty = vec![0; room.len()];
+        let error = odometry
+            .track(33.3, &empty, DEPTH_UNITS, &intrinsics(), None)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Only 0 correspondences, lost tracking");
+
+        odometry.reset();
+        assert!(odometry.trajectory().is_empty());
+        assert_eq!(odometry.pose(), Affine3A::IDENTITY);
+    }
+
+    #[test]
+    fn eigenvalues_of_a_rotated_diagonal() {
+        let expected = [5.0, 3.0, 2.0, 1.0, 0.5, 0.01];
+        // Q D Qᵀ, with a rotation mixing every axis
+        let q: [[f64; 6]; 6] = {
+            let mut q = [[0.0; 6]; 6];
+            for (i, row) in q.iter_mut().enumerate() {
+                for (j, value) in row.iter_mut().enumerate() {
+                    // Orthogonal discrete cosine basis
+                    let scale = if i == 0 {
+                        (1.0f64 / 6.0).sqrt()
+                    } else {
+                        (2.0f64 / 6.0).sqrt()
+                    };
+                    *value =
+                        scale * (std::f64::consts::PI * (j as f64 + 0.5) * i as f64 / 6.0).cos();
+                }
+            }
+            q
+        };
+        let a = std::array::from_fn(|row| {
+            std::array::from_fn(|col| (0..6).map(|k| q[k][row] * expected[k] * q[k][col]).sum())
+        });
+        let mut values = eigenvalues(a);
+        values.sort_by(|a, b| b.total_cmp(a));
+        for (value, expected) in values.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-9, "{values:?}");
+        }
+    }
+
+    #[test]
+    fn tum_format() {
+        let odometry = Odometry {
+            trajectory: vec![
+                (1500.0, Affine3A::IDENTITY),
+                (
+                    1533.3,
+                    Affine3A::from_rotation_translation(
+                        Quat::from_rotation_z(std::f32::consts::PI),
+                        Vec3::new(0.5, -0.25, 1.0),
+                    ),
+                ),
+            ],
+            ..Default::default()
+        };
+        let mut text = Vec::new();
+        odometry.write_tum(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "# timestamp tx ty tz qx qy qz qw");
+        assert_eq!(lines[1], "1.500000 0 0 0 0 0 0 1");
+        let values: Vec<f32> = lines[2]
+            .split(' ')
+            .map(|value| value.parse().unwrap())
+            .collect();
+        assert_eq!(values[..4], [1.5333, 0.5, -0.25, 1.0]);
+        assert!((values[6].abs() - 1.0).abs() < 1e-6, "{}", lines[2]);
+    }
+}
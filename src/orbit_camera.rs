@@ -0,0 +1,309 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Camera orbiting around a pivot, to navigate the 3D views.
+
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4Swizzles};
+
+const MIN_DISTANCE: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViewPreset {
+    /// From the RealSense, looking forward
+    Front,
+    /// From above, looking down
+    Top,
+    /// From the right, looking left
+    Side,
+}
+
+impl ViewPreset {
+    pub const ALL: [Self; 3] = [Self::Front, Self::Top, Self::Side];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Front => "Front",
+            Self::Top => "Top",
+            Self::Side => "Side",
+        }
+    }
+}
+
+/// Camera looking at a pivot from some distance, in OpenGL coordinates: X
+/// right, Y up and looking along -Z
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrbitCamera {
+    pub pivot: Vec3,
+    pub rotation: Quat,
+    pub distance: f32,
+    /// Vertical field of view, in degrees
+    pub fov: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Default for OrbitCamera {
+    /// Where the RealSense is, looking at a point one meter ahead
+    fn default() -> Self {
+        Self {
+            pivot: Vec3::new(0.0, 0.0, -1.0),
+            rotation: Quat::IDENTITY,
+            distance: 1.0,
+            fov: 45.0,
+            near: 0.01,
+            far: 100.0,
+        }
+    }
+}
+
+impl OrbitCamera {
+    pub fn position(&self) -> Vec3 {
+        self.pivot + self.rotation * Vec3::Z * self.distance
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::from_rotation_translation(self.rotation, self.position()).inverse()
+    }
+
+    pub fn projection(&self, aspect_ratio: f32) -> Mat4 {
+        Mat4::perspective_rh_gl(self.fov.to_radians(), aspect_ratio, self.near, self.far)
+    }
+
+    pub fn view_projection(&self, aspect_ratio: f32) -> Mat4 {
+        self.projection(aspect_ratio) * self.view()
+    }
+
+    /// Looks at the pivot from a preset direction, keeping the distance
+    pub fn preset(&mut self, preset: ViewPreset) {
+        self.rotation = match preset {
+            ViewPreset::Front => Quat::IDENTITY,
+            ViewPreset::Top => Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+            ViewPreset::Side => Quat::from_rotation_y(std::f32::consts::FRAC_PI_2),
+        };
+    }
+
+    /// Rotates around the pivot as if dragging a ball under the cursor.
+    /// Cursor positions are in normalized device coordinates, from -1 to 1
+    /// with Y up.
+    pub fn arcball(&mut self, from: Vec2, to: Vec2) {
+        let (from, to) = (arcball_point(from), arcball_point(to));
+        if from.abs_diff_eq(to, 1e-6) {
+            return;
+        }
+        // The camera turns the opposite way the scene seems to
+        self.rotation = (self.rotation * Quat::from_rotation_arc(to, from)).normalize();
+    }
+
+    /// Moves the pivot with the cursor, so the point under it follows it
+    pub fn pan(&mut self, delta: Vec2, viewport_height: f32) {
+        let meters_per_pixel =
+            2.0 * (self.fov.to_radians() / 2.0).tan() * self.distance / viewport_height;
+        self.pivot -= self.rotation * Vec3::new(delta.x, -delta.y, 0.0) * meters_per_pixel;
+    }
+
+    /// Gets closer to the pivot with factors below 1
+    pub fn zoom(&mut self, factor: f32) {
+        self.distance = (self.distance * factor).max(MIN_DISTANCE);
+    }
+
+    /// Moves camera and pivot together, in the camera frame
+    pub fn fly(&mut self, translation: Vec3) {
+        self.pivot += self.rotation * translation;
+    }
+
+    /// Orbits around a new pivot, without moving the camera
+    pub fn set_pivot(&mut self, pivot: Vec3) {
+        let position = self.position();
+        let distance = position.distance(pivot);
+        if distance < MIN_DISTANCE {
+            return;
+        }
+        let back = (position - pivot) / distance;
+        let up = self.rotation * Vec3::Y;
+        let right = up
+            .cross(back)
+            .try_normalize()
+            .unwrap_or(self.rotation * Vec3::X);
+        let up = back.cross(right);
+        self.rotation = Quat::from_mat3(&Mat3::from_cols(right, up, back));
+        self.pivot = pivot;
+        self.distance = distance;
+    }
+}
+
+/// Point of the unit sphere under the cursor, or of its rim outside it
+fn arcball_point(cursor: Vec2) -> Vec3 {
+    let length2 = cursor.length_squared();
+    if length2 <= 1.0 {
+        cursor.extend((1.0 - length2).sqrt())
+    } else {
+        (cursor / length2.sqrt()).extend(0.0)
+    }
+}
+
+/// Closest point drawn within a radius of the cursor, both in normalized
+/// device coordinates
+pub fn pick_point(
+    points: &[Vec3],
+    view_projection: &Mat4,
+    cursor: Vec2,
+    radius: f32,
+) -> Option<Vec3> {
+    points
+        .iter()
+        .filter_map(|point| {
+            let clip = *view_projection * point.extend(1.0);
+            if clip.w <= 0.0 {
+                return None;
+            }
+            let ndc = clip.xyz() / clip.w;
+            let inside = ndc.z.abs() <= 1.0 && ndc.truncate().distance(cursor) <= radius;
+            inside.then_some((ndc.z, *point))
+        })
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, point)| point)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!(a.abs_diff_eq(b, 1e-4), "{a} != {b}");
+    }
+
+    /// Normalized device coordinates of a point, with a square viewport
+    fn ndc(camera: &OrbitCamera, point: Vec3) -> Vec2 {
+        let clip = camera.view_projection(1.0) * point.extend(1.0);
+        clip.xy() / clip.w
+    }
+
+    #[test]
+    fn arcball_orbits_around_the_pivot() {
+        let mut camera = OrbitCamera::default();
+        let pivot = camera.pivot;
+        camera.arcball(Vec2::ZERO, Vec2::new(0.5, 0.0));
+        // Dragging the scene right moves the camera left, at the same distance
+        assert_eq!(camera.pivot, pivot);
+        assert!(camera.position().x < -0.1, "{}", camera.position());
+        assert!((camera.position().distance(pivot) - 1.0).abs() < 1e-5);
+        assert!((camera.rotation.length() - 1.0).abs() < 1e-5);
+        // The pivot is still at the center of the view
+        assert!(ndc(&camera, pivot).length() < 1e-4);
+
+        // Dragging back undoes it
+        camera.arcball(Vec2::new(0.5, 0.0), Vec2::ZERO);
+        assert_close(camera.position(), OrbitCamera::default().position());
+
+        // Not moving the cursor does nothing
+        let before = camera;
+        camera.arcball(Vec2::new(0.2, 0.3), Vec2::new(0.2, 0.3));
+        assert_eq!(camera, before);
+    }
+
+    #[test]
+    fn set_pivot_keeps_the_camera_in_place() {
+        let mut camera = OrbitCamera::default();
+        camera.arcball(Vec2::ZERO, Vec2::new(0.3, 0.2));
+        let position = camera.position();
+        let pivot = Vec3::new(0.4, -0.2, -2.0);
+        camera.set_pivot(pivot);
+        assert_eq!(camera.pivot, pivot);
+        assert_close(camera.position(), position);
+        assert!((camera.distance - position.distance(pivot)).abs() < 1e-5);
+        // Looking at the new pivot
+        assert!(ndc(&camera, pivot).length() < 1e-4);
+        assert_close(camera.rotation * -Vec3::Z, (pivot - position).normalize());
+
+        // A pivot on the camera is ignored
+        let before = camera;
+        camera.set_pivot(position);
+        assert_eq!(camera, before);
+    }
+
+    #[test]
+    fn set_pivot_does_not_roll() {
+        let mut camera = OrbitCamera::default();
+        camera.set_pivot(Vec3::new(1.0, 0.0, -1.0));
+        assert!((camera.rotation * Vec3::X).y.abs() < 1e-5);
+        assert!((camera.rotation * Vec3::Y).y > 0.9);
+    }
+
+    #[test]
+    fn presets_look_at_the_pivot() {
+        let mut camera = OrbitCamera {
+            pivot: Vec3::new(0.0, 0.0, -2.0),
+            distance: 3.0,
+            ..Default::default()
+        };
+        let cases = [
+            (ViewPreset::Top, Vec3::new(0.0, 3.0, -2.0)),
+            (ViewPreset::Side, Vec3::new(3.0, 0.0, -2.0)),
+            (ViewPreset::Front, Vec3::new(0.0, 0.0, 1.0)),
+        ];
+        for (preset, position) in cases {
+            camera.preset(preset);
+            assert_close(camera.position(), position);
+            assert_eq!(camera.distance, 3.0, "{}", preset.as_str());
+        }
+    }
+
+    #[test]
+    fn pan_moves_the_pivot_with_the_cursor() {
+        let mut camera = OrbitCamera {
+            distance: 2.0,
+            ..Default::default()
+        };
+        let point = camera.pivot;
+        let height = 400.0;
+        // 100 pixels right and 50 down, out of 400
+        camera.pan(Vec2::new(100.0, 50.0), height);
+        let moved = ndc(&camera, point);
+        assert!((moved.x - 0.5).abs() < 1e-4, "{moved}");
+        assert!((moved.y + 0.25).abs() < 1e-4, "{moved}");
+        assert_eq!(camera.distance, 2.0);
+    }
+
+    #[test]
+    fn pick_the_closest_point_under_the_cursor() {
+        let camera = OrbitCamera::default();
+        let view_projection = camera.view_projection(1.0);
+        let near = Vec3::new(0.0, 0.0, -1.0);
+        let far = Vec3::new(0.0, 0.0, -3.0);
+        let aside = Vec3::new(0.5, 0.0, -1.0);
+        let behind = Vec3::new(0.0, 0.0, 2.0);
+        let points = [far, aside, near, behind];
+        assert_eq!(
+            pick_point(&points, &view_projection, Vec2::ZERO, 0.05),
+            Some(near)
+        );
+        assert_eq!(
+            pick_point(&[far, behind], &view_projection, Vec2::ZERO, 0.05),
+            Some(far)
+        );
+        let cursor = ndc(&camera, aside);
+        assert_eq!(
+            pick_point(&points, &view_projection, cursor, 0.05),
+            Some(aside)
+        );
+        assert_eq!(
+            pick_point(&points, &view_projection, Vec2::new(-0.8, 0.8), 0.05),
+            None
+        );
+    }
+}
@@ -0,0 +1,393 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Dominant planes of a point cloud, found with RANSAC and refined with a
+//! least squares fit of their inliers.
+
+use glam::{Mat3, Vec3};
+
+/// Farthest inlier by default, in meters
+pub const DEFAULT_THRESHOLD: f32 = 0.01;
+
+pub const DEFAULT_MAX_PLANES: usize = 3;
+
+/// Smallest share of the points making a plane by default
+pub const DEFAULT_MIN_SHARE: f32 = 0.05;
+
+const ITERATIONS: usize = 200;
+
+/// Points scoring every hypothesis, picked at random
+const SAMPLES: usize = 2000;
+
+/// Rounds of fitting the inliers and finding them again
+const REFINEMENTS: usize = 3;
+
+/// Plane `normal · p + distance = 0`, with the normal facing the camera so
+/// the distance is from the camera to the plane
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plane {
+    pub normal: Vec3,
+    /// In meters
+    pub distance: f32,
+    /// Indexes of the points on the plane
+    pub inliers: Vec<usize>,
+    /// Root mean square distance of the inliers to the plane, in meters
+    pub rms: f32,
+}
+
+impl Plane {
+    /// Through three points, unless they are aligned
+    fn from_points(a: Vec3, b: Vec3, c: Vec3) -> Option<Self> {
+        let normal = (b - a).cross(c - a).try_normalize()?;
+        Some(Self::oriented(normal, -normal.dot(a)))
+    }
+
+    /// Flips the plane if its normal doesn't face the camera
+    fn oriented(normal: Vec3, distance: f32) -> Self {
+        let (normal, distance) = if distance < 0.0 {
+            (-normal, -distance)
+        } else {
+            (normal, distance)
+        };
+        Self {
+            normal,
+            distance,
+            inliers: Vec::new(),
+            rms: 0.0,
+        }
+    }
+
+    /// Positive in front of the plane, in meters
+    pub fn signed_distance(&self, point: Vec3) -> f32 {
+        self.normal.dot(point) + self.distance
+    }
+
+    /// Angle between the normal and an axis, in degrees
+    pub fn angle_to(&self, axis: Vec3) -> f32 {
+        self.normal.angle_between(axis).to_degrees()
+    }
+}
+
+/// Settings of the plane detection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlaneDetector {
+    /// Farthest inlier, in meters
+    pub threshold: f32,
+    pub max_planes: usize,
+    /// Smallest share of the points making a plane
+    pub min_share: f32,
+}
+
+impl Default for PlaneDetector {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            max_planes: DEFAULT_MAX_PLANES,
+            min_share: DEFAULT_MIN_SHARE,
+        }
+    }
+}
+
+impl PlaneDetector {
+    /// Largest planes first. The inliers of every plane are taken out before
+    /// looking for the next one.
+    pub fn detect(&self, points: &[Vec3]) -> Vec<Plane> {
+        let min_inliers = ((points.len() as f32 * self.min_share) as usize).max(3);
+        let mut rng = XorShift::default();
+        let mut remaining: Vec<usize> = (0..points.len()).collect();
+        let mut planes = Vec::new();
+        while planes.len() < self.max_planes && remaining.len() >= min_inliers {
+            let Some(plane) = self.ransac(points, &remaining, &mut rng) else {
+                break;
+            };
+            let plane = self.refine(points, &remaining, plane);
+            if plane.inliers.len() < min_inliers {
+                break;
+            }
+            // Both lists are sorted
+            let mut inliers = plane.inliers.iter().peekable();
+            remaining.retain(|index| {
+                let is_inlier = inliers.peek() == Some(&index);
+                if is_inlier {
+                    inliers.next();
+                }
+                !is_inlier
+            });
+            planes.push(plane);
+        }
+        planes
+    }
+
+    /// Plane through three random points agreeing with most of a sample
+    fn ransac(&self, points: &[Vec3], indexes: &[usize], rng: &mut XorShift) -> Option<Plane> {
+        let samples: Vec<Vec3> = (0..SAMPLES.min(indexes.len()))
+            .map(|_| points[indexes[rng.below(indexes.len())]])
+            .collect();
+        let mut best: Option<(usize, Plane)> = None;
+        for _ in 0..ITERATIONS {
+            let [a, b, c] = [(); 3].map(|_| samples[rng.below(samples.len())]);
+            let Some(plane) = Plane::from_points(a, b, c) else {
+                continue;
+            };
+            let score = samples
+                .iter()
+                .filter(|point| plane.signed_distance(**point).abs() <= self.threshold)
+                .count();
+            if best.as_ref().is_none_or(|(best, _)| score > *best) {
+                best = Some((score, plane));
+            }
+        }
+        best.map(|(_, plane)| plane)
+    }
+
+    /// Fits the plane to its inliers, and finds them again
+    fn refine(&self, points: &[Vec3], indexes: &[usize], mut plane: Plane) -> Plane {
+        for round in 0..=REFINEMENTS {
+            plane.inliers = indexes
+                .iter()
+                .copied()
+                .filter(|index| plane.signed_distance(points[*index]).abs() <= self.threshold)
+                .collect();
+            if round == REFINEMENTS {
+                break;
+            }
+            let inliers = plane.inliers.iter().map(|index| points[*index]);
+            match fit_plane(inliers) {
+                Some(fitted) => plane = fitted,
+                None => break,
+            }
+        }
+        let squares: f32 = plane
+            .inliers
+            .iter()
+            .map(|index| plane.signed_distance(points[*index]).powi(2))
+            .sum();
+        plane.rms = (squares / plane.inliers.len().max(1) as f32).sqrt();
+        plane
+    }
+}
+
+/// Least squares plane through some points: through their centroid, normal
+/// to the direction they spread the least, or `None` with fewer than three
+pub fn fit_plane(points: impl Iterator<Item = Vec3> + Clone) -> Option<Plane> {
+    let count = points.clone().count();
+    if count < 3 {
+        return None;
+    }
+    let centroid = points.clone().sum::<Vec3>() / count as f32;
+    let mut covariance = Mat3::ZERO;
+    for point in points {
+        let offset = point - centroid;
+        covariance += Mat3::from_cols(offset * offset.x, offset * offset.y, offset * offset.z);
+    }
+    // The smallest eigenvector of the covariance is the largest one of its
+    // inverse, found with the power iteration. Regularized, as the
+    // covariance of points exactly on a plane is singular.
+    let trace = covariance.x_axis.x + covariance.y_axis.y + covariance.z_axis.z;
+    let regularized = covariance + Mat3::from_diagonal(Vec3::splat(trace * 1e-6));
+    if regularized.determinant() <= 0.0 {
+        return None;
+    }
+    let inverse = regularized.inverse();
+    // Seeded with the longest column, the inverse applied to the axis the
+    // normal is closest to, so the seed is never orthogonal to the normal
+    let mut normal = [inverse.x_axis, inverse.y_axis, inverse.z_axis]
+        .into_iter()
+        .max_by(|a, b| a.length_squared().total_cmp(&b.length_squared()))?
+        .try_normalize()?;
+    for _ in 0..10 {
+        normal = (inverse * normal).try_normalize()?;
+    }
+    Some(Plane::oriented(normal, -normal.dot(centroid)))
+}
+
+/// Small deterministic generator, so the planes don't flicker between runs
+/// on the same points
+struct XorShift(u64);
+
+impl Default for XorShift {
+    fn default() -> Self {
+        Self(0x9E37_79B9_7F4A_7C15)
+    }
+}
+
+impl XorShift {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noise of the points across their plane, uniform, in meters
+    const NOISE: f32 = 0.003;
+
+    /// In [-0.5, 0.5)
+    fn uniform(rng: &mut XorShift) -> f32 {
+        rng.below(1 << 20) as f32 / (1 << 20) as f32 - 0.5
+    }
+
+    /// Random points of a rectangle of a plane, spanned by two directions
+    /// from a corner, moved across it by the noise
+    fn rectangle(
+        rng: &mut XorShift,
+        count: usize,
+        normal: Vec3,
+        corner: Vec3,
+        sides: [Vec3; 2],
+    ) -> Vec<Vec3> {
+        (0..count)
+            .map(|_| {
+                let [u, v] = [(); 2].map(|_| uniform(rng) + 0.5);
+                corner + u * sides[0] + v * sides[1] + 2.0 * NOISE * uniform(rng) * normal
+            })
+            .collect()
+    }
+
+    /// The floor, below the camera as Y is down
+    fn floor(rng: &mut XorShift, count: usize) -> Vec<Vec3> {
+        let corner = Vec3::new(-1.0, 0.5, 0.5);
+        rectangle(rng, count, Vec3::Y, corner, [2.0 * Vec3::X, 2.5 * Vec3::Z])
+    }
+
+    /// A wall to the right, seen at 45 degrees. Its normal is orthogonal to
+    /// `Vec3::ONE`, the seed of the power iteration.
+    const WALL_NORMAL: Vec3 = Vec3::new(
+        -std::f32::consts::FRAC_1_SQRT_2,
+        std::f32::consts::FRAC_1_SQRT_2,
+        0.0,
+    );
+    const WALL_DISTANCE: f32 = 1.2;
+
+    fn wall(rng: &mut XorShift, count: usize) -> Vec<Vec3> {
+        let center = -WALL_DISTANCE * WALL_NORMAL;
+        let along = Vec3::new(1.0, 1.0, 0.0).normalize();
+        let corner = center - along + Vec3::Z;
+        rectangle(
+            rng,
+            count,
+            WALL_NORMAL,
+            corner,
+            [2.0 * along, 2.0 * Vec3::Z],
+        )
+    }
+
+    fn assert_plane(plane: &Plane, normal: Vec3, distance: f32) {
+        assert!(plane.angle_to(normal) < 0.5, "{plane:?}");
+        assert!((plane.distance - distance).abs() < 0.002, "{plane:?}");
+    }
+
+    #[test]
+    fn fits_noisy_planes() {
+        let mut rng = XorShift::default();
+        let wall = wall(&mut rng, 1000);
+        let plane = fit_plane(wall.iter().copied()).unwrap();
+        assert_plane(&plane, WALL_NORMAL, WALL_DISTANCE);
+
+        let floor = floor(&mut rng, 1000);
+        let plane = fit_plane(floor.iter().copied()).unwrap();
+        assert_plane(&plane, -Vec3::Y, 0.5);
+    }
+
+    #[test]
+    fn fits_exact_planes() {
+        // On the plane, so the covariance is singular
+        let center = -WALL_DISTANCE * WALL_NORMAL;
+        let points = [
+            Vec3::ZERO,
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::Z,
+            Vec3::new(1.0, 1.0, 1.0),
+        ]
+        .map(|offset| center + offset);
+        let plane = fit_plane(points.into_iter()).unwrap();
+        assert_plane(&plane, WALL_NORMAL, WALL_DISTANCE);
+        assert!(fit_plane(points[..2].iter().copied()).is_none());
+    }
+
+    #[test]
+    fn detects_planes_among_outliers() {
+        let mut rng = XorShift(42);
+        let mut points = floor(&mut rng, 3000);
+        points.extend(wall(&mut rng, 2000));
+        // Scattered around the scene
+        points.extend((0..500).map(|_| {
+            Vec3::new(
+                2.0 * uniform(&mut rng),
+                2.0 * uniform(&mut rng),
+                1.75 + 2.5 * uniform(&mut rng),
+            )
+        }));
+
+        let planes = PlaneDetector::default().detect(&points);
+        assert_eq!(planes.len(), 2, "{planes:?}");
+        let [floor, wall] = [&planes[0], &planes[1]];
+        assert_plane(floor, -Vec3::Y, 0.5);
+        assert_plane(wall, WALL_NORMAL, WALL_DISTANCE);
+
+        // Every point of the planes, and a few outliers close to them
+        let is_floor = |index: &usize| *index < 3000;
+        let is_wall = |index: &usize| (3000..5000).contains(index);
+        assert_eq!(
+            floor.inliers.iter().filter(|index| is_floor(index)).count(),
+            3000
+        );
+        assert_eq!(
+            wall.inliers.iter().filter(|index| is_wall(index)).count(),
+            2000
+        );
+        assert!(floor.inliers.len() < 3000 + 25, "{}", floor.inliers.len());
+        assert!(wall.inliers.len() < 2000 + 25, "{}", wall.inliers.len());
+        assert!(floor.inliers.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Uniform noise has a RMS of its half width over the root of three
+        let expected_rms = NOISE / 3.0f32.sqrt();
+        for plane in &planes {
+            assert!((plane.rms - expected_rms).abs() < 0.0005, "{}", plane.rms);
+        }
+    }
+
+    #[test]
+    fn too_few_points() {
+        let detector = PlaneDetector::default();
+        assert!(detector.detect(&[]).is_empty());
+        assert!(detector.detect(&[Vec3::ZERO, Vec3::X]).is_empty());
+
+        // Outliers only, none of their planes has enough of them
+        let mut rng = XorShift::default();
+        let points: Vec<Vec3> = (0..1000)
+            .map(|_| {
+                Vec3::new(
+                    uniform(&mut rng),
+                    uniform(&mut rng),
+                    uniform(&mut rng) + 1.0,
+                )
+            })
+            .collect();
+        let detector = PlaneDetector {
+            min_share: 0.2,
+            ..Default::default()
+        };
+        assert!(detector.detect(&points).is_empty());
+    }
+}
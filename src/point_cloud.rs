@@ -0,0 +1,512 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Depth deprojected to 3D points, and the files to export them.
+
+use crate::camera_model::{Extrinsics, Intrinsics};
+use crate::frame_snapshot::{jet_colormap, CompositeSnapshot, FrameSnapshot, SnapshotData};
+use anyhow::{anyhow, bail, Result};
+use glam::{Vec2, Vec3};
+use std::io::Write;
+use std::path::Path;
+
+/// Depth shown as the farthest color of the colormap, in meters
+const MAX_DEPTH: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PointCloudFormat {
+    #[default]
+    PlyBinary,
+    PlyAscii,
+    Pcd,
+    Xyz,
+}
+
+impl PointCloudFormat {
+    pub const ALL: [Self; 4] = [Self::PlyBinary, Self::PlyAscii, Self::Pcd, Self::Xyz];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PlyBinary => "PLY (binary)",
+            Self::PlyAscii => "PLY (ASCII)",
+            Self::Pcd => "PCD",
+            Self::Xyz => "XYZ",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::PlyBinary | Self::PlyAscii => "ply",
+            Self::Pcd => "pcd",
+            Self::Xyz => "xyz",
+        }
+    }
+}
+
+/// Stream the points take their color from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureSource {
+    #[default]
+    None,
+    Color,
+    Infrared,
+    /// Depth color mapped, as the viewer shows it
+    Depth,
+}
+
+impl TextureSource {
+    pub const ALL: [Self; 4] = [Self::None, Self::Color, Self::Infrared, Self::Depth];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Color => "Color",
+            Self::Infrared => "Infrared",
+            Self::Depth => "Depth",
+        }
+    }
+}
+
+/// Points in meters, in the frame of the depth camera
+#[derive(Clone, Debug, Default)]
+pub struct PointCloud {
+    pub points: Vec<Vec3>,
+    /// RGB of every point, when textured
+    pub colors: Option<Vec<[u8; 3]>>,
+}
+
+impl PointCloud {
+    /// Deprojects every pixel with a valid depth
+    pub fn from_depth(data: &[u16], intrinsics: &Intrinsics, depth_units: f32) -> Self {
+        let mut points = Vec::with_capacity(data.len());
+        for (index, depth) in data.iter().enumerate() {
+            if *depth == 0 {
+                continue;
+            }
+            let pixel = Vec2::new(
+                (index % intrinsics.width) as f32,
+                (index / intrinsics.width) as f32,
+            );
+            points.push(intrinsics.deproject(pixel, *depth as f32 * depth_units));
+        }
+        Self {
+            points,
+            colors: None,
+        }
+    }
+
+    pub fn from_snapshot(depth: &FrameSnapshot) -> Result<Self> {
+        let SnapshotData::Depth {
+            depth_units, data, ..
+        } = &depth.data
+        else {
+            bail!("{} is not a depth frame", depth.stream);
+        };
+        let intrinsics = depth
+            .intrinsics
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing intrinsics of {}", depth.stream))?;
+        Ok(Self::from_depth(data, intrinsics, *depth_units))
+    }
+
+    /// Points of the first depth frame, colored from another stream of the
+    /// same composite
+    pub fn from_composite(snapshot: &CompositeSnapshot, texture: TextureSource) -> Result<Self> {
+        let depth = snapshot
+            .frames
+            .iter()
+            .find(|frame| matches!(frame.data, SnapshotData::Depth { .. }))
+            .ok_or_else(|| anyhow!("There is no depth frame"))?;
+        let mut cloud = Self::from_snapshot(depth)?;
+        if texture == TextureSource::Depth {
+            cloud.colorize_depth();
+            return Ok(cloud);
+        }
+        let image = snapshot.frames.iter().find(|frame| match texture {
+            TextureSource::None | TextureSource::Depth => false,
+            TextureSource::Color => matches!(frame.data, SnapshotData::Color { .. }),
+            TextureSource::Infrared => matches!(
+                frame.data,
+                SnapshotData::Infrared { .. } | SnapshotData::Infrared16 { .. }
+            ),
+        });
+        match image {
+            Some(image) => {
+                let extrinsics = depth
+                    .extrinsics
+                    .iter()
+                    .find(|(stream, _)| *stream == image.stream)
+                    .map(|(_, extrinsics)| extrinsics)
+                    .ok_or_else(|| anyhow!("Missing extrinsics to {}", image.stream))?;
+                cloud.texture(image, extrinsics)?;
+            }
+            None if texture != TextureSource::None => {
+                bail!("There is no {} frame", texture.as_str().to_lowercase());
+            }
+            None => (),
+        }
+        Ok(cloud)
+    }
+
+    /// Colors the points with the pixels they project to. Points outside the
+    /// image are black.
+    pub fn texture(&mut self, image: &FrameSnapshot, extrinsics: &Extrinsics) -> Result<()> {
+        let intrinsics = image
+            .intrinsics
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing intrinsics of {}", image.stream))?;
+        let colors = self
+            .points
+            .iter()
+            .map(|point| {
+                let point = extrinsics.transform(*point);
+                let pixel = intrinsics.project(point).round();
+                if point.z <= 0.0 || pixel.x < 0.0 || pixel.y < 0.0 {
+                    return [0, 0, 0];
+                }
+                pixel_rgb(image, pixel.x as usize, pixel.y as usize).unwrap_or([0, 0, 0])
+            })
+            .collect();
+        self.colors = Some(colors);
+        Ok(())
+    }
+
+    /// Colors the points by their distance to the camera
+    pub fn colorize_depth(&mut self) {
+        let colors = self
+            .points
+            .iter()
+            .map(|point| jet_colormap(point.z / MAX_DEPTH).0)
+            .collect();
+        self.colors = Some(colors);
+    }
+
+    /// Keeps the points passing the test
+    pub fn crop(&self, keep: impl Fn(Vec3) -> bool) -> Self {
+        let kept: Vec<bool> = self.points.iter().map(|point| keep(*point)).collect();
+        let points = self
+            .points
+            .iter()
+            .zip(&kept)
+            .filter(|(_, kept)| **kept)
+            .map(|(point, _)| *point)
+            .collect();
+        let colors = self.colors.as_ref().map(|colors| {
+            colors
+                .iter()
+                .zip(&kept)
+                .filter(|(_, kept)| **kept)
+                .map(|(color, _)| *color)
+                .collect()
+        });
+        Self { points, colors }
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    pub fn write(&self, writer: &mut impl Write, format: PointCloudFormat) -> std::io::Result<()> {
+        match format {
+            PointCloudFormat::PlyBinary => self.write_ply(writer, true),
+            PointCloudFormat::PlyAscii => self.write_ply(writer, false),
+            PointCloudFormat::Pcd => self.write_pcd(writer),
+            PointCloudFormat::Xyz => self.write_xyz(writer),
+        }
+    }
+
+    /// Writes the file, adding the extension of the format
+    pub fn save(&self, path: &Path, format: PointCloudFormat) -> Result<std::path::PathBuf> {
+        let path = path.with_extension(format.extension());
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        self.write(&mut writer, format)?;
+        writer.flush()?;
+        Ok(path)
+    }
+
+    fn write_ply(&self, writer: &mut impl Write, binary: bool) -> std::io::Result<()> {
+        writeln!(writer, "ply")?;
+        if binary {
+            writeln!(writer, "format binary_little_endian 1.0")?;
+        } else {
+            writeln!(writer, "format ascii 1.0")?;
+        }
+        writeln!(writer, "comment Written by realsense-tools-rs")?;
+        writeln!(writer, "element vertex {}", self.len())?;
+        writeln!(writer, "property float x")?;
+        writeln!(writer, "property float y")?;
+        writeln!(writer, "property float z")?;
+        if self.colors.is_some() {
+            writeln!(writer, "property uchar red")?;
+            writeln!(writer, "property uchar green")?;
+            writeln!(writer, "property uchar blue")?;
+        }
+        writeln!(writer, "end_header")?;
+        for (index, point) in self.points.iter().enumerate() {
+            let color = self.colors.as_ref().map(|colors| colors[index]);
+            if binary {
+                for value in point.to_array() {
+                    writer.write_all(&value.to_le_bytes())?;
+                }
+                if let Some(color) = color {
+                    writer.write_all(&color)?;
+                }
+            } else {
+                write!(writer, "{} {} {}", point.x, point.y, point.z)?;
+                if let Some([r, g, b]) = color {
+                    write!(writer, " {r} {g} {b}")?;
+                }
+                writeln!(writer)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// ASCII PCD, as read by the Point Cloud Library. Colors are packed in a
+    /// single field.
+    fn write_pcd(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        let colored = self.colors.is_some();
+        writeln!(writer, "# .PCD v0.7 - Point Cloud Data file format")?;
+        writeln!(writer, "VERSION 0.7")?;
+        if colored {
+            writeln!(writer, "FIELDS x y z rgb")?;
+            writeln!(writer, "SIZE 4 4 4 4")?;
+            writeln!(writer, "TYPE F F F U")?;
+            writeln!(writer, "COUNT 1 1 1 1")?;
+        } else {
+            writeln!(writer, "FIELDS x y z")?;
+            writeln!(writer, "SIZE 4 4 4")?;
+            writeln!(writer, "TYPE F F F")?;
+            writeln!(writer, "COUNT 1 1 1")?;
+        }
+        writeln!(writer, "WIDTH {}", self.len())?;
+        writeln!(writer, "HEIGHT 1")?;
+        writeln!(writer, "VIEWPOINT 0 0 0 1 0 0 0")?;
+        writeln!(writer, "POINTS {}", self.len())?;
+        writeln!(writer, "DATA ascii")?;
+        for (index, point) in self.points.iter().enumerate() {
+            write!(writer, "{} {} {}", point.x, point.y, point.z)?;
+            if let Some(colors) = &self.colors {
+                let [r, g, b] = colors[index];
+                let rgb = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+                write!(writer, " {rgb}")?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    /// One point per line, followed by its color if any
+    fn write_xyz(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        for (index, point) in self.points.iter().enumerate() {
+            write!(writer, "{} {} {}", point.x, point.y, point.z)?;
+            if let Some(colors) = &self.colors {
+                let [r, g, b] = colors[index];
+                write!(writer, " {r} {g} {b}")?;
+            }
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Color of an image pixel, gray for infrared
+fn pixel_rgb(image: &FrameSnapshot, x: usize, y: usize) -> Option<[u8; 3]> {
+    let (width, height) = image.image_size()?;
+    if x >= width || y >= height {
+        return None;
+    }
+    let index = y * width + x;
+    match &image.data {
+        SnapshotData::Color { data, .. } => {
+            let rgb = data.get(index * 3..index * 3 + 3)?;
+            Some([rgb[0], rgb[1], rgb[2]])
+        }
+        SnapshotData::Infrared { data, .. } => Some([data[index]; 3]),
+        SnapshotData::Infrared16 { data, .. } => Some([(data[index] >> 8) as u8; 3]),
+        SnapshotData::Depth { .. } | SnapshotData::Gyro(_) | SnapshotData::Accel(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cloud(colored: bool) -> PointCloud {
+        PointCloud {
+            points: vec![
+                Vec3::new(0.1, -0.2, 0.5),
+                Vec3::new(-1.25, 0.0, 3.0),
+                Vec3::new(0.3333, 0.75, 1.125),
+            ],
+            colors: colored.then(|| vec![[255, 0, 16], [1, 2, 3], [128, 64, 200]]),
+        }
+    }
+
+    /// Lines of the header, up to and including `end`, and the bytes after
+    fn split_header<'a>(bytes: &'a [u8], end: &str) -> (Vec<String>, &'a [u8]) {
+        let mut lines = Vec::new();
+        let mut rest = bytes;
+        loop {
+            let length = rest.iter().position(|byte| *byte == b'\n').unwrap();
+            let line = std::str::from_utf8(&rest[..length]).unwrap().to_string();
+            rest = &rest[length + 1..];
+            let done = line.starts_with(end);
+            lines.push(line);
+            if done {
+                return (lines, rest);
+            }
+        }
+    }
+
+    /// Points and colors of lines of numbers
+    fn parse_rows(text: &str, colors: usize) -> (Vec<Vec3>, Vec<Vec<u32>>) {
+        text.lines()
+            .map(|line| {
+                let values: Vec<&str> = line.split(' ').collect();
+                assert_eq!(values.len(), 3 + colors, "{line}");
+                let point: Vec<f32> = values[..3].iter().map(|v| v.parse().unwrap()).collect();
+                let color = values[3..].iter().map(|v| v.parse().unwrap()).collect();
+                (Vec3::from_slice(&point), color)
+            })
+            .unzip()
+    }
+
+    fn parse_ply(bytes: &[u8]) -> PointCloud {
+        let (header, body) = split_header(bytes, "end_header");
+        assert_eq!(header[0], "ply");
+        let binary = header[1] == "format binary_little_endian 1.0";
+        let count: usize = header
+            .iter()
+            .find_map(|line| line.strip_prefix("element vertex "))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let colored = header.iter().any(|line| line == "property uchar red");
+        if binary {
+            let stride = 12 + if colored { 3 } else { 0 };
+            assert_eq!(body.len(), count * stride);
+            let points = body
+                .chunks_exact(stride)
+                .map(|vertex| {
+                    let value =
+                        |i: usize| f32::from_le_bytes(vertex[i * 4..i * 4 + 4].try_into().unwrap());
+                    Vec3::new(value(0), value(1), value(2))
+                })
+                .collect();
+            let colors = colored.then(|| {
+                body.chunks_exact(stride)
+                    .map(|vertex| [vertex[12], vertex[13], vertex[14]])
+                    .collect()
+            });
+            PointCloud { points, colors }
+        } else {
+            let (points, colors) = parse_rows(
+                std::str::from_utf8(body).unwrap(),
+                if colored { 3 } else { 0 },
+            );
+            assert_eq!(points.len(), count);
+            let colors = colored.then(|| {
+                colors
+                    .iter()
+                    .map(|c| [c[0] as u8, c[1] as u8, c[2] as u8])
+                    .collect()
+            });
+            PointCloud { points, colors }
+        }
+    }
+
+    fn parse_pcd(bytes: &[u8]) -> PointCloud {
+        let (header, body) = split_header(bytes, "DATA");
+        assert_eq!(header.last().unwrap(), "DATA ascii");
+        let field = |name: &str| {
+            header
+                .iter()
+                .find_map(|line| line.strip_prefix(name))
+                .unwrap()
+                .to_string()
+        };
+        let colored = field("FIELDS ") == "x y z rgb";
+        let count: usize = field("POINTS ").parse().unwrap();
+        assert_eq!(field("WIDTH ").parse::<usize>().unwrap(), count);
+        let (points, colors) = parse_rows(
+            std::str::from_utf8(body).unwrap(),
+            if colored { 1 } else { 0 },
+        );
+        assert_eq!(points.len(), count);
+        let colors = colored.then(|| {
+            colors
+                .iter()
+                .map(|c| [(c[0] >> 16) as u8, (c[0] >> 8) as u8, c[0] as u8])
+                .collect()
+        });
+        PointCloud { points, colors }
+    }
+
+    fn parse_xyz(bytes: &[u8], colored: bool) -> PointCloud {
+        let (points, colors) = parse_rows(
+            std::str::from_utf8(bytes).unwrap(),
+            if colored { 3 } else { 0 },
+        );
+        let colors = colored.then(|| {
+            colors
+                .iter()
+                .map(|c| [c[0] as u8, c[1] as u8, c[2] as u8])
+                .collect()
+        });
+        PointCloud { points, colors }
+    }
+
+    #[test]
+    fn writers_round_trip() {
+        for format in PointCloudFormat::ALL {
+            for colored in [false, true] {
+                let cloud = cloud(colored);
+                let mut bytes = Vec::new();
+                cloud.write(&mut bytes, format).unwrap();
+                let parsed = match format {
+                    PointCloudFormat::PlyBinary | PointCloudFormat::PlyAscii => parse_ply(&bytes),
+                    PointCloudFormat::Pcd => parse_pcd(&bytes),
+                    PointCloudFormat::Xyz => parse_xyz(&bytes, colored),
+                };
+                // Floats are written in their shortest exact form
+                assert_eq!(parsed.points, cloud.points, "{format:?}");
+                assert_eq!(parsed.colors, cloud.colors, "{format:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn empty_cloud() {
+        let cloud = PointCloud::default();
+        for format in PointCloudFormat::ALL {
+            let mut bytes = Vec::new();
+            cloud.write(&mut bytes, format).unwrap();
+            let parsed = match format {
+                PointCloudFormat::PlyBinary | PointCloudFormat::PlyAscii => parse_ply(&bytes),
+                PointCloudFormat::Pcd => parse_pcd(&bytes),
+                PointCloudFormat::Xyz => parse_xyz(&bytes, false),
+            };
+            assert!(parsed.is_empty());
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Visual presets and validation of the advanced mode JSON presets.
+
+use anyhow::{bail, ensure, Context, Result};
+use serde_json::{Map, Value};
+
+/// Values of the visual preset option in D400 devices
+pub const VISUAL_PRESETS: [(f32, &str); 6] = [
+    (0.0, "Custom"),
+    (1.0, "Default"),
+    (2.0, "Hand"),
+    (3.0, "High Accuracy"),
+    (4.0, "High Density"),
+    (5.0, "Medium Density"),
+];
+
+/// Gets the name of a visual preset value
+pub fn visual_preset_name(value: f32) -> &'static str {
+    VISUAL_PRESETS
+        .iter()
+        .find(|(preset, _)| *preset == value)
+        .map(|(_, name)| *name)
+        .unwrap_or("Unknown")
+}
+
+/// Visual presets offered by a device, from the range of the option
+pub fn visual_presets_in_range(min: f32, max: f32, step: f32) -> Vec<f32> {
+    if max < min {
+        return Vec::new();
+    }
+    let step = step.max(1.0);
+    let count = ((max - min) / step).round() as usize;
+    (0..=count).map(|i| min + i as f32 * step).collect()
+}
+
+/// Parameter families of the advanced mode
+const PARAMETER_PREFIXES: [&str; 4] = ["param-", "aux-param-", "controls-", "stream-"];
+
+/// Parameters with textual values, the rest must be numbers
+const TEXT_PARAMETERS: [&str; 2] = ["stream-depth-format", "stream-ir-format"];
+
+/// Checks a preset against the schema of the files written by the Intel
+/// RealSense Viewer and returns its number of parameters.
+///
+/// Both layouts are accepted: the current one, with `device`, `parameters`
+/// and `schema version` keys, and the legacy flat one, with the parameters
+/// at the top level. Values may be numbers, booleans or strings holding
+/// them, as the Intel tools quote every value.
+pub fn validate_preset(json: &str) -> Result<usize> {
+    let root: Value = serde_json::from_str(json).context("Preset is not valid JSON")?;
+    let root = root.as_object().context("Preset must be a JSON object")?;
+
+    let parameters = if let Some(parameters) = root.get("parameters") {
+        for key in root.keys() {
+            ensure!(
+                ["device", "parameters", "schema version"].contains(&key.as_str()),
+                "Unexpected key '{key}'"
+            );
+        }
+        if let Some(version) = root.get("schema version") {
+            ensure!(version.is_u64(), "'schema version' must be an integer");
+        }
+        if let Some(device) = root.get("device") {
+            let device = device.as_object().context("'device' must be an object")?;
+            for (key, value) in device {
+                ensure!(value.is_string(), "Device field '{key}' must be a string");
+            }
+        }
+        parameters
+            .as_object()
+            .context("'parameters' must be an object")?
+    } else {
+        root
+    };
+
+    ensure!(!parameters.is_empty(), "Preset has no parameters");
+    validate_parameters(parameters)?;
+    Ok(parameters.len())
+}
+
+fn validate_parameters(parameters: &Map<String, Value>) -> Result<()> {
+    for (key, value) in parameters {
+        // Legacy files carry this flag without prefix
+        let known = key == "ignoreSAD"
+            || PARAMETER_PREFIXES
+                .iter()
+                .any(|prefix| key.starts_with(prefix));
+        ensure!(known, "Unknown parameter '{key}'");
+
+        if TEXT_PARAMETERS.contains(&key.as_str()) {
+            ensure!(value.is_string(), "Parameter '{key}' must be a string");
+            continue;
+        }
+        match value {
+            Value::Number(_) | Value::Bool(_) => (),
+            Value::String(text) => {
+                let is_bool = ["true", "false"].contains(&text.to_lowercase().as_str());
+                ensure!(
+                    is_bool || text.trim().parse::<f64>().is_ok(),
+                    "Parameter '{key}' has a non numeric value '{text}'"
+                );
+            }
+            _ => bail!("Parameter '{key}' must be a number, a boolean or a string"),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_layout() {
+        let json = r#"{
+            "device": {"fw version": "5.13.0.50", "name": "Intel RealSense D435"},
+            "parameters": {
+                "aux-param-autoexposure-setpoint": "1536",
+                "controls-autoexposure-auto": "True",
+                "param-disparityshift": 0,
+                "stream-depth-format": "Z16"
+            },
+            "schema version": 1
+        }"#;
+        assert_eq!(validate_preset(json).unwrap(), 4);
+    }
+
+    #[test]
+    fn legacy_layout() {
+        let json = r#"{"ignoreSAD": "0", "param-censususize": "9", "stream-width": 848}"#;
+        assert_eq!(validate_preset(json).unwrap(), 3);
+    }
+
+    #[test]
+    fn invalid_presets() {
+        let invalid = [
+            ("not json", "not valid JSON"),
+            ("[1, 2]", "must be a JSON object"),
+            ("{}", "no parameters"),
+            (r#"{"parameters": {}}"#, "no parameters"),
+            (r#"{"parameters": 3}"#, "'parameters' must be an object"),
+            (
+                r#"{"parameters": {"param-a": 1}, "extra": 1}"#,
+                "Unexpected key 'extra'",
+            ),
+            (
+                r#"{"parameters": {"param-a": 1}, "schema version": "1"}"#,
+                "must be an integer",
+            ),
+            (
+                r#"{"parameters": {"param-a": 1}, "device": 1}"#,
+                "must be an object",
+            ),
+            (
+                r#"{"device": {"name": 1}, "parameters": {"param-a": 1}}"#,
+                "must be a string",
+            ),
+            (r#"{"laser-power": 150}"#, "Unknown parameter 'laser-power'"),
+            (r#"{"param-a": "high"}"#, "non numeric value"),
+            (r#"{"param-a": [1]}"#, "must be a number"),
+            (r#"{"stream-ir-format": 8}"#, "must be a string"),
+        ];
+        for (json, message) in invalid {
+            let error = validate_preset(json).unwrap_err();
+            assert!(format!("{error:#}").contains(message), "{json}: {error:#}");
+        }
+    }
+
+    #[test]
+    fn preset_names() {
+        assert_eq!(visual_preset_name(3.0), "High Accuracy");
+        assert_eq!(visual_preset_name(42.0), "Unknown");
+    }
+
+    #[test]
+    fn presets_in_range() {
+        assert_eq!(
+            visual_presets_in_range(0.0, 5.0, 1.0),
+            [0.0, 1.0, 2.0, 3.0, 4.0, 5.0]
+        );
+        assert_eq!(visual_presets_in_range(0.0, 2.0, 0.0), [0.0, 1.0, 2.0]);
+        assert!(visual_presets_in_range(1.0, 0.0, 1.0).is_empty());
+    }
+}
@@ -16,31 +16,64 @@
 //
 // You can contact the author via carlospzlz@gmail.com
 
+use anyhow::Context;
 use eframe::egui;
 use eframe::glow;
 use eframe::glow::HasContext;
+use realsense_rust::frame::FrameEx;
+use realsense_rust::kind::Rs2StreamKind;
+use realsense_tools_rs::advanced_mode;
+use realsense_tools_rs::camera_model::Extrinsics;
+use realsense_tools_rs::clipping::{ClipBox, Clipping};
+use realsense_tools_rs::frame_snapshot::{CompositeSnapshot, FrameSnapshot};
+use realsense_tools_rs::gravity::{floor_height, GravityEstimator};
+use realsense_tools_rs::mesh::{Mesh, MeshFormat, DEFAULT_MAX_EDGE};
+use realsense_tools_rs::odometry::Odometry;
+use realsense_tools_rs::orbit_camera::{pick_point, OrbitCamera, ViewPreset};
+use realsense_tools_rs::planes::{Plane, PlaneDetector};
+use realsense_tools_rs::point_cloud::{PointCloud, PointCloudFormat, TextureSource};
+use realsense_tools_rs::tsdf::{TsdfVolume, DEFAULT_BOUNDS, DEFAULT_VOXEL_SIZE, MAX_VOXELS};
 use std::collections::HashSet;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 const VERTEX_SHADER_SRC: &str = r#"
     #version 330 core
     layout(location = 0) in vec3 position;
-    layout(location = 1) in vec2 instanceTranslation;
-    layout(location = 2) in float instanceDepth;
-    layout(location = 3) in vec4 instanceColor;
+    layout(location = 1) in vec3 instancePosition;
+    layout(location = 2) in vec4 instanceColor;
 
     uniform mat4 viewProjection;
+    // Size of a pixel at one meter, so cubes cover their pixel at any depth
+    uniform float cubeScale;
 
     out vec4 fragColor;
 
     void main() {
-        vec3 translation = vec3(instanceTranslation.x, instanceTranslation.y, instanceDepth);
-        vec3 worldPosition = position * vec3(1.0, 1.0, 1.0) + translation;
+        float distance = -instancePosition.z;
+        vec3 worldPosition = position * distance * cubeScale + instancePosition;
         gl_Position = viewProjection * vec4(worldPosition, 1.0);
         fragColor = instanceColor;
     }
 "#;
 
+/// Draws the positions as points, or as the vertices of the surface
+const POINT_VERTEX_SHADER_SRC: &str = r#"
+    #version 330 core
+    layout(location = 0) in vec3 position;
+    layout(location = 1) in vec4 color;
+
+    uniform mat4 viewProjection;
+    uniform float pointSize;
+
+    out vec4 fragColor;
+
+    void main() {
+        gl_Position = viewProjection * vec4(position, 1.0);
+        gl_PointSize = pointSize;
+        fragColor = color;
+    }
+"#;
+
 const FRAGMENT_SHADER_SRC: &str = r#"
     #version 330 core
     in vec4 fragColor;
@@ -50,11 +83,197 @@ const FRAGMENT_SHADER_SRC: &str = r#"
     }
 "#;
 
-const FRAME_SIZE: (usize, usize) = (640, 480);
+const DEFAULT_RESOLUTION: (usize, usize) = (640, 480);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum RenderMode {
+    Points,
+    /// A cube per pixel, as big as the pixel at its depth
+    #[default]
+    Cubes,
+    /// Triangles between neighbor pixels, without the long edges
+    Surface,
+    Wireframe,
+}
+
+impl RenderMode {
+    const ALL: [Self; 4] = [Self::Points, Self::Cubes, Self::Surface, Self::Wireframe];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Points => "Points",
+            Self::Cubes => "Cubes",
+            Self::Surface => "Surface",
+            Self::Wireframe => "Wireframe",
+        }
+    }
+
+    fn is_triangulated(&self) -> bool {
+        matches!(self, Self::Surface | Self::Wireframe)
+    }
+}
+
+/// GL buffer that only grows, to avoid reallocating it every frame
+struct DynamicBuffer {
+    buffer: glow::NativeBuffer,
+    capacity: usize,
+}
+
+impl DynamicBuffer {
+    fn new(gl: &glow::Context) -> Self {
+        Self {
+            buffer: unsafe { gl.create_buffer().unwrap() },
+            capacity: 0,
+        }
+    }
+
+    /// Uploads the data, reallocating the buffer if it does not fit
+    unsafe fn upload(&mut self, gl: &glow::Context, target: u32, bytes: &[u8]) {
+        gl.bind_buffer(target, Some(self.buffer));
+        if bytes.len() > self.capacity {
+            gl.buffer_data_u8_slice(target, bytes, glow::DYNAMIC_DRAW);
+            self.capacity = bytes.len();
+        } else {
+            gl.buffer_sub_data_u8_slice(target, 0, bytes);
+        }
+    }
+}
+
+const USAGE: &str = "Usage: realsense-3d-viewer [--auto-exposure] [--preset FILE.json] \
+                     [--resolution WIDTHxHEIGHT] [--color] [--emitter interleaved|on|off] \
+                     [--gyro] [--accel]";
+
+/// Flying speed, in meters per second
+const FLY_SPEED: f32 = 1.0;
+
+/// Distance from the cursor to pick a pivot, in pixels
+const PICK_RADIUS: f32 = 10.0;
+
+/// Half the size of the floor grid, and its spacing, in meters
+const GRID_EXTENT: f32 = 5.0;
+const GRID_SPACING: f32 = 0.5;
+
+/// Colors of the inliers of every plane
+const PLANE_COLORS: [[f32; 3]; 6] = [
+    [1.0, 0.2, 0.2],
+    [0.2, 1.0, 0.2],
+    [0.2, 0.4, 1.0],
+    [1.0, 1.0, 0.2],
+    [1.0, 0.2, 1.0],
+    [0.2, 1.0, 1.0],
+];
+
+/// Depth and infrared taken further apart are not paired, in milliseconds
+const MAX_PAIR_OFFSET: f64 = 10.0;
+
+/// Frames fused between extractions of the scan surface, which takes longer
+/// than fusing one
+const SCAN_MESH_INTERVAL: usize = 10;
+
+/// Reach of the scanned volume from the camera, in meters
+const MAX_SCAN_REACH: f32 = 5.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum EmitterMode {
+    /// Emitter on for depth and off for infrared, in alternate frames
+    #[default]
+    Interleaved,
+    On,
+    Off,
+}
+
+impl EmitterMode {
+    const ALL: [Self; 3] = [Self::Interleaved, Self::On, Self::Off];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Interleaved => "Interleaved",
+            Self::On => "On",
+            Self::Off => "Off",
+        }
+    }
+
+    fn from_arg(arg: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|mode| mode.as_str().eq_ignore_ascii_case(arg))
+    }
+}
+
+/// Command line options
+#[derive(Clone)]
+struct Args {
+    auto_exposure: bool,
+    preset: Option<String>,
+    /// Of both depth and infrared
+    resolution: (usize, usize),
+    /// Stream color too, to texture the points
+    color: bool,
+    emitter: EmitterMode,
+    /// Stream the gyro too, to seed the camera tracking
+    gyro: bool,
+    /// Stream the accelerometer too, to level the scene
+    accel: bool,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            auto_exposure: false,
+            preset: None,
+            resolution: DEFAULT_RESOLUTION,
+            color: false,
+            emitter: EmitterMode::default(),
+            gyro: false,
+            accel: false,
+        }
+    }
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--auto-exposure" => parsed.auto_exposure = true,
+                "--color" => parsed.color = true,
+                "--gyro" => parsed.gyro = true,
+                "--accel" => parsed.accel = true,
+                "--preset" => {
+                    parsed.preset = Some(args.next().ok_or("Missing value for --preset")?);
+                }
+                "--emitter" => {
+                    let value = args.next().ok_or("Missing value for --emitter")?;
+                    parsed.emitter = EmitterMode::from_arg(&value)
+                        .ok_or(format!("Invalid emitter mode '{value}'"))?;
+                }
+                "--resolution" => {
+                    let value = args.next().ok_or("Missing value for --resolution")?;
+                    parsed.resolution = parse_resolution(&value)
+                        .ok_or(format!("Invalid resolution '{value}', e.g. 848x480"))?;
+                }
+                _ => return Err(format!("Unknown argument '{arg}'")),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Parses "WIDTHxHEIGHT"
+fn parse_resolution(value: &str) -> Option<(usize, usize)> {
+    let (width, height) = value.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
 
 fn main() -> Result<(), eframe::Error> {
-    let args: Vec<String> = std::env::args().collect();
-    let enable_auto_exposure = args.len() > 1 && args[1] == "--auto-exposure";
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("{USAGE}");
+            std::process::exit(-1);
+        }
+    };
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([730.0, 550.0]),
@@ -67,39 +286,144 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Realsense 3D Viewer \u{1F980}",
         options,
-        Box::new(|cc| {
-            Ok(Box::new(MyApp::new(
-                cc,
-                realsense_ctx,
-                enable_auto_exposure,
-            )))
-        }),
+        Box::new(|cc| Ok(Box::new(MyApp::new(cc, realsense_ctx, args)))),
     )
 }
 
 struct MyApp {
-    pipeline: realsense_rust::pipeline::ActivePipeline,
-    program: glow::Program,
-    vao: glow::VertexArray,
-    instance_depth_vbo: glow::NativeBuffer,
-    instance_color_vbo: glow::NativeBuffer,
+    realsense_ctx: realsense_rust::context::Context,
+    /// Missing when it failed to start
+    pipeline: Option<realsense_rust::pipeline::ActivePipeline>,
+    /// Why the pipeline isn't running
+    pipeline_message: Option<String>,
+    args: Args,
+    /// Resolutions supported by both depth and infrared at 30 fps
+    resolutions: Vec<(usize, usize)>,
+    /// The device can alternate the emitter between frames
+    supports_interleaved: bool,
+    emitter_warning: Option<String>,
+    cube_program: glow::Program,
+    point_program: glow::Program,
+    /// Instanced cubes
+    cube_vao: glow::VertexArray,
+    /// Points and surface, sharing the positions and colors of the cubes
+    point_vao: glow::VertexArray,
+    position_vbo: DynamicBuffer,
+    color_vbo: DynamicBuffer,
+    triangle_ebo: DynamicBuffer,
+    /// Points in the buffers, one per pixel with depth
+    instance_count: usize,
+    /// Indices of the surface triangles
+    index_count: usize,
+    render_mode: RenderMode,
+    point_size: f32,
+    /// Inverse of the depth focal length
+    cube_scale: f32,
     depth_frame: Option<realsense_rust::frame::DepthFrame>,
     infrared_frame: Option<realsense_rust::frame::InfraredFrame>,
-    translation: glam::Vec3,
-    rotation: glam::Vec2,
+    /// Latest color, paired with whichever depth comes
+    color_frame: Option<realsense_rust::frame::ColorFrame>,
+    camera: OrbitCamera,
+    /// Positions drawn, to pick the pivot
+    points: Vec<glam::Vec3>,
+    /// Box around the points between the near and far depths, whether the
+    /// clip box crops them or not, to fit it to them
+    points_box: Option<ClipBox>,
+    grid_vao: glow::VertexArray,
+    grid_vertex_count: usize,
+    show_grid: bool,
+    show_gizmo: bool,
+    /// Height of the grid below the camera, in meters
+    floor_height: f32,
+    /// Last depth, infrared and color shown, to export them
+    snapshot: Option<CompositeSnapshot>,
+    point_cloud_format: PointCloudFormat,
+    texture: TextureSource,
+    mesh_format: MeshFormat,
+    /// Longest triangle edge in the surface and exported mesh, in meters
+    max_edge: f32,
+    export_message: Option<String>,
+    /// Fusing the frames in the volume
+    scanning: bool,
+    volume: TsdfVolume,
+    /// Surface of the volume, shown instead of the frames while there is one
+    scan_mesh: Option<Mesh>,
+    scan_frames: usize,
+    /// Corners of the volume, applied on reset
+    scan_bounds: (glam::Vec3, glam::Vec3),
+    voxel_size: f32,
+    /// Tracking the camera, to scan while moving it
+    tracking: bool,
+    odometry: Odometry,
+    tracking_message: Option<String>,
+    /// Rotation of the depth camera since the last frame tracked
+    gyro_rotation: glam::Quat,
+    gyro_timestamp: Option<f64>,
+    /// Missing without gyro
+    gyro_to_depth: Option<glam::Mat3>,
+    /// Line through the positions of the camera tracked
+    trajectory_vao: glow::VertexArray,
+    trajectory_vbo: DynamicBuffer,
+    trajectory_color_vbo: DynamicBuffer,
+    trajectory_len: usize,
+    gravity: GravityEstimator,
+    /// Missing without accelerometer
+    accel_to_depth: Option<glam::Mat3>,
+    /// Rotate the scene so the floor is horizontal
+    align_gravity: bool,
+    /// Move the grid to the floor found in the leveled points
+    detect_floor: bool,
+    /// Color the inliers of the dominant planes
+    detect_planes: bool,
+    plane_detector: PlaneDetector,
+    /// Planes of the points shown
+    planes: Vec<Plane>,
+    /// Points culled before the upload, in the frame of the points
+    clipping: Clipping,
+    /// Apply the clipping to the exports too
+    crop_exports: bool,
+    /// Edges of the clipping box
+    clip_box_vao: glow::VertexArray,
+    clip_box_vbo: DynamicBuffer,
+    clip_box_color_vbo: DynamicBuffer,
 }
 
 impl MyApp {
     fn new(
         cc: &eframe::CreationContext<'_>,
         realsense_ctx: realsense_rust::context::Context,
-        enable_auto_exposure: bool,
+        mut args: Args,
     ) -> Self {
         // Start pipeline
         let devices = realsense_ctx.query_devices(HashSet::new());
+        let Some(device) = find_realsense(devices) else {
+            eprintln!("No RealSense device found!");
+            std::process::exit(-1);
+        };
+        // The one the pipeline opens, not any other connected camera
+        let supports_interleaved = supports_emitter_on_off(&device);
+        let mut emitter_warning = None;
+        if args.emitter == EmitterMode::Interleaved && !supports_interleaved {
+            args.emitter = EmitterMode::On;
+            emitter_warning = Some("The device can't interleave the emitter".to_string());
+        }
+        let resolutions = supported_resolutions(&device);
         let pipeline = realsense_rust::pipeline::InactivePipeline::try_from(&realsense_ctx)
             .expect("Failed to create inactive pipeline from context");
-        let pipeline = start_pipeline(devices, pipeline, enable_auto_exposure);
+        // e.g. a resolution the device doesn't have, which can be changed in
+        // the Stream window
+        let (pipeline, pipeline_message) = match start_pipeline(&device, pipeline, &args) {
+            Ok(pipeline) => (Some(pipeline), None),
+            Err(e) => (None, Some(format!("Failed to start: {e:#}"))),
+        };
+        let profile = pipeline.as_ref().map(|p| p.profile());
+        let gyro_to_depth = profile.and_then(|p| motion_to_depth(p, Rs2StreamKind::Gyro));
+        let accel_to_depth = profile.and_then(|p| motion_to_depth(p, Rs2StreamKind::Accel));
+        let texture = if args.color {
+            TextureSource::Color
+        } else {
+            TextureSource::Infrared
+        };
 
         // Prepare GL
         let gl = cc
@@ -108,18 +432,19 @@ impl MyApp {
             .expect("You need to run eframe with the glow backend");
 
         // Set up shaders
-        let program = create_shader_program(gl);
+        let cube_program = create_shader_program(gl, VERTEX_SHADER_SRC);
+        let point_program = create_shader_program(gl, POINT_VERTEX_SHADER_SRC);
 
-        // Cube vertices (8 unique vertices for the cube)
+        // Unit cube vertices (8 unique vertices for the cube)
         let vertices: [f32; 24] = [
-            -0.005, -0.005, -0.005, // 0: Bottom-left-back
-            0.005, -0.005, -0.005, // 1: Bottom-right-back
-            0.005, 0.005, -0.005, // 2: Top-right-back
-            -0.005, 0.005, -0.005, // 3: Top-left-back
-            -0.005, -0.005, 0.005, // 4: Bottom-left-front
-            0.005, -0.005, 0.005, // 5: Bottom-right-front
-            0.005, 0.005, 0.005, // 6: Top-right-front
-            -0.005, 0.005, 0.005, // 7: Top-left-front
+            -0.5, -0.5, -0.5, // 0: Bottom-left-back
+            0.5, -0.5, -0.5, // 1: Bottom-right-back
+            0.5, 0.5, -0.5, // 2: Top-right-back
+            -0.5, 0.5, -0.5, // 3: Top-left-back
+            -0.5, -0.5, 0.5, // 4: Bottom-left-front
+            0.5, -0.5, 0.5, // 5: Bottom-right-front
+            0.5, 0.5, 0.5, // 6: Top-right-front
+            -0.5, 0.5, 0.5, // 7: Top-left-front
         ];
 
         // Define indices (referencing the 8 unique vertices)
@@ -132,18 +457,23 @@ impl MyApp {
             1, 2, 6, 6, 5, 1, // Right face
         ];
 
-        // VAO to store:
+        // Positions, in meters, and colors of the points. Sized with the
+        // first frame.
+        let position_vbo = DynamicBuffer::new(gl);
+        let color_vbo = DynamicBuffer::new(gl);
+        let triangle_ebo = DynamicBuffer::new(gl);
+
+        // Cubes VAO to store:
         // - position VBO
         // - indexes
-        // - instance translation VBO
-        // - instance depth VBO
+        // - instance position VBO
         // - instance color VBO
         // - vertex attrib pointers
-        let vao = unsafe { gl.create_vertex_array().unwrap() };
+        let cube_vao = unsafe { gl.create_vertex_array().unwrap() };
 
         // Unique cube
         unsafe {
-            gl.bind_vertex_array(Some(vao));
+            gl.bind_vertex_array(Some(cube_vao));
 
             // Prepare OpenGL buffers for vertex and index data
             let vertex_buffer = gl.create_buffer().unwrap();
@@ -153,7 +483,7 @@ impl MyApp {
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(vertex_buffer));
             gl.buffer_data_u8_slice(
                 glow::ARRAY_BUFFER,
-                &bytemuck::cast_slice(&vertices),
+                bytemuck::cast_slice(&vertices),
                 glow::STATIC_DRAW,
             );
 
@@ -161,145 +491,397 @@ impl MyApp {
             gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(index_buffer));
             gl.buffer_data_u8_slice(
                 glow::ELEMENT_ARRAY_BUFFER,
-                &bytemuck::cast_slice(&indices),
+                bytemuck::cast_slice(&indices),
                 glow::STATIC_DRAW,
             );
 
             // Set up vertex attribute for position
-            let position_location = gl.get_attrib_location(program, "position").unwrap() as u32;
-            gl.vertex_attrib_pointer_f32(
-                position_location,
-                3,
-                glow::FLOAT,
-                false,
-                3 * std::mem::size_of::<f32>() as i32,
-                0,
-            );
-            gl.enable_vertex_attrib_array(position_location);
-        }
+            let location = gl.get_attrib_location(cube_program, "position").unwrap();
+            set_attribute(gl, location, 3, 0);
 
-        let instance_number = FRAME_SIZE.0 * FRAME_SIZE.1;
+            // Important! position and color are per-instance, not per vertex
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(position_vbo.buffer));
+            let location = gl
+                .get_attrib_location(cube_program, "instancePosition")
+                .unwrap();
+            set_attribute(gl, location, 3, 1);
 
-        // Instance translations
-        let mut translation_data: Vec<f32> = vec![0.0; instance_number * 2];
-        let (half_width, half_height) = (FRAME_SIZE.0 as f32 / 2.0, FRAME_SIZE.1 as f32 / 2.0);
-        for row in 0..FRAME_SIZE.1 {
-            for col in 0..FRAME_SIZE.0 {
-                let base_index = (row * FRAME_SIZE.0 + col) * 2;
-                // First pixel in frame is top-left corner
-                translation_data[base_index] = (col as f32 - half_width) / 100.0;
-                translation_data[base_index + 1] =
-                    ((FRAME_SIZE.1 - row) as f32 - half_height) / 100.0;
-            }
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(color_vbo.buffer));
+            let location = gl
+                .get_attrib_location(cube_program, "instanceColor")
+                .unwrap();
+            set_attribute(gl, location, 4, 1);
+
+            gl.bind_vertex_array(None);
         }
-        let instance_translation_vbo = unsafe { gl.create_buffer().unwrap() };
+
+        // Points VAO, with the triangles indexes for the surface
+        let point_vao = unsafe { gl.create_vertex_array().unwrap() };
         unsafe {
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(instance_translation_vbo));
-            gl.buffer_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                &bytemuck::cast_slice(&translation_data),
-                glow::STATIC_DRAW,
-            );
+            gl.bind_vertex_array(Some(point_vao));
 
-            let location = gl
-                .get_attrib_location(program, "instanceTranslation")
-                .unwrap() as u32;
-            gl.vertex_attrib_pointer_f32(
-                location,
-                2,
-                glow::FLOAT,
-                false,
-                2 * std::mem::size_of::<f32>() as i32,
-                0,
-            );
-            gl.enable_vertex_attrib_array(location);
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(position_vbo.buffer));
+            let location = gl.get_attrib_location(point_program, "position").unwrap();
+            set_attribute(gl, location, 3, 0);
+
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(color_vbo.buffer));
+            let location = gl.get_attrib_location(point_program, "color").unwrap();
+            set_attribute(gl, location, 4, 0);
+
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(triangle_ebo.buffer));
 
-            // Important! translation is per-instance, not per vertex
-            gl.vertex_attrib_divisor(location, 1);
+            gl.bind_vertex_array(None);
         }
 
-        // Initialize instance depths
-        let depth_data: Vec<f32> = vec![1.0; instance_number];
-        let instance_depth_vbo = unsafe { gl.create_buffer().unwrap() };
+        // Floor grid lines, drawn with the points program
+        let (grid_positions, grid_colors) = grid_lines();
+        let grid_vertex_count = grid_positions.len() / 3;
+        let grid_vao = unsafe { gl.create_vertex_array().unwrap() };
         unsafe {
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(instance_depth_vbo));
-            gl.buffer_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                &bytemuck::cast_slice(&depth_data),
-                glow::DYNAMIC_DRAW,
-            );
+            gl.bind_vertex_array(Some(grid_vao));
 
-            let location = gl.get_attrib_location(program, "instanceDepth").unwrap() as u32;
-            gl.vertex_attrib_pointer_f32(
-                location,
-                1,
-                glow::FLOAT,
-                false,
-                std::mem::size_of::<f32>() as i32,
-                0,
-            );
-            gl.enable_vertex_attrib_array(location);
+            for (data, attribute, size) in
+                [(&grid_positions, "position", 3), (&grid_colors, "color", 4)]
+            {
+                let buffer = gl.create_buffer().unwrap();
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+                gl.buffer_data_u8_slice(
+                    glow::ARRAY_BUFFER,
+                    bytemuck::cast_slice(data),
+                    glow::STATIC_DRAW,
+                );
+                let location = gl.get_attrib_location(point_program, attribute).unwrap();
+                set_attribute(gl, location, size, 0);
+            }
 
-            gl.vertex_attrib_divisor(location, 1);
+            gl.bind_vertex_array(None);
         }
 
-        // Initialize instance colors
-        let color_data: Vec<f32> = vec![0.0; instance_number * 4];
-        let instance_color_vbo = unsafe { gl.create_buffer().unwrap() };
+        // Trajectory line, drawn with the points program
+        let trajectory_vbo = DynamicBuffer::new(gl);
+        let trajectory_color_vbo = DynamicBuffer::new(gl);
+        let trajectory_vao = unsafe { gl.create_vertex_array().unwrap() };
         unsafe {
-            gl.bind_buffer(glow::ARRAY_BUFFER, Some(instance_color_vbo));
-            gl.buffer_data_u8_slice(
-                glow::ARRAY_BUFFER,
-                &bytemuck::cast_slice(&color_data),
-                glow::DYNAMIC_DRAW,
-            );
+            gl.bind_vertex_array(Some(trajectory_vao));
 
-            let location = gl.get_attrib_location(program, "instanceColor").unwrap() as u32;
-            gl.vertex_attrib_pointer_f32(
-                location,
-                4,
-                glow::FLOAT,
-                false,
-                4 * std::mem::size_of::<f32>() as i32,
-                0,
-            );
-            gl.enable_vertex_attrib_array(location);
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(trajectory_vbo.buffer));
+            let location = gl.get_attrib_location(point_program, "position").unwrap();
+            set_attribute(gl, location, 3, 0);
 
-            gl.vertex_attrib_divisor(location, 1);
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(trajectory_color_vbo.buffer));
+            let location = gl.get_attrib_location(point_program, "color").unwrap();
+            set_attribute(gl, location, 4, 0);
+
+            gl.bind_vertex_array(None);
         }
 
-        // Unbind VAO
+        // Clipping box edges, drawn with the points program
+        let clip_box_vbo = DynamicBuffer::new(gl);
+        let clip_box_color_vbo = DynamicBuffer::new(gl);
+        let clip_box_vao = unsafe { gl.create_vertex_array().unwrap() };
         unsafe {
+            gl.bind_vertex_array(Some(clip_box_vao));
+
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(clip_box_vbo.buffer));
+            let location = gl.get_attrib_location(point_program, "position").unwrap();
+            set_attribute(gl, location, 3, 0);
+
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(clip_box_color_vbo.buffer));
+            let location = gl.get_attrib_location(point_program, "color").unwrap();
+            set_attribute(gl, location, 4, 0);
+
             gl.bind_vertex_array(None);
         }
 
         Self {
+            realsense_ctx,
             pipeline,
-            program,
-            vao,
-            instance_depth_vbo,
-            instance_color_vbo,
+            pipeline_message,
+            args,
+            resolutions,
+            supports_interleaved,
+            emitter_warning,
+            cube_program,
+            point_program,
+            cube_vao,
+            point_vao,
+            position_vbo,
+            color_vbo,
+            triangle_ebo,
+            instance_count: 0,
+            index_count: 0,
+            render_mode: RenderMode::default(),
+            point_size: 2.0,
+            cube_scale: 0.0,
             depth_frame: None,
             infrared_frame: None,
-            translation: glam::Vec3::new(0.0, 0.0, -15.0),
-            rotation: glam::Vec2::new(0.0, 0.0),
+            color_frame: None,
+            camera: OrbitCamera::default(),
+            points: Vec::new(),
+            points_box: None,
+            grid_vao,
+            grid_vertex_count,
+            show_grid: true,
+            show_gizmo: true,
+            floor_height: -1.0,
+            snapshot: None,
+            point_cloud_format: PointCloudFormat::default(),
+            texture,
+            mesh_format: MeshFormat::default(),
+            max_edge: DEFAULT_MAX_EDGE,
+            export_message: None,
+            scanning: false,
+            volume: TsdfVolume::default(),
+            scan_mesh: None,
+            scan_frames: 0,
+            scan_bounds: DEFAULT_BOUNDS,
+            voxel_size: DEFAULT_VOXEL_SIZE,
+            tracking: false,
+            odometry: Odometry::default(),
+            tracking_message: None,
+            gyro_rotation: glam::Quat::IDENTITY,
+            gyro_timestamp: None,
+            gyro_to_depth,
+            trajectory_vao,
+            trajectory_vbo,
+            trajectory_color_vbo,
+            trajectory_len: 0,
+            gravity: GravityEstimator::default(),
+            accel_to_depth,
+            align_gravity: true,
+            detect_floor: true,
+            detect_planes: false,
+            plane_detector: PlaneDetector::default(),
+            planes: Vec::new(),
+            clipping: Clipping::default(),
+            crop_exports: true,
+            clip_box_vao,
+            clip_box_vbo,
+            clip_box_color_vbo,
         }
     }
-}
 
-impl eframe::App for MyApp {
-    fn update(&mut self, egui_ctx: &egui::Context, frame: &mut eframe::Frame) {
-        // Get frames
-        let timeout = Duration::from_millis(100);
-        let frames = match self.pipeline.wait(Some(timeout)) {
-            Ok(frames) => Some(frames),
-            Err(e) => {
-                println!("{e}");
-                None
+    /// Uploads the points of a new frame, and the surface when drawn. While
+    /// there is a scan, its surface is uploaded instead. Only the points
+    /// inside the clipping are uploaded.
+    fn update_buffers(&mut self, gl: &glow::Context, snapshot: &CompositeSnapshot) {
+        let triangulate = self.render_mode.is_triangulated();
+        let mut frame_mesh;
+        let mesh = match &self.scan_mesh {
+            Some(mesh) => mesh,
+            None => {
+                frame_mesh = reconstruct(snapshot, self.texture, triangulate, self.max_edge);
+                if self.tracking {
+                    let pose = self.odometry.pose();
+                    for vertex in &mut frame_mesh.vertices {
+                        *vertex = pose.transform_point3(*vertex);
+                    }
+                }
+                &frame_mesh
             }
         };
+        let clipping = self.clipping;
+        let depth_range = Clipping {
+            clip_box: None,
+            ..clipping
+        };
+        self.points_box = ClipBox::around(
+            mesh.vertices
+                .iter()
+                .copied()
+                .filter(|vertex| depth_range.contains(*vertex)),
+        );
+        let mesh = &mesh.crop(|vertex| clipping.contains(vertex));
+        let (positions, mut colors) = get_buffers_data(mesh);
+        if self.detect_planes {
+            self.planes = self.plane_detector.detect(&mesh.vertices);
+            for (plane, color) in self.planes.iter().zip(PLANE_COLORS.iter().cycle()) {
+                for index in &plane.inliers {
+                    colors[index * 4..index * 4 + 3].copy_from_slice(color);
+                }
+            }
+        }
+        unsafe {
+            let bytes = bytemuck::cast_slice(&positions);
+            self.position_vbo.upload(gl, glow::ARRAY_BUFFER, bytes);
+            let bytes = bytemuck::cast_slice(&colors);
+            self.color_vbo.upload(gl, glow::ARRAY_BUFFER, bytes);
+            // Element buffers belong to the VAO
+            gl.bind_vertex_array(Some(self.point_vao));
+            let bytes = bytemuck::cast_slice(mesh.triangles.as_flattened());
+            self.triangle_ebo
+                .upload(gl, glow::ELEMENT_ARRAY_BUFFER, bytes);
+            gl.bind_vertex_array(None);
+        }
+        self.points = positions
+            .chunks_exact(3)
+            .map(glam::Vec3::from_slice)
+            .collect();
+        self.instance_count = mesh.vertices.len();
+        self.index_count = mesh.triangles.len() * 3;
+        self.cube_scale = snapshot.frames[0]
+            .intrinsics
+            .map_or(0.0, |intrinsics| 1.0 / intrinsics.fx);
+    }
+
+    /// Draws the points in the current render mode
+    unsafe fn draw(&self, gl: &glow::Context, view_projection: &glam::Mat4) {
+        let program = match self.render_mode {
+            RenderMode::Cubes => self.cube_program,
+            _ => self.point_program,
+        };
+        gl.use_program(Some(program));
+
+        // Apply view projection matrix
+        let uniform_location = gl.get_uniform_location(program, "viewProjection").unwrap();
+        gl.uniform_matrix_4_f32_slice(
+            Some(&uniform_location),
+            false,
+            view_projection.to_cols_array().as_slice(),
+        );
+
+        match self.render_mode {
+            RenderMode::Points => {
+                let location = gl.get_uniform_location(program, "pointSize").unwrap();
+                gl.uniform_1_f32(Some(&location), self.point_size);
+                gl.enable(glow::PROGRAM_POINT_SIZE);
+                gl.bind_vertex_array(Some(self.point_vao));
+                gl.draw_arrays(glow::POINTS, 0, self.instance_count as i32);
+                gl.disable(glow::PROGRAM_POINT_SIZE);
+            }
+            RenderMode::Cubes => {
+                let location = gl.get_uniform_location(program, "cubeScale").unwrap();
+                gl.uniform_1_f32(Some(&location), self.cube_scale);
+                gl.bind_vertex_array(Some(self.cube_vao));
+                gl.draw_elements_instanced(
+                    glow::TRIANGLES,
+                    36,
+                    glow::UNSIGNED_INT,
+                    0,
+                    self.instance_count as i32,
+                );
+            }
+            RenderMode::Surface | RenderMode::Wireframe => {
+                let location = gl.get_uniform_location(program, "pointSize").unwrap();
+                gl.uniform_1_f32(Some(&location), 1.0);
+                if self.render_mode == RenderMode::Wireframe {
+                    gl.polygon_mode(glow::FRONT_AND_BACK, glow::LINE);
+                }
+                gl.bind_vertex_array(Some(self.point_vao));
+                gl.draw_elements(
+                    glow::TRIANGLES,
+                    self.index_count as i32,
+                    glow::UNSIGNED_INT,
+                    0,
+                );
+                // egui draws filled triangles after us
+                gl.polygon_mode(glow::FRONT_AND_BACK, glow::FILL);
+            }
+        }
+        gl.bind_vertex_array(None);
+    }
 
-        if let Some(ref frames) = frames {
+    /// Draws the floor grid lines
+    unsafe fn draw_grid(&self, gl: &glow::Context, view_projection: &glam::Mat4) {
+        let floor = glam::Mat4::from_translation(glam::Vec3::Y * self.floor_height);
+        let matrix = *view_projection * floor;
+        self.draw_lines(
+            gl,
+            self.grid_vao,
+            glow::LINES,
+            self.grid_vertex_count,
+            &matrix,
+        );
+    }
+
+    /// Draws the trajectory of the camera tracked
+    unsafe fn draw_trajectory(&self, gl: &glow::Context, view_projection: &glam::Mat4) {
+        let (vao, count) = (self.trajectory_vao, self.trajectory_len);
+        self.draw_lines(gl, vao, glow::LINE_STRIP, count, view_projection);
+    }
+
+    /// Draws the edges of the clipping box, when there is one
+    unsafe fn draw_clip_box(&self, gl: &glow::Context, view_projection: &glam::Mat4) {
+        if self.clipping.clip_box.is_some() {
+            let vao = self.clip_box_vao;
+            self.draw_lines(gl, vao, glow::LINES, 24, view_projection);
+        }
+    }
+
+    /// Draws lines with the points program
+    unsafe fn draw_lines(
+        &self,
+        gl: &glow::Context,
+        vao: glow::VertexArray,
+        mode: u32,
+        count: usize,
+        matrix: &glam::Mat4,
+    ) {
+        gl.use_program(Some(self.point_program));
+        let location = gl
+            .get_uniform_location(self.point_program, "viewProjection")
+            .unwrap();
+        gl.uniform_matrix_4_f32_slice(Some(&location), false, &matrix.to_cols_array());
+        let location = gl
+            .get_uniform_location(self.point_program, "pointSize")
+            .unwrap();
+        gl.uniform_1_f32(Some(&location), 1.0);
+        gl.bind_vertex_array(Some(vao));
+        gl.draw_arrays(mode, 0, count as i32);
+        gl.bind_vertex_array(None);
+    }
+
+    /// Uploads the positions of the camera tracked
+    fn update_trajectory(&mut self, gl: &glow::Context) {
+        let positions: Vec<f32> = self
+            .odometry
+            .trajectory()
+            .iter()
+            .flat_map(|(_, pose)| [pose.translation.x, -pose.translation.y, -pose.translation.z])
+            .collect();
+        let colors = [1.0, 1.0, 0.0, 1.0].repeat(positions.len() / 3);
+        unsafe {
+            let bytes = bytemuck::cast_slice(&positions);
+            self.trajectory_vbo.upload(gl, glow::ARRAY_BUFFER, bytes);
+            let bytes = bytemuck::cast_slice(&colors);
+            self.trajectory_color_vbo
+                .upload(gl, glow::ARRAY_BUFFER, bytes);
+        }
+        self.trajectory_len = positions.len() / 3;
+    }
+
+    /// Uploads the edges of the clipping box
+    fn update_clip_box(&mut self, gl: &glow::Context) {
+        let Some(clip_box) = &self.clipping.clip_box else {
+            return;
+        };
+        let positions: Vec<f32> = clip_box
+            .edges()
+            .iter()
+            .flat_map(|(a, b)| [a.x, -a.y, -a.z, b.x, -b.y, -b.z])
+            .collect();
+        let colors = [0.0, 1.0, 1.0, 1.0].repeat(positions.len() / 3);
+        unsafe {
+            let bytes = bytemuck::cast_slice(&positions);
+            self.clip_box_vbo.upload(gl, glow::ARRAY_BUFFER, bytes);
+            let bytes = bytemuck::cast_slice(&colors);
+            self.clip_box_color_vbo
+                .upload(gl, glow::ARRAY_BUFFER, bytes);
+        }
+    }
+
+    /// Keeps the depth, infrared and color frames of a frameset to pair
+    /// them, and integrates its motion frames. Returns whether the emitter
+    /// metadata is missing.
+    fn receive_frames(&mut self, frames: &realsense_rust::frame::CompositeFrame) -> bool {
+        let depth_frames = frames.frames_of_type::<realsense_rust::frame::DepthFrame>();
+        let infrared_frames = frames.frames_of_type::<realsense_rust::frame::InfraredFrame>();
+        // Recordings and some firmwares don't tell the emitter state
+        let missing_metadata = self.args.emitter == EmitterMode::Interleaved
+            && depth_frames.first().is_some_and(|frame| {
+                !frame.supports_metadata(realsense_rust::kind::Rs2FrameMetadata::FrameEmitterMode)
+            });
+        if self.args.emitter == EmitterMode::Interleaved {
             // Get a pair of:
             //  - Depth frame with emitter on
             //  - IR1 frame with emitter off
@@ -307,17 +889,731 @@ impl eframe::App for MyApp {
             // previous two infrared with emitter 1?). However, in the
             // infrared, 1 gives the frames with no emitter's pattern.
             if self.depth_frame.is_none() {
-                let depth_frames = frames.frames_of_type::<realsense_rust::frame::DepthFrame>();
                 self.depth_frame = frame_of_type_with_emitter(depth_frames, 0);
             }
             if self.infrared_frame.is_none() {
-                let infrared_frames =
-                    frames.frames_of_type::<realsense_rust::frame::InfraredFrame>();
                 self.infrared_frame = frame_of_type_with_emitter(infrared_frames, 1);
             }
+        } else {
+            // Latest of each, paired by timestamp in update
+            if let Some(frame) = depth_frames.into_iter().next() {
+                self.depth_frame = Some(frame);
+            }
+            if let Some(frame) = infrared_frames.into_iter().next() {
+                self.infrared_frame = Some(frame);
+            }
+        }
+        for frame in frames.frames_of_type::<realsense_rust::frame::GyroFrame>() {
+            self.integrate_gyro(&frame);
+        }
+        if let Some(accel_to_depth) = self.accel_to_depth {
+            for frame in frames.frames_of_type::<realsense_rust::frame::AccelFrame>() {
+                let acceleration = glam::Vec3::from(*frame.acceleration());
+                self.gravity.update(accel_to_depth * acceleration);
+            }
+        }
+        let mut color_frames = frames.frames_of_type::<realsense_rust::frame::ColorFrame>();
+        if !color_frames.is_empty() {
+            self.color_frame = Some(color_frames.remove(0));
+        }
+        missing_metadata
+    }
+
+    /// Integrates the rotation measured by the gyro since the last frame
+    fn integrate_gyro(&mut self, frame: &realsense_rust::frame::GyroFrame) {
+        let timestamp = frame.timestamp();
+        if let (Some(previous), Some(gyro_to_depth)) = (self.gyro_timestamp, self.gyro_to_depth) {
+            let dt = ((timestamp - previous) / 1000.0) as f32;
+            let velocity = gyro_to_depth * glam::Vec3::from(*frame.rotational_velocity());
+            self.gyro_rotation *= glam::Quat::from_scaled_axis(velocity * dt);
+        }
+        self.gyro_timestamp = Some(timestamp);
+    }
+
+    /// Rotation of the scene leveling it with the floor, in OpenGL coordinates
+    fn leveling(&self) -> glam::Mat4 {
+        let Some(up) = self.up().filter(|_| self.align_gravity) else {
+            return glam::Mat4::IDENTITY;
+        };
+        let up = glam::Vec3::new(up.x, -up.y, -up.z);
+        glam::Mat4::from_quat(glam::Quat::from_rotation_arc(up, glam::Vec3::Y))
+    }
+
+    /// Up direction in the frame of the points, from the accelerometer
+    fn up(&self) -> Option<glam::Vec3> {
+        let up = self.gravity.up()?;
+        // While tracking, the scene is in the frame of the first camera
+        Some(if self.tracking {
+            self.odometry.pose().transform_vector3(up)
+        } else {
+            up
+        })
+    }
+
+    fn planes_window(&mut self, egui_ctx: &egui::Context) {
+        egui::Window::new("Planes")
+            .default_open(false)
+            .show(egui_ctx, |ui| {
+                if ui
+                    .checkbox(&mut self.detect_planes, "Detect planes")
+                    .changed()
+                {
+                    self.planes.clear();
+                }
+                let detector = &mut self.plane_detector;
+                ui.horizontal(|ui| {
+                    ui.add(max_edge_value(&mut detector.threshold));
+                    ui.label("Max distance");
+                });
+                ui.add(egui::Slider::new(&mut detector.max_planes, 1..=6).text("Max planes"));
+                let share = egui::Slider::new(&mut detector.min_share, 0.01..=0.5);
+                ui.add(share.text("Min share of points"));
+                if self.planes.is_empty() {
+                    return;
+                }
+                ui.separator();
+                let up = self.up();
+                egui::Grid::new("planes").striped(true).show(ui, |ui| {
+                    ui.label("");
+                    ui.label("Normal");
+                    ui.label("Distance");
+                    ui.label("Inliers");
+                    ui.label("RMS");
+                    ui.label("To camera")
+                        .on_hover_text("Angle between the normal and the optical axis");
+                    if up.is_some() {
+                        ui.label("Tilt")
+                            .on_hover_text("Angle between the normal and up");
+                    }
+                    ui.end_row();
+                    for (plane, [r, g, b]) in self.planes.iter().zip(PLANE_COLORS.iter().cycle()) {
+                        let color = egui::Rgba::from_rgb(*r, *g, *b);
+                        ui.colored_label(color, "⏺");
+                        let [x, y, z] = plane.normal.to_array();
+                        ui.label(format!("{x:.3}, {y:.3}, {z:.3}"));
+                        ui.label(format!("{:.3} m", plane.distance));
+                        ui.label(plane.inliers.len().to_string());
+                        ui.label(format!("{:.1} mm", plane.rms * 1000.0));
+                        ui.label(format!("{:.1}°", plane.angle_to(glam::Vec3::NEG_Z)));
+                        if let Some(up) = up {
+                            ui.label(format!("{:.1}°", plane.angle_to(up)));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    fn clipping_window(&mut self, egui_ctx: &egui::Context) {
+        egui::Window::new("Clipping")
+            .default_open(false)
+            .show(egui_ctx, |ui| {
+                let clipping = &mut self.clipping;
+                ui.horizontal(|ui| {
+                    let near = egui::DragValue::new(&mut clipping.near)
+                        .range(0.0..=clipping.far)
+                        .speed(0.01)
+                        .prefix("Near: ")
+                        .suffix(" m");
+                    ui.add(near);
+                    let far = egui::DragValue::new(&mut clipping.far)
+                        .range(clipping.near..=100.0)
+                        .speed(0.01)
+                        .prefix("Far: ")
+                        .suffix(" m");
+                    ui.add(far);
+                });
+                let mut enabled = clipping.clip_box.is_some();
+                ui.horizontal(|ui| {
+                    if ui.checkbox(&mut enabled, "Box").changed() {
+                        clipping.clip_box = enabled.then(ClipBox::default);
+                    }
+                    let fit = ui.add_enabled(enabled, egui::Button::new("Fit to points"));
+                    if fit.clicked() && self.points_box.is_some() {
+                        clipping.clip_box = self.points_box;
+                    }
+                });
+                if let Some(clip_box) = &mut clipping.clip_box {
+                    egui::Grid::new("clip_box").show(ui, |ui| {
+                        ui.label("Center");
+                        vec3_values(ui, &mut clip_box.center, 0.01, -100.0..=100.0, " m");
+                        ui.end_row();
+                        ui.label("Size");
+                        vec3_values(ui, &mut clip_box.size, 0.01, 0.0..=100.0, " m");
+                        ui.end_row();
+                        ui.label("Rotation");
+                        vec3_values(ui, &mut clip_box.angles, 1.0, -180.0..=180.0, "°");
+                        ui.end_row();
+                    });
+                    ui.label("In the frame of the camera, X right, Y down and Z forward");
+                }
+            });
+    }
+
+    /// Moves the grid to the floor, once the points are leveled
+    fn find_floor(&mut self) {
+        if !self.detect_floor || !self.align_gravity || self.gravity.up().is_none() {
+            return;
+        }
+        let leveling = self.leveling();
+        let heights = self
+            .points
+            .iter()
+            .map(|point| leveling.transform_point3(*point).y);
+        if let Some(height) = floor_height(heights) {
+            self.floor_height = height;
+        }
+    }
+
+    /// Tracks the camera with a new frame, seeded with the gyro. Returns the
+    /// new pose, or why the frame couldn't be tracked.
+    fn track(
+        &mut self,
+        gl: &glow::Context,
+        snapshot: &CompositeSnapshot,
+    ) -> anyhow::Result<glam::Affine3A> {
+        let rotation = self.gyro_to_depth.map(|_| self.gyro_rotation);
+        self.gyro_rotation = glam::Quat::IDENTITY;
+        let result = self.odometry.track_snapshot(&snapshot.frames[0], rotation);
+        self.tracking_message = result.as_ref().err().map(|e| e.to_string());
+        self.update_trajectory(gl);
+        result
+    }
+
+    /// Orbits, pans and zooms with the mouse, and flies with the keyboard
+    fn navigate(
+        &mut self,
+        egui_ctx: &egui::Context,
+        view_projection: &glam::Mat4,
+        leveling: &glam::Mat4,
+    ) {
+        let screen = egui_ctx.screen_rect();
+        let to_ndc = |pos: egui::Pos2| {
+            let pos = (pos - screen.min) / screen.size();
+            glam::Vec2::new(pos.x * 2.0 - 1.0, 1.0 - pos.y * 2.0)
+        };
+        let pointer_free = !egui_ctx.is_pointer_over_area();
+        let keyboard_free = !egui_ctx.wants_keyboard_input();
+        egui_ctx.input(|input| {
+            if pointer_free {
+                let delta = input.pointer.delta();
+                if let Some(pos) = input.pointer.latest_pos() {
+                    if input.pointer.primary_down() && delta != egui::Vec2::ZERO {
+                        self.camera.arcball(to_ndc(pos - delta), to_ndc(pos));
+                    }
+                    if input
+                        .pointer
+                        .button_double_clicked(egui::PointerButton::Primary)
+                    {
+                        let radius = PICK_RADIUS * 2.0 / screen.height();
+                        let scene = *view_projection * *leveling;
+                        let pivot = pick_point(&self.points, &scene, to_ndc(pos), radius);
+                        if let Some(pivot) = pivot {
+                            self.camera.set_pivot(leveling.transform_point3(pivot));
+                        }
+                    }
+                }
+                if input.pointer.secondary_down() || input.pointer.middle_down() {
+                    let delta = glam::Vec2::new(delta.x, delta.y);
+                    self.camera.pan(delta, screen.height());
+                }
+                let scroll = input.smooth_scroll_delta.y;
+                if scroll != 0.0 {
+                    self.camera.zoom((-scroll * 0.002).exp());
+                }
+            }
+            if keyboard_free {
+                let mut direction = glam::Vec3::ZERO;
+                for (key, axis) in [
+                    (egui::Key::W, glam::Vec3::NEG_Z),
+                    (egui::Key::S, glam::Vec3::Z),
+                    (egui::Key::A, glam::Vec3::NEG_X),
+                    (egui::Key::D, glam::Vec3::X),
+                    (egui::Key::Q, glam::Vec3::NEG_Y),
+                    (egui::Key::E, glam::Vec3::Y),
+                ] {
+                    if input.key_down(key) {
+                        direction += axis;
+                    }
+                }
+                let speed = if input.modifiers.shift {
+                    FLY_SPEED * 4.0
+                } else {
+                    FLY_SPEED
+                };
+                let dt = input.stable_dt.min(0.1);
+                self.camera.fly(direction * speed * dt);
+            }
+        });
+    }
+
+    /// Axes of the scene as seen from the camera, in a corner
+    fn draw_gizmo(&self, egui_ctx: &egui::Context) {
+        let layer = egui::LayerId::new(egui::Order::Foreground, egui::Id::new("gizmo"));
+        let painter = egui_ctx.layer_painter(layer);
+        let screen = egui_ctx.screen_rect();
+        let center = egui::pos2(screen.min.x + 50.0, screen.max.y - 50.0);
+        let inverse = self.camera.rotation.inverse();
+        for (axis, color, label) in [
+            (glam::Vec3::X, egui::Color32::RED, "X"),
+            (glam::Vec3::Y, egui::Color32::GREEN, "Y"),
+            (glam::Vec3::Z, egui::Color32::LIGHT_BLUE, "Z"),
+        ] {
+            let direction = inverse * axis;
+            let end = center + egui::vec2(direction.x, -direction.y) * 35.0;
+            painter.line_segment([center, end], egui::Stroke::new(2.0, color));
+            let font = egui::FontId::proportional(12.0);
+            painter.text(end, egui::Align2::CENTER_CENTER, label, font, color);
+        }
+    }
+
+    fn view_window(&mut self, egui_ctx: &egui::Context) {
+        egui::Window::new("View")
+            .default_open(false)
+            .show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    if ui.button("Reset").clicked() {
+                        self.camera = OrbitCamera {
+                            fov: self.camera.fov,
+                            near: self.camera.near,
+                            far: self.camera.far,
+                            ..OrbitCamera::default()
+                        };
+                    }
+                    for preset in ViewPreset::ALL {
+                        if ui.button(preset.as_str()).clicked() {
+                            self.camera.preset(preset);
+                        }
+                    }
+                });
+                ui.add(egui::Slider::new(&mut self.camera.fov, 20.0..=120.0).text("FOV"));
+                ui.horizontal(|ui| {
+                    let near = egui::DragValue::new(&mut self.camera.near)
+                        .range(0.001..=self.camera.far)
+                        .speed(0.001)
+                        .prefix("Near: ")
+                        .suffix(" m");
+                    ui.add(near);
+                    let far = egui::DragValue::new(&mut self.camera.far)
+                        .range(self.camera.near..=1000.0)
+                        .prefix("Far: ")
+                        .suffix(" m");
+                    ui.add(far);
+                });
+                ui.checkbox(&mut self.show_gizmo, "Axes");
+                let has_accel = self.accel_to_depth.is_some();
+                ui.add_enabled(
+                    has_accel,
+                    egui::Checkbox::new(&mut self.align_gravity, "Align with gravity"),
+                )
+                .on_disabled_hover_text("Start with --accel");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.show_grid, "Floor");
+                    let detecting = self.detect_floor && self.align_gravity && has_accel;
+                    let floor = egui::DragValue::new(&mut self.floor_height)
+                        .speed(0.01)
+                        .suffix(" m");
+                    ui.add_enabled(self.show_grid && !detecting, floor);
+                    ui.add_enabled(
+                        self.show_grid && self.align_gravity && has_accel,
+                        egui::Checkbox::new(&mut self.detect_floor, "Detect"),
+                    );
+                });
+                ui.label(
+                    "Drag to orbit, right drag to pan, double-click a point to orbit \
+                     around it, and WASD QE to fly",
+                );
+            });
+    }
+
+    /// Fuses a new frame in the volume, and extracts its surface every few
+    /// frames
+    fn integrate(&mut self, snapshot: &CompositeSnapshot) {
+        // Without tracking, the volume stays in the frame of the camera
+        let pose = if self.tracking {
+            self.odometry.pose()
+        } else {
+            glam::Affine3A::IDENTITY
+        };
+        let integrated = texture_fallbacks(&self.texture).iter().any(|texture| {
+            self.volume
+                .integrate_composite(snapshot, *texture, &pose)
+                .is_ok()
+        });
+        if integrated {
+            self.scan_frames += 1;
+            if self.scan_frames % SCAN_MESH_INTERVAL == 1 {
+                self.scan_mesh = Some(self.volume.extract_mesh());
+            }
+        }
+    }
+
+    /// Empties the volume, with the current bounds and voxel size, and
+    /// tracks the camera from its current position
+    fn reset_scan(&mut self) {
+        let (min, max) = self.scan_bounds;
+        self.volume = TsdfVolume::new(min, max, self.voxel_size);
+        self.scan_mesh = None;
+        self.scan_frames = 0;
+        self.odometry.reset();
+        self.tracking_message = None;
+        self.trajectory_len = 0;
+    }
+
+    fn scan_window(&mut self, egui_ctx: &egui::Context) {
+        egui::Window::new("Scan")
+            .default_open(false)
+            .show(egui_ctx, |ui| {
+                ui.horizontal(|ui| {
+                    let label = if self.scanning {
+                        "⏹ Stop"
+                    } else {
+                        "⏺ Scan"
+                    };
+                    if ui.button(label).clicked() {
+                        self.scanning = !self.scanning;
+                        // With the frames fused since the last extraction
+                        if !self.scanning && self.scan_frames > 0 {
+                            self.scan_mesh = Some(self.volume.extract_mesh());
+                        }
+                    }
+                    if ui.button("Reset").clicked() {
+                        self.reset_scan();
+                    }
+                });
+                let mut changed = false;
+                ui.horizontal(|ui| {
+                    let voxel_size = egui::DragValue::new(&mut self.voxel_size)
+                        .range(0.005..=0.05)
+                        .speed(0.001)
+                        .suffix(" m");
+                    changed |= ui.add(voxel_size).changed();
+                    ui.label("Voxel size");
+                });
+                let (min, max) = &mut self.scan_bounds;
+                for (corner, label) in [(min, "Min"), (max, "Max")] {
+                    ui.horizontal(|ui| {
+                        let ranges = [
+                            -MAX_SCAN_REACH..=MAX_SCAN_REACH,
+                            -MAX_SCAN_REACH..=MAX_SCAN_REACH,
+                            0.0..=MAX_SCAN_REACH,
+                        ];
+                        for (value, range) in [&mut corner.x, &mut corner.y, &mut corner.z]
+                            .into_iter()
+                            .zip(ranges)
+                        {
+                            let value = egui::DragValue::new(value)
+                                .range(range)
+                                .speed(0.01)
+                                .suffix(" m");
+                            changed |= ui.add(value).changed();
+                        }
+                        ui.label(label);
+                    });
+                }
+                if changed {
+                    self.reset_scan();
+                }
+                let [x, y, z] = self.volume.dims();
+                ui.label(format!("{x}x{y}x{z} voxels, {} frames", self.scan_frames));
+                if self.volume.voxel_size > self.voxel_size {
+                    ui.colored_label(
+                        egui::Color32::YELLOW,
+                        format!(
+                            "Voxels of {:.3} m, to fit {} voxels",
+                            self.volume.voxel_size, MAX_VOXELS
+                        ),
+                    );
+                }
+                ui.separator();
+                let tracking = ui.checkbox(&mut self.tracking, "Track camera");
+                let hint = if self.gyro_to_depth.is_some() {
+                    "Seeded with the gyro"
+                } else {
+                    "Start with --gyro to seed it with the gyro"
+                };
+                if tracking.on_hover_text(hint).changed() {
+                    self.reset_scan();
+                }
+                if let Some(msg) = &self.tracking_message {
+                    ui.colored_label(egui::Color32::YELLOW, msg);
+                }
+            });
+    }
+
+    /// Starts the pipeline again with the current settings
+    fn restart_pipeline(&mut self) {
+        // Starting consumes the pipeline, even when it fails
+        let pipeline = match self.pipeline.take() {
+            Some(pipeline) => pipeline.stop(),
+            None => realsense_rust::pipeline::InactivePipeline::try_from(&self.realsense_ctx)
+                .expect("Failed to create inactive pipeline from context"),
+        };
+        let devices = self.realsense_ctx.query_devices(HashSet::new());
+        let result = match find_realsense(devices) {
+            Some(device) => {
+                self.supports_interleaved = supports_emitter_on_off(&device);
+                start_pipeline(&device, pipeline, &self.args)
+            }
+            None => Err(anyhow::anyhow!("No RealSense device found")),
+        };
+        match result {
+            Ok(pipeline) => {
+                self.pipeline = Some(pipeline);
+                self.pipeline_message = None;
+            }
+            Err(e) => self.pipeline_message = Some(format!("Failed to start: {e:#}")),
         }
+        self.depth_frame = None;
+        self.infrared_frame = None;
+        self.color_frame = None;
+        self.gyro_timestamp = None;
+        let profile = self.pipeline.as_ref().map(|p| p.profile());
+        self.gyro_to_depth = profile.and_then(|p| motion_to_depth(p, Rs2StreamKind::Gyro));
+        self.accel_to_depth = profile.and_then(|p| motion_to_depth(p, Rs2StreamKind::Accel));
+        self.gravity.reset();
+    }
+
+    fn render_window(&mut self, egui_ctx: &egui::Context) {
+        egui::Window::new("Render")
+            .default_open(false)
+            .show(egui_ctx, |ui| {
+                egui::ComboBox::from_label("Mode")
+                    .selected_text(self.render_mode.as_str())
+                    .show_ui(ui, |ui| {
+                        for mode in RenderMode::ALL {
+                            ui.selectable_value(&mut self.render_mode, mode, mode.as_str());
+                        }
+                    });
+                egui::ComboBox::from_label("Texture")
+                    .selected_text(self.texture.as_str())
+                    .show_ui(ui, |ui| {
+                        for texture in TextureSource::ALL {
+                            ui.selectable_value(&mut self.texture, texture, texture.as_str());
+                        }
+                    })
+                    .response
+                    .on_hover_text("Also used to export");
+                match self.render_mode {
+                    RenderMode::Points => {
+                        let slider = egui::Slider::new(&mut self.point_size, 1.0..=10.0);
+                        ui.add(slider.text("Point size"));
+                    }
+                    RenderMode::Cubes => (),
+                    RenderMode::Surface | RenderMode::Wireframe => {
+                        ui.horizontal(|ui| {
+                            ui.add(max_edge_value(&mut self.max_edge));
+                            ui.label("Max edge");
+                        });
+                    }
+                }
+            });
+    }
 
-        if self.depth_frame.is_some() && self.infrared_frame.is_some() {
+    fn stream_window(&mut self, egui_ctx: &egui::Context) {
+        egui::Window::new("Stream")
+            .default_open(self.pipeline_message.is_some())
+            .show(egui_ctx, |ui| {
+                let (width, height) = self.args.resolution;
+                let mut resolution = self.args.resolution;
+                egui::ComboBox::from_label("Resolution")
+                    .selected_text(format!("{width}x{height}"))
+                    .show_ui(ui, |ui| {
+                        for (width, height) in &self.resolutions {
+                            let text = format!("{width}x{height}");
+                            ui.selectable_value(&mut resolution, (*width, *height), text);
+                        }
+                    });
+                if resolution != self.args.resolution {
+                    self.args.resolution = resolution;
+                    self.restart_pipeline();
+                }
+                if ui.checkbox(&mut self.args.color, "Color stream").changed() {
+                    self.restart_pipeline();
+                }
+                let mut emitter = self.args.emitter;
+                egui::ComboBox::from_label("Emitter")
+                    .selected_text(emitter.as_str())
+                    .show_ui(ui, |ui| {
+                        for mode in EmitterMode::ALL {
+                            let enabled =
+                                mode != EmitterMode::Interleaved || self.supports_interleaved;
+                            ui.add_enabled_ui(enabled, |ui| {
+                                ui.selectable_value(&mut emitter, mode, mode.as_str());
+                            });
+                        }
+                    });
+                if emitter != self.args.emitter {
+                    self.args.emitter = emitter;
+                    self.emitter_warning = None;
+                    self.restart_pipeline();
+                }
+                if let Some(warning) = &self.emitter_warning {
+                    ui.colored_label(egui::Color32::YELLOW, warning);
+                }
+                if let Some(msg) = &self.pipeline_message {
+                    ui.colored_label(egui::Color32::YELLOW, msg);
+                }
+                ui.label(format!("{} points", self.instance_count));
+            });
+    }
+
+    fn export_window(&mut self, egui_ctx: &egui::Context) {
+        egui::Window::new("Export")
+            .default_open(false)
+            .show(egui_ctx, |ui| {
+                egui::ComboBox::from_label("Point cloud")
+                    .selected_text(self.point_cloud_format.as_str())
+                    .show_ui(ui, |ui| {
+                        for format in PointCloudFormat::ALL {
+                            let format_str = format.as_str();
+                            ui.selectable_value(&mut self.point_cloud_format, format, format_str);
+                        }
+                    });
+                if ui.button("Export point cloud").clicked() {
+                    self.export_message = Some(match self.export_point_cloud() {
+                        Ok(path) => format!("Saved {path}"),
+                        Err(e) => format!("Failed to export point cloud: {e}"),
+                    });
+                }
+                ui.separator();
+                egui::ComboBox::from_label("Mesh")
+                    .selected_text(self.mesh_format.as_str())
+                    .show_ui(ui, |ui| {
+                        for format in MeshFormat::ALL {
+                            ui.selectable_value(&mut self.mesh_format, format, format.as_str());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    ui.add(max_edge_value(&mut self.max_edge));
+                    ui.label("Max edge");
+                });
+                if ui.button("Export mesh").clicked() {
+                    self.export_message = Some(match self.export_mesh() {
+                        Ok(path) => format!("Saved {path}"),
+                        Err(e) => format!("Failed to export mesh: {e}"),
+                    });
+                }
+                ui.separator();
+                let export = ui.add_enabled(
+                    !self.odometry.trajectory().is_empty(),
+                    egui::Button::new("Export trajectory"),
+                );
+                if export.on_hover_text("TUM format").clicked() {
+                    self.export_message = Some(match self.export_trajectory() {
+                        Ok(path) => format!("Saved {path}"),
+                        Err(e) => format!("Failed to export trajectory: {e}"),
+                    });
+                }
+                ui.separator();
+                ui.checkbox(&mut self.crop_exports, "Apply clipping")
+                    .on_hover_text("Crop the point cloud and mesh exported");
+                if let Some(msg) = &self.export_message {
+                    ui.label(msg);
+                }
+            });
+    }
+
+    /// Writes the scan, or else the last depth frame, as a point cloud in the
+    /// working directory
+    fn export_point_cloud(&self) -> anyhow::Result<String> {
+        let cloud = match &self.scan_mesh {
+            Some(mesh) => PointCloud {
+                points: mesh.vertices.clone(),
+                colors: mesh.colors.clone(),
+            },
+            None => PointCloud::from_composite(self.last_snapshot()?, self.texture)?,
+        };
+        let cloud = cloud.crop(self.export_crop());
+        let path = format!("realsense-points-{}", time_ms());
+        let path = cloud.save(std::path::Path::new(&path), self.point_cloud_format)?;
+        Ok(path.display().to_string())
+    }
+
+    /// Writes the scan, or else the last depth frame, as a mesh in the
+    /// working directory
+    fn export_mesh(&self) -> anyhow::Result<String> {
+        let frame_mesh;
+        let mesh = match &self.scan_mesh {
+            Some(mesh) => mesh,
+            None => {
+                frame_mesh =
+                    Mesh::from_composite(self.last_snapshot()?, self.texture, self.max_edge)?;
+                &frame_mesh
+            }
+        };
+        let mesh = mesh.crop(self.export_crop());
+        let path = format!("realsense-mesh-{}", time_ms());
+        let path = mesh.save(std::path::Path::new(&path), self.mesh_format)?;
+        Ok(path.display().to_string())
+    }
+
+    /// Test of the points exported, in the frame of the scan, or else of the
+    /// last depth frame, which the tracking moves in the frame of the clipping
+    fn export_crop(&self) -> impl Fn(glam::Vec3) -> bool {
+        let clipping = self.crop_exports.then_some(self.clipping);
+        let pose = if self.tracking && self.scan_mesh.is_none() {
+            self.odometry.pose()
+        } else {
+            glam::Affine3A::IDENTITY
+        };
+        move |point| clipping.is_none_or(|clipping| clipping.contains(pose.transform_point3(point)))
+    }
+
+    /// Writes the poses of the camera tracked in the working directory
+    fn export_trajectory(&self) -> anyhow::Result<String> {
+        let path = format!("realsense-trajectory-{}", time_ms());
+        let path = self.odometry.save_tum(std::path::Path::new(&path))?;
+        Ok(path.display().to_string())
+    }
+
+    fn last_snapshot(&self) -> anyhow::Result<&CompositeSnapshot> {
+        self.snapshot
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("There are no frames"))
+    }
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, egui_ctx: &egui::Context, frame: &mut eframe::Frame) {
+        // Drain the queue, as the IMU sends many more framesets than the
+        // cameras, waiting a bit for the first one
+        let mut framesets = Vec::new();
+        if let Some(pipeline) = self.pipeline.as_mut() {
+            let timeout = Duration::from_millis(100);
+            let mut next = pipeline.wait(Some(timeout)).map(Some);
+            loop {
+                match next {
+                    Ok(Some(frames)) => framesets.push(frames),
+                    Ok(None) => break,
+                    Err(e) => {
+                        println!("{e}");
+                        break;
+                    }
+                }
+                next = pipeline.poll();
+            }
+        }
+
+        let mut missing_metadata = false;
+        for frames in &framesets {
+            missing_metadata |= self.receive_frames(frames);
+        }
+
+        if missing_metadata {
+            self.emitter_warning = Some("No emitter metadata, pairing by timestamp".to_string());
+            self.args.emitter = EmitterMode::On;
+            self.restart_pipeline();
+        }
+
+        let paired = match (&self.depth_frame, &self.infrared_frame) {
+            (Some(depth), Some(infrared)) => {
+                self.args.emitter == EmitterMode::Interleaved
+                    || (depth.timestamp() - infrared.timestamp()).abs() <= MAX_PAIR_OFFSET
+            }
+            _ => false,
+        };
+        if paired {
             let depth_frame = self.depth_frame.take().unwrap();
             let infrared_frame = self.infrared_frame.take().unwrap();
             if depth_frame.width() != infrared_frame.width()
@@ -326,47 +1622,35 @@ impl eframe::App for MyApp {
                 panic!("Make sure depth and infrared frames are the same size");
             }
 
-            let (depth_data, infrared_data) = get_buffers_data(depth_frame, infrared_frame);
+            let snapshot = snapshot_of(&depth_frame, &infrared_frame, self.color_frame.as_ref());
 
             // Get the OpenGL context from the frame
             let gl = frame.gl().expect("Can't get GL from frame");
-
-            // Update instances depth
-            unsafe {
-                gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.instance_depth_vbo));
-                gl.buffer_data_u8_slice(
-                    glow::ARRAY_BUFFER,
-                    &bytemuck::cast_slice(&depth_data),
-                    glow::DYNAMIC_DRAW,
-                );
-            }
-
-            // Update instances color
-            unsafe {
-                gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.instance_color_vbo));
-                gl.buffer_data_u8_slice(
-                    glow::ARRAY_BUFFER,
-                    &bytemuck::cast_slice(&infrared_data),
-                    glow::DYNAMIC_DRAW,
-                );
+            // A frame lost by the tracking has no pose to be fused at
+            let tracked = !self.tracking || self.track(gl, &snapshot).is_ok();
+            if self.scanning && tracked {
+                self.integrate(&snapshot);
             }
+            self.update_buffers(gl, &snapshot);
+            self.find_floor();
+            self.snapshot = Some(snapshot);
         }
 
-        // Compute View Projection matrix
-        let projection = glam::Mat4::perspective_rh_gl(45.0_f32.to_radians(), 1.0, 0.1, 100.0);
-        let input = egui_ctx.input(|i| i.clone());
-        self.translation += get_translation(&input);
-        self.rotation += get_rotation(&input);
-        let translation = glam::Mat4::from_translation(self.translation);
-        let rotation =
-            glam::Mat4::from_euler(glam::EulerRot::XYZ, -self.rotation.y, self.rotation.x, 0.0);
-        let view = translation * rotation;
-        let view_projection = projection * view;
+        // Compute View Projection matrix, following the window shape
+        let screen = egui_ctx.screen_rect();
+        let aspect_ratio = screen.width() / screen.height().max(1.0);
+        let leveling = self.leveling();
+        let view_projection = self.camera.view_projection(aspect_ratio);
+        self.navigate(egui_ctx, &view_projection, &leveling);
+        let view_projection = self.camera.view_projection(aspect_ratio);
+        // The points are leveled, the grid already is
+        let scene = view_projection * leveling;
 
-        unsafe {
-            // Get the OpenGL context from the frame
-            let gl = frame.gl().expect("Can't get GL from frame");
+        // Get the OpenGL context from the frame
+        let gl = frame.gl().expect("Can't get GL from frame");
+        self.update_clip_box(gl);
 
+        unsafe {
             gl.clear_color(0.0, 0.0, 0.0, 1.0);
             gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
 
@@ -374,43 +1658,39 @@ impl eframe::App for MyApp {
             gl.enable(glow::DEPTH_TEST);
             gl.depth_func(glow::LESS); // Default: Pass if fragment is closer
 
-            gl.use_program(Some(self.program));
-            gl.bind_vertex_array(Some(self.vao));
-
-            // Apply view projection matrix
-            let uniform_location = gl
-                .get_uniform_location(self.program, "viewProjection")
-                .unwrap();
-            gl.uniform_matrix_4_f32_slice(
-                Some(&uniform_location),
-                false,
-                view_projection.to_cols_array().as_slice(),
-            );
-
-            // Draw the cube
-            gl.draw_elements_instanced(glow::TRIANGLES, 36, glow::UNSIGNED_INT, 0, 640 * 640);
+            self.draw(gl, &scene);
+            if self.show_grid {
+                self.draw_grid(gl, &view_projection);
+            }
+            if self.tracking {
+                self.draw_trajectory(gl, &scene);
+            }
+            self.draw_clip_box(gl, &scene);
+        }
+        if self.show_gizmo {
+            self.draw_gizmo(egui_ctx);
         }
 
+        self.view_window(egui_ctx);
+        self.render_window(egui_ctx);
+        self.stream_window(egui_ctx);
+        self.export_window(egui_ctx);
+        self.scan_window(egui_ctx);
+        self.planes_window(egui_ctx);
+        self.clipping_window(egui_ctx);
+
         egui_ctx.request_repaint();
     }
 }
 
 /// Starts RealSense pipeline
 fn start_pipeline(
-    devices: Vec<realsense_rust::device::Device>,
+    realsense_device: &realsense_rust::device::Device,
     pipeline: realsense_rust::pipeline::InactivePipeline,
-    enable_auto_exposure: bool,
-) -> realsense_rust::pipeline::ActivePipeline {
-    let realsense_device = find_realsense(devices);
-
-    if realsense_device.is_none() {
-        eprintln!("No RealSense device found!");
-        std::process::exit(-1);
-    }
-
+    args: &Args,
+) -> anyhow::Result<realsense_rust::pipeline::ActivePipeline> {
     // We want depth and color
     let mut config = realsense_rust::config::Config::new();
-    let realsense_device = realsense_device.unwrap();
     let serial_number = realsense_device
         .info(realsense_rust::kind::Rs2CameraInfo::SerialNumber)
         .unwrap();
@@ -422,8 +1702,8 @@ fn start_pipeline(
         .enable_stream(
             realsense_rust::kind::Rs2StreamKind::Depth,
             None,
-            FRAME_SIZE.0,
-            FRAME_SIZE.1,
+            args.resolution.0,
+            args.resolution.1,
             realsense_rust::kind::Rs2Format::Z16,
             30,
         )
@@ -431,40 +1711,145 @@ fn start_pipeline(
         .enable_stream(
             realsense_rust::kind::Rs2StreamKind::Infrared,
             Some(1),
-            FRAME_SIZE.0,
-            FRAME_SIZE.1,
+            args.resolution.0,
+            args.resolution.1,
             realsense_rust::kind::Rs2Format::Y8,
             30,
         )
         .expect("Failed to enable infrared stream");
+    if args.gyro {
+        config
+            .enable_stream(
+                realsense_rust::kind::Rs2StreamKind::Gyro,
+                None,
+                0,
+                0,
+                realsense_rust::kind::Rs2Format::Any,
+                0,
+            )
+            .expect("Failed to enable gyro stream");
+    }
+    if args.accel {
+        config
+            .enable_stream(
+                realsense_rust::kind::Rs2StreamKind::Accel,
+                None,
+                0,
+                0,
+                realsense_rust::kind::Rs2Format::Any,
+                0,
+            )
+            .expect("Failed to enable accel stream");
+    }
+    if args.color {
+        config
+            .enable_stream(
+                realsense_rust::kind::Rs2StreamKind::Color,
+                None,
+                640,
+                0,
+                realsense_rust::kind::Rs2Format::Rgb8,
+                30,
+            )
+            .expect("Failed to enable color stream");
+    }
 
-    let pipeline = pipeline
-        .start(Some(config))
-        .expect("Failed to start pipeline");
+    let pipeline = pipeline.start(Some(config))?;
 
     for mut sensor in pipeline.profile().device().sensors() {
         // Enable emitter
         if sensor.supports_option(realsense_rust::kind::Rs2Option::EmitterEnabled) {
+            let val = if args.emitter == EmitterMode::Off {
+                0.0
+            } else {
+                1.0
+            };
             sensor
-                .set_option(realsense_rust::kind::Rs2Option::EmitterEnabled, 1.0)
+                .set_option(realsense_rust::kind::Rs2Option::EmitterEnabled, val)
                 .expect("Failed to set option: EmitterEnabled");
         }
         // Interleave mode, so we have depth and we can overlay IR1
         if sensor.supports_option(realsense_rust::kind::Rs2Option::EmitterOnOff) {
+            let val = if args.emitter == EmitterMode::Interleaved {
+                1.0
+            } else {
+                0.0
+            };
             sensor
-                .set_option(realsense_rust::kind::Rs2Option::EmitterOnOff, 1.0)
+                .set_option(realsense_rust::kind::Rs2Option::EmitterOnOff, val)
                 .expect("Failed to set option: EmitterOnOff");
         }
         // Enable Auto Exposure
         if sensor.supports_option(realsense_rust::kind::Rs2Option::EnableAutoExposure) {
-            let val = if enable_auto_exposure { 1.0 } else { 0.0 };
+            let val = if args.auto_exposure { 1.0 } else { 0.0 };
             sensor
                 .set_option(realsense_rust::kind::Rs2Option::EnableAutoExposure, val)
                 .expect("Failed to set option: EnableAutoExposure");
         }
     }
 
-    pipeline
+    // Apply preset, e.g. High Accuracy, from a JSON file
+    if let Some(path) = &args.preset {
+        std::fs::read_to_string(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| advanced_mode::load_json(pipeline.profile().device(), &json))
+            .with_context(|| format!("Failed to load preset {path}"))?;
+    }
+
+    Ok(pipeline)
+}
+
+/// Rotation from a motion stream to the depth camera, when both are streaming
+fn motion_to_depth(
+    profile: &realsense_rust::pipeline::PipelineProfile,
+    kind: Rs2StreamKind,
+) -> Option<glam::Mat3> {
+    let streams = profile.streams();
+    let find = |kind| streams.iter().find(|stream| stream.kind() == kind);
+    let motion = find(kind)?;
+    let depth = find(Rs2StreamKind::Depth)?;
+    let extrinsics = motion.extrinsics(depth).ok()?;
+    Some(glam::Mat3::from_cols_array(&extrinsics.rotation()))
+}
+
+/// Resolutions with both Z16 depth and Y8 infrared at the 30 fps that
+/// start_pipeline asks for, smallest first
+fn supported_resolutions(device: &realsense_rust::device::Device) -> Vec<(usize, usize)> {
+    use realsense_rust::kind::{Rs2Format, Rs2StreamKind};
+    let mut depth = HashSet::new();
+    let mut infrared = HashSet::new();
+    for sensor in device.sensors() {
+        for profile in sensor.stream_profiles() {
+            // Only video profiles have intrinsics
+            let Ok(intrinsics) = profile.intrinsics() else {
+                continue;
+            };
+            if profile.framerate() != 30 {
+                continue;
+            }
+            let resolution = (intrinsics.width(), intrinsics.height());
+            match (profile.kind(), profile.format()) {
+                (Rs2StreamKind::Depth, Rs2Format::Z16) => {
+                    depth.insert(resolution);
+                }
+                (Rs2StreamKind::Infrared, Rs2Format::Y8) if profile.index() == 1 => {
+                    infrared.insert(resolution);
+                }
+                _ => (),
+            }
+        }
+    }
+    let mut resolutions: Vec<_> = depth.intersection(&infrared).copied().collect();
+    resolutions.sort_by_key(|(width, height)| width * height);
+    resolutions
+}
+
+/// Whether the device can alternate the emitter on and off between frames
+fn supports_emitter_on_off(device: &realsense_rust::device::Device) -> bool {
+    device
+        .sensors()
+        .iter()
+        .any(|sensor| sensor.supports_option(realsense_rust::kind::Rs2Option::EmitterOnOff))
 }
 
 /// Finds first Real Sense device available
@@ -492,10 +1877,10 @@ fn match_info(
 }
 
 /// Creates shader program to draw cubes with depth translation
-fn create_shader_program(gl: &glow::Context) -> glow::NativeProgram {
+fn create_shader_program(gl: &glow::Context, vertex_src: &str) -> glow::NativeProgram {
     unsafe {
         // Vertex shader
-        let vertex_shader = compile_shader(gl, glow::VERTEX_SHADER, VERTEX_SHADER_SRC);
+        let vertex_shader = compile_shader(gl, glow::VERTEX_SHADER, vertex_src);
 
         // Fragment shader
         let fragment_shader = compile_shader(gl, glow::FRAGMENT_SHADER, FRAGMENT_SHADER_SRC);
@@ -511,6 +1896,48 @@ fn create_shader_program(gl: &glow::Context) -> glow::NativeProgram {
     }
 }
 
+/// Points a float attribute to the bound buffer. A divisor of 1 makes it
+/// per-instance.
+unsafe fn set_attribute(gl: &glow::Context, location: u32, size: i32, divisor: u32) {
+    gl.vertex_attrib_pointer_f32(
+        location,
+        size,
+        glow::FLOAT,
+        false,
+        size * std::mem::size_of::<f32>() as i32,
+        0,
+    );
+    gl.enable_vertex_attrib_array(location);
+    gl.vertex_attrib_divisor(location, divisor);
+}
+
+fn max_edge_value(max_edge: &mut f32) -> egui::DragValue<'_> {
+    egui::DragValue::new(max_edge)
+        .range(0.001..=1.0)
+        .speed(0.001)
+        .suffix(" m")
+}
+
+/// Edits the coordinates of a vector
+fn vec3_values(
+    ui: &mut egui::Ui,
+    value: &mut glam::Vec3,
+    speed: f32,
+    range: std::ops::RangeInclusive<f32>,
+    suffix: &str,
+) {
+    ui.horizontal(|ui| {
+        for (coordinate, prefix) in value.as_mut().iter_mut().zip(["X: ", "Y: ", "Z: "]) {
+            let drag = egui::DragValue::new(coordinate)
+                .range(range.clone())
+                .speed(speed)
+                .prefix(prefix)
+                .suffix(suffix);
+            ui.add(drag);
+        }
+    });
+}
+
 fn compile_shader(gl: &glow::Context, shader_type: u32, src: &str) -> glow::NativeShader {
     unsafe {
         let shader = gl.create_shader(shader_type).unwrap();
@@ -526,27 +1953,25 @@ fn compile_shader(gl: &glow::Context, shader_type: u32, src: &str) -> glow::Nati
     }
 }
 
-fn get_translation(input: &egui::InputState) -> glam::Vec3 {
-    if input.pointer.secondary_down() {
-        glam::Vec3::new(
-            input.pointer.delta().x * 0.01,
-            -input.pointer.delta().y * 0.01,
-            input.smooth_scroll_delta.y * 0.01,
-        )
-    } else {
-        glam::Vec3::new(0.0, 0.0, input.smooth_scroll_delta.y * 0.01)
-    }
+/// Milliseconds since the epoch, to name the exported files
+fn time_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before Unix epoch")
+        .as_millis()
 }
 
-fn get_rotation(input: &egui::InputState) -> glam::Vec2 {
-    if input.pointer.primary_down() {
-        glam::Vec2::new(
-            input.pointer.delta().x * 0.01,
-            -input.pointer.delta().y * 0.01,
-        )
-    } else {
-        glam::Vec2::ZERO
+/// Positions and colors of the floor grid lines, on the XZ plane
+fn grid_lines() -> (Vec<f32>, Vec<f32>) {
+    let mut positions = Vec::new();
+    let steps = (GRID_EXTENT / GRID_SPACING) as i32;
+    for step in -steps..=steps {
+        let offset = step as f32 * GRID_SPACING;
+        positions.extend_from_slice(&[offset, 0.0, -GRID_EXTENT, offset, 0.0, GRID_EXTENT]);
+        positions.extend_from_slice(&[-GRID_EXTENT, 0.0, offset, GRID_EXTENT, 0.0, offset]);
     }
+    let colors = [0.3, 0.3, 0.3, 1.0].repeat(positions.len() / 3);
+    (positions, colors)
 }
 
 fn frame_of_type_with_emitter<T: realsense_rust::frame::FrameEx>(
@@ -571,37 +1996,94 @@ fn frame_of_type_with_emitter<T: realsense_rust::frame::FrameEx>(
     }
 }
 
-fn get_buffers_data(
-    depth_frame: realsense_rust::frame::DepthFrame,
-    infrared_frame: realsense_rust::frame::InfraredFrame,
-) -> (Vec<f32>, Vec<f32>) {
-    let (width, height) = (depth_frame.width(), depth_frame.height());
-    let instance_number = width * height;
-    let mut infrared_data: Vec<f32> = vec![0.0; instance_number * 4];
-    let mut depth_data: Vec<f32> = vec![0.0; instance_number];
-    let max_depth = 4000.0; // 4m
-    for col in 0..width {
-        for row in 0..height {
-            match depth_frame.get_unchecked(col, row) {
-                realsense_rust::frame::PixelKind::Z16 { depth } => {
-                    let normalized = (*depth as f32 / max_depth).clamp(0.0, 1.0);
-                    if normalized > 0.05 {
-                        depth_data[row * width + col] = (1.0 - normalized) * 4.0;
-                        match infrared_frame.get_unchecked(col, row) {
-                            realsense_rust::frame::PixelKind::Y8 { y } => {
-                                let base_index = (row * width + col) * 4;
-                                infrared_data[base_index] = *y as f32 / 255.0;
-                                infrared_data[base_index + 1] = *y as f32 / 255.0;
-                                infrared_data[base_index + 2] = *y as f32 / 255.0;
-                                infrared_data[base_index + 3] = 1.0;
-                            }
-                            _ => panic!("Color type is wrong!"),
-                        }
-                    }
-                }
-                _ => panic!("Depth type is wrong!"),
-            }
+/// Copies a depth and infrared pair, and the color if any. When interleaving,
+/// the infrared was taken with the emitter off, so it comes from another
+/// frameset.
+fn snapshot_of(
+    depth_frame: &realsense_rust::frame::DepthFrame,
+    infrared_frame: &realsense_rust::frame::InfraredFrame,
+    color_frame: Option<&realsense_rust::frame::ColorFrame>,
+) -> CompositeSnapshot {
+    let mut depth = FrameSnapshot::from_depth(depth_frame);
+    let mut frames = vec![(
+        FrameSnapshot::from_infrared(infrared_frame),
+        infrared_frame.stream_profile(),
+    )];
+    if let Some(color_frame) = color_frame {
+        frames.push((
+            FrameSnapshot::from_color(color_frame),
+            color_frame.stream_profile(),
+        ));
+    }
+    // Extrinsics map the depth pixels into the other streams
+    for (frame, profile) in &frames {
+        if let Ok(extrinsics) = depth_frame.stream_profile().extrinsics(profile) {
+            depth
+                .extrinsics
+                .push((frame.stream.clone(), Extrinsics::from(&extrinsics)));
         }
     }
-    (depth_data, infrared_data)
+    CompositeSnapshot {
+        host_time: depth.timestamp,
+        frames: std::iter::once(depth)
+            .chain(frames.into_iter().map(|(frame, _)| frame))
+            .collect(),
+    }
+}
+
+/// Points of the pixels with depth, and the triangles between them when
+/// drawing a surface. Without color, they fall back to infrared, and then
+/// to the depth colormap.
+fn reconstruct(
+    snapshot: &CompositeSnapshot,
+    texture: TextureSource,
+    triangulate: bool,
+    max_edge: f32,
+) -> Mesh {
+    let build = |texture: TextureSource| {
+        if triangulate {
+            Mesh::from_composite(snapshot, texture, max_edge)
+        } else {
+            PointCloud::from_composite(snapshot, texture).map(|cloud| Mesh {
+                vertices: cloud.points,
+                colors: cloud.colors,
+                triangles: Vec::new(),
+            })
+        }
+    };
+    texture_fallbacks(&texture)
+        .iter()
+        .find_map(|texture| build(*texture).ok())
+        .expect("Failed to deproject depth")
+}
+
+/// Textures to try in order, when the streams are missing
+fn texture_fallbacks(texture: &TextureSource) -> &[TextureSource] {
+    match texture {
+        TextureSource::Color => &[
+            TextureSource::Color,
+            TextureSource::Infrared,
+            TextureSource::Depth,
+        ],
+        TextureSource::Infrared => &[TextureSource::Infrared, TextureSource::Depth],
+        _ => std::slice::from_ref(texture),
+    }
+}
+
+/// Positions and colors of the vertices. The camera sits at the origin,
+/// looking along -Z with Y up as OpenGL does.
+fn get_buffers_data(mesh: &Mesh) -> (Vec<f32>, Vec<f32>) {
+    let positions = mesh
+        .vertices
+        .iter()
+        .flat_map(|vertex| [vertex.x, -vertex.y, -vertex.z])
+        .collect();
+    let colors = match &mesh.colors {
+        Some(colors) => colors
+            .iter()
+            .flat_map(|[r, g, b]| [*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0, 1.0])
+            .collect(),
+        None => vec![1.0; mesh.vertices.len() * 4],
+    };
+    (positions, colors)
 }
@@ -17,15 +17,44 @@
 // You can contact the author via carlospzlz@gmail.com
 
 use eframe::egui;
-use std::collections::HashSet;
+use realsense_rust::frame::FrameEx;
+use realsense_tools_rs::advanced_mode;
+use realsense_tools_rs::depth_quality::{
+    DepthQuality, DepthQualityHistory, Roi, DEFAULT_ROI_SHARE,
+};
+use realsense_tools_rs::device_manager::{DeviceEvent, DeviceEventKind, DeviceManager};
+use realsense_tools_rs::frame_buffer::{FrameBuffer, DEFAULT_DURATION, DEFAULT_MAX_BYTES};
+use realsense_tools_rs::frame_snapshot::{
+    CompositeSnapshot, ExtrinsicsCache, FrameSnapshot, SnapshotData,
+};
+use realsense_tools_rs::inter_cam_sync::{InterCamSyncMode, SyncVerifier};
+use realsense_tools_rs::layout::{Layout, LayoutMode};
+use realsense_tools_rs::point_cloud::{PointCloud, PointCloudFormat, TextureSource};
+use realsense_tools_rs::presets::{visual_preset_name, visual_presets_in_range};
+use realsense_tools_rs::stream_stats::SessionStats;
+use realsense_tools_rs::sync_analysis::{PairStats, SyncAnalyzer};
+use realsense_tools_rs::tile_view::{TileView, GRID_MIN_PIXEL_SIZE};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ffi::CString;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 fn main() -> Result<(), eframe::Error> {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+    let sync_modes = match parse_sync_modes(std::env::args().skip(1)) {
+        Ok(sync_modes) => sync_modes,
+        Err(e) => {
+            eprintln!("{e}");
+            eprintln!("Usage: realsense-viewer [--sync-mode [SERIAL=]MODE]...");
+            std::process::exit(-1);
+        }
+    };
+
     let realsense_ctx =
         realsense_rust::context::Context::new().expect("Failed to create RealSense context");
+    let device_manager =
+        DeviceManager::new(realsense_ctx).expect("Failed to register device changes callback");
 
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([960.0, 550.0]),
@@ -34,121 +63,696 @@ fn main() -> Result<(), eframe::Error> {
     eframe::run_native(
         "Realsense Viewer \u{1F980}",
         options,
-        Box::new(|cc| Ok(Box::new(MyApp::new(cc, realsense_ctx)))),
+        Box::new(|cc| Ok(Box::new(MyApp::new(cc, device_manager, sync_modes)))),
     )
 }
 
+/// Parses `--sync-mode [SERIAL=]MODE` arguments. A mode without serial number
+/// applies to every device.
+fn parse_sync_modes(
+    mut args: impl Iterator<Item = String>,
+) -> Result<Vec<(Option<String>, InterCamSyncMode)>, String> {
+    let mut sync_modes = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg != "--sync-mode" {
+            return Err(format!("Unknown argument '{arg}'"));
+        }
+        let value = args.next().ok_or("Missing value for --sync-mode")?;
+        let sync_mode = match value.split_once('=') {
+            Some((serial_number, mode)) => (Some(serial_number.to_string()), mode.parse()?),
+            None => (None, value.parse()?),
+        };
+        sync_modes.push(sync_mode);
+    }
+    Ok(sync_modes)
+}
+
 struct MyApp {
-    realsense_ctx: realsense_rust::context::Context,
-    dev_index: u8,
+    device_manager: DeviceManager,
     warning: Option<String>,
-    pipeline: Option<realsense_rust::pipeline::ActivePipeline>,
-    depth_stream_enabled: bool,
-    color_stream_enabled: bool,
-    infrared_1_stream_enabled: bool,
-    infrared_2_stream_enabled: bool,
-    accel_stream_enabled: bool,
-    gyro_stream_enabled: bool,
-    global_time_enabled: bool,
-    auto_exposure_enabled: bool,
-    emitter_enabled: bool,
-    emitter_on_off: bool,
-    emitter_always_on: bool,
+    sessions: Vec<DeviceSession>,
+    selected_session: usize,
+    auto_start: bool,
+    stats_export: Option<String>,
+    sync_window_open: bool,
+    sync_modes: Vec<(Option<String>, InterCamSyncMode)>,
+    inter_cam_window_open: bool,
+    inter_cam_reference: Option<String>,
+    depth_quality_window_open: bool,
+    /// Depth tile and region measured, the middle of the first depth tile
+    /// until one is selected
+    quality_roi: Option<(TileKey, Roi)>,
+    /// Pixel where the selection of the ROI started
+    roi_drag_start: Option<(usize, usize)>,
+    ground_truth_enabled: bool,
+    /// Distance to the target along the optical axis, in meters
+    ground_truth: f32,
+    quality: DepthQualityHistory,
+    /// Why the last frame couldn't be measured
+    quality_message: Option<String>,
+    quality_export_message: Option<String>,
+    sync_verifier: SyncVerifier,
+    /// Showing the frame buffer instead of the live frames
+    paused: bool,
+    /// Host time shown while paused, in milliseconds
+    cursor: f64,
+    /// Length of the frame buffers, in seconds
+    buffer_duration: f64,
+    /// Memory of the frame buffer of each device, in megabytes
+    buffer_memory: usize,
+    export_message: Option<String>,
+    point_cloud_format: PointCloudFormat,
+    point_cloud_texture: TextureSource,
+    tile_views: HashMap<TileKey, TileView>,
+    maximized: Option<TileKey>,
+    layout: Layout,
 }
 
+/// Serial number and stream name of a tile
+type TileKey = (String, String);
+
+/// Height of the title, timestamp and motion values lines of the tiles
+const LINE_HEIGHT: f32 = 16.0;
+
 impl MyApp {
     fn new(
-        _cc: &eframe::CreationContext<'_>,
-        realsense_ctx: realsense_rust::context::Context,
+        cc: &eframe::CreationContext<'_>,
+        device_manager: DeviceManager,
+        sync_modes: Vec<(Option<String>, InterCamSyncMode)>,
     ) -> Self {
         Self {
-            realsense_ctx,
-            dev_index: 0,
+            device_manager,
             warning: None,
-            pipeline: None,
-            depth_stream_enabled: true,
-            color_stream_enabled: true,
-            infrared_1_stream_enabled: true,
-            infrared_2_stream_enabled: true,
-            accel_stream_enabled: true,
-            gyro_stream_enabled: true,
-            global_time_enabled: true,
-            auto_exposure_enabled: true,
-            emitter_enabled: true,
-            emitter_on_off: false,
-            emitter_always_on: false,
+            sessions: Vec::new(),
+            selected_session: 0,
+            auto_start: true,
+            stats_export: None,
+            sync_window_open: false,
+            sync_modes,
+            inter_cam_window_open: false,
+            inter_cam_reference: None,
+            depth_quality_window_open: false,
+            quality_roi: None,
+            roi_drag_start: None,
+            ground_truth_enabled: false,
+            ground_truth: 1.0,
+            quality: DepthQualityHistory::default(),
+            quality_message: None,
+            quality_export_message: None,
+            sync_verifier: SyncVerifier::default(),
+            paused: false,
+            cursor: 0.0,
+            buffer_duration: DEFAULT_DURATION,
+            buffer_memory: DEFAULT_MAX_BYTES / 1_000_000,
+            export_message: None,
+            point_cloud_format: PointCloudFormat::default(),
+            point_cloud_texture: TextureSource::default(),
+            tile_views: HashMap::new(),
+            maximized: None,
+            layout: cc
+                .storage
+                .and_then(|storage| eframe::get_value(storage, LAYOUT_KEY))
+                .unwrap_or_default(),
         }
     }
 }
 
+/// Storage key of the tiles layout
+const LAYOUT_KEY: &str = "layout";
+
+/// Wait before starting again a device back that failed to start
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Wait for a device being reset to disappear, some come back before the
+/// list of devices is polled
+const RESET_TIMEOUT: Duration = Duration::from_secs(10);
+
 impl eframe::App for MyApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, LAYOUT_KEY, &self.layout);
+    }
+
     fn update(&mut self, egui_ctx: &egui::Context, _frame: &mut eframe::Frame) {
         // Reset warning
         self.warning = None;
 
-        // Check selected camera and update pipeline if needed
-        let devices = self.realsense_ctx.query_devices(HashSet::new());
-        self.update_pipeline_for_selected_device(&devices);
-
-        // Get frames
-        let frames = self.get_frames();
+        // Stop the pipelines of the gone cameras and restart the returning ones
+        self.update_sessions();
+
+        // Get frames of every device, the buffers stand still while paused
+        let mut frames = Vec::new();
+        for session in &mut self.sessions {
+            let session_frames = session.get_frames();
+            if let Some(session_frames) = &session_frames {
+                session.record_stats(session_frames);
+                if !self.paused {
+                    let snapshot = CompositeSnapshot::new(
+                        session_frames,
+                        host_time_ms(),
+                        &mut session.extrinsics,
+                    );
+                    session.buffer.push(snapshot);
+                }
+            }
+            frames.push(session_frames);
+        }
+        self.record_inter_cam_sync(&frames);
+        let snapshots = self.shown_snapshots();
+        self.measure_depth_quality(&snapshots);
 
         // Update GUI
+        self.playback_panel(egui_ctx);
         self.left_panel(egui_ctx);
-        self.right_panel(egui_ctx, &frames);
-        self.bottom_panel(egui_ctx, devices);
-        self.central_panel(egui_ctx, frames);
+        self.right_panel(egui_ctx, &snapshots);
+        self.bottom_panel(egui_ctx);
+        self.central_panel(egui_ctx, snapshots);
+        self.sync_window(egui_ctx);
+        self.inter_cam_window(egui_ctx);
+        self.depth_quality_window(egui_ctx);
 
         egui_ctx.request_repaint();
     }
 }
 
 impl MyApp {
-    fn update_pipeline_for_selected_device(
-        &mut self,
-        devices: &Vec<realsense_rust::device::Device>,
-    ) {
-        if devices.len() == 0 {
-            self.pipeline = None;
+    fn update_sessions(&mut self) {
+        let events = self.device_manager.poll();
+        self.handle_device_events(events);
+        self.retry_recoveries();
+
+        let devices = self.device_manager.devices();
+        if devices.is_empty() {
             self.warning = Some("No devices!".to_string());
+        }
+
+        // Start the first camera on launch, as the single device viewer did
+        if self.auto_start {
+            if let Some(device_index) = devices.iter().position(is_realsense) {
+                self.auto_start = false;
+                self.start_device(device_index);
+            }
+        }
+    }
+
+    /// Follows the devices gone and back, e.g. after a hardware reset
+    fn handle_device_events(&mut self, events: Vec<DeviceEvent>) {
+        for event in events {
+            let index = match self.session_index(&event.serial_number) {
+                Some(index) => index,
+                None => continue,
+            };
+            let session = &mut self.sessions[index];
+            match event.kind {
+                DeviceEventKind::Disconnected => {
+                    let streaming = session.pipeline.take().is_some();
+                    if streaming || session.recovery.is_some() {
+                        session.recovery = Some(Recovery::Gone);
+                        session.warning = Some("Device is gone".to_string());
+                    }
+                }
+                DeviceEventKind::Connected
+                    if matches!(session.recovery, Some(Recovery::Gone | Recovery::Reset(_))) =>
+                {
+                    self.restore_device(index);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Starts streaming again from a device that came back. It may still be
+    /// enumerating, then it is tried again later.
+    fn restore_device(&mut self, index: usize) {
+        let serial_number = self.sessions[index].serial_number.clone();
+        let Some(device_index) = self.device_index(&serial_number) else {
             return;
+        };
+        self.start_device(device_index);
+        let session = &mut self.sessions[index];
+        if session.pipeline.is_some() {
+            session.recovery = None;
+            let name = session.name.clone();
+            self.device_manager
+                .log(DeviceEventKind::Reconnected, &serial_number, &name);
+        } else {
+            session.recovery = Some(Recovery::Failed(Instant::now()));
         }
+    }
 
-        if usize::from(self.dev_index) >= devices.len() {
-            self.pipeline = None;
-            self.warning = Some(format!("Device {0} is gone", self.dev_index));
+    fn retry_recoveries(&mut self) {
+        for index in 0..self.sessions.len() {
+            let retry = match self.sessions[index].recovery {
+                Some(Recovery::Failed(time)) => time.elapsed() >= RETRY_INTERVAL,
+                Some(Recovery::Reset(time)) => time.elapsed() >= RESET_TIMEOUT,
+                _ => false,
+            };
+            if retry {
+                self.restore_device(index);
+            }
+        }
+    }
+
+    /// Resets the device, the pipeline is restored once it re-enumerates
+    fn hardware_reset(&mut self, device_index: usize) {
+        let device = &self.device_manager.devices()[device_index];
+        let serial_number = get_serial_number(device);
+        if let Some(index) = self.session_index(&serial_number) {
+            let session = &mut self.sessions[index];
+            if let Some(pipeline) = session.pipeline.take() {
+                pipeline.stop();
+                session.recovery = Some(Recovery::Reset(Instant::now()));
+                session.warning = Some("Resetting".to_string());
+            }
+        }
+        device.hardware_reset();
+    }
+
+    /// Opens the device again from a fresh enumeration, trying again later
+    /// if it fails to start
+    fn reconnect_device(&mut self, serial_number: &str) {
+        self.stop_device(serial_number);
+        let events = self.device_manager.refresh();
+        self.handle_device_events(events);
+        match (
+            self.session_index(serial_number),
+            self.device_index(serial_number),
+        ) {
+            (Some(index), Some(_)) => self.restore_device(index),
+            (None, Some(device_index)) => self.start_device(device_index),
+            (_, None) => self.warning = Some(format!("Device {serial_number} not found")),
+        }
+    }
+
+    /// Starts streaming from a device, reusing its previous settings if any.
+    /// Failures, e.g. a device busy in another process, are shown in its row.
+    fn start_device(&mut self, device_index: usize) {
+        let device = &self.device_manager.devices()[device_index];
+        let serial_number = get_serial_number(device);
+        let index = match self.session_index(&serial_number) {
+            Some(index) => index,
+            None => {
+                let mut session = DeviceSession::new(device);
+                // The last matching mode in the command line wins
+                for (mode_serial_number, mode) in &self.sync_modes {
+                    if mode_serial_number.is_none()
+                        || mode_serial_number.as_deref() == Some(serial_number.as_str())
+                    {
+                        session.inter_cam_sync_mode = *mode;
+                    }
+                }
+                self.sessions.push(session);
+                self.sessions.len() - 1
+            }
+        };
+        let buffer = &mut self.sessions[index].buffer;
+        buffer.set_duration(self.buffer_duration);
+        buffer.set_max_bytes(self.buffer_memory * 1_000_000);
+        self.sessions[index].start(self.device_manager.context());
+        self.selected_session = index;
+    }
+
+    fn stop_device(&mut self, serial_number: &str) {
+        if let Some(index) = self.session_index(serial_number) {
+            self.sessions[index].stop();
+            self.sessions[index].recovery = None;
+        }
+    }
+
+    fn selected_pipeline(&self) -> Option<&realsense_rust::pipeline::ActivePipeline> {
+        self.sessions
+            .get(self.selected_session)
+            .and_then(|session| session.pipeline.as_ref())
+    }
+
+    /// Device the others are compared against, the chosen one or the master
+    fn inter_cam_reference(&self) -> Option<String> {
+        let streaming = || {
+            self.sessions
+                .iter()
+                .filter(|session| session.pipeline.is_some())
+        };
+        self.inter_cam_reference
+            .clone()
+            .or_else(|| {
+                streaming()
+                    .find(|session| session.inter_cam_sync_mode == InterCamSyncMode::Master)
+                    .map(|session| session.serial_number.clone())
+            })
+            .or_else(|| {
+                streaming()
+                    .next()
+                    .map(|session| session.serial_number.clone())
+            })
+    }
+
+    fn record_inter_cam_sync(&mut self, frames: &[Option<realsense_rust::frame::CompositeFrame>]) {
+        let latest: BTreeMap<String, (u64, f64)> = self
+            .sessions
+            .iter()
+            .zip(frames)
+            .filter_map(|(session, frames)| {
+                let frame = latest_frame_sync_info(frames.as_ref()?)?;
+                Some((session.serial_number.clone(), frame))
+            })
+            .collect();
+        if latest.len() < 2 {
             return;
         }
+        if let Some(reference) = self.inter_cam_reference() {
+            self.sync_verifier.record(&reference, &latest);
+        }
+    }
 
-        let new_device = &devices[self.dev_index as usize];
-        let name = match_info(new_device, realsense_rust::kind::Rs2CameraInfo::Name);
-        if !name.starts_with("Intel RealSense") {
-            self.pipeline = None;
-            self.warning = Some(format!(
-                "Device {0} is not an Intel RealSense",
-                self.dev_index
-            ));
+    fn session_index(&self, serial_number: &str) -> Option<usize> {
+        self.sessions
+            .iter()
+            .position(|session| session.serial_number == serial_number)
+    }
+
+    fn device_index(&self, serial_number: &str) -> Option<usize> {
+        self.device_manager
+            .devices()
+            .iter()
+            .position(|device| get_serial_number(device) == serial_number)
+    }
+
+    /// Snapshot of each session to show, the latest one or the one under the
+    /// cursor while paused
+    fn shown_snapshots(&self) -> Vec<Option<Arc<CompositeSnapshot>>> {
+        self.sessions
+            .iter()
+            .map(|session| {
+                if self.paused {
+                    session.buffer.at(self.cursor)
+                } else if session.pipeline.is_some() {
+                    session.buffer.latest()
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Measures the depth of the ROI, once per frame
+    fn measure_depth_quality(&mut self, snapshots: &[Option<Arc<CompositeSnapshot>>]) {
+        if !self.depth_quality_window_open {
             return;
         }
+        // The tile of the ROI, or else the first depth tile
+        let target = self
+            .sessions
+            .iter()
+            .zip(snapshots)
+            .find_map(|(session, snapshot)| {
+                let frame =
+                    snapshot
+                        .as_ref()?
+                        .frames
+                        .iter()
+                        .find(|frame| match &self.quality_roi {
+                            Some(((serial_number, stream), _)) => {
+                                *serial_number == session.serial_number && frame.stream == *stream
+                            }
+                            None => matches!(frame.data, SnapshotData::Depth { .. }),
+                        })?;
+                Some((session, frame))
+            });
+        let Some((session, frame)) = target else {
+            return;
+        };
+        if self
+            .quality
+            .latest()
+            .is_some_and(|quality| quality.timestamp == frame.timestamp)
+        {
+            return;
+        }
+        let roi = match &self.quality_roi {
+            Some((_, roi)) => *roi,
+            None => {
+                let Some((width, height)) = frame.image_size() else {
+                    return;
+                };
+                let roi = Roi::centered(width, height, DEFAULT_ROI_SHARE);
+                let key = (session.serial_number.clone(), frame.stream.clone());
+                self.quality_roi = Some((key, roi));
+                roi
+            }
+        };
+        let ground_truth = self.ground_truth_enabled.then_some(self.ground_truth);
+        match DepthQuality::measure(frame, &roi, session.stereo_baseline(), ground_truth) {
+            Ok(quality) => {
+                self.quality.push(quality);
+                self.quality_message = None;
+            }
+            Err(e) => self.quality_message = Some(e.to_string()),
+        }
+    }
 
-        let new_serial_number = get_serial_number(new_device);
-        if let Some(pipeline) = &self.pipeline {
-            let current_device = &pipeline.profile().device();
-            if new_serial_number == get_serial_number(current_device) {
-                return;
+    /// Oldest and newest host times in the frame buffers
+    fn buffer_range(&self) -> Option<(f64, f64)> {
+        self.sessions
+            .iter()
+            .filter_map(|session| session.buffer.range())
+            .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if let Some((_, newest)) = self.buffer_range() {
+            self.cursor = newest;
+        }
+    }
+
+    /// Moves the cursor to the previous or next snapshot of the selected device
+    fn step(&mut self, forward: bool) {
+        if let Some(session) = self.sessions.get(self.selected_session) {
+            let host_time = if forward {
+                session.buffer.next(self.cursor)
+            } else {
+                session.buffer.previous(self.cursor)
+            };
+            if let Some(host_time) = host_time {
+                self.cursor = host_time;
             }
         }
+    }
 
-        let pipeline = if let Some(pipeline) = self.pipeline.take() {
-            // ActivePipeline -> InactivePipeline
-            pipeline.stop()
-        } else {
-            realsense_rust::pipeline::InactivePipeline::try_from(&self.realsense_ctx)
-                .expect("Failed to create inactive pipeline from context")
-        };
+    /// Writes the frame buffer of every device in a new directory
+    fn save_buffers(&self) -> anyhow::Result<String> {
+        let dir = format!("realsense-buffer-{}", host_time_ms() as u64);
+        let mut count = 0;
+        for session in &self.sessions {
+            if !session.buffer.is_empty() {
+                let path = std::path::Path::new(&dir).join(&session.serial_number);
+                count += session.buffer.save(&path)?;
+            }
+        }
+        anyhow::ensure!(count > 0, "The buffer is empty");
+        Ok(dir)
+    }
+
+    /// Writes the frames shown of every device, with a `snapshot.json` sidecar
+    fn save_snapshot(&self) -> anyhow::Result<String> {
+        let dir = format!("realsense-snapshot-{}", host_time_ms() as u64);
+        let dir_path = std::path::Path::new(&dir);
+        let mut sidecar = serde_json::Map::new();
+        for (session, snapshot) in self.sessions.iter().zip(self.shown_snapshots()) {
+            if let Some(snapshot) = snapshot {
+                std::fs::create_dir_all(dir_path)?;
+                let value = snapshot.save(dir_path, &session.serial_number, true)?;
+                sidecar.insert(session.serial_number.clone(), value);
+            }
+        }
+        anyhow::ensure!(!sidecar.is_empty(), "There are no frames");
+        let sidecar = serde_json::to_string_pretty(&sidecar)?;
+        std::fs::write(dir_path.join("snapshot.json"), sidecar)?;
+        Ok(dir)
+    }
+
+    /// Writes the depth shown of every device as a point cloud
+    fn export_point_clouds(&self) -> anyhow::Result<String> {
+        let time = host_time_ms() as u64;
+        let mut paths = Vec::new();
+        for (session, snapshot) in self.sessions.iter().zip(self.shown_snapshots()) {
+            if let Some(snapshot) = snapshot {
+                let cloud = PointCloud::from_composite(&snapshot, self.point_cloud_texture)?;
+                let path = format!("realsense-points-{}-{time}", session.serial_number);
+                let path = cloud.save(std::path::Path::new(&path), self.point_cloud_format)?;
+                paths.push(path.display().to_string());
+            }
+        }
+        anyhow::ensure!(!paths.is_empty(), "There are no frames");
+        Ok(paths.join(", "))
+    }
+
+    /// Writes the depth quality measured as CSV in the working directory
+    fn export_depth_quality(&self) -> anyhow::Result<String> {
+        let path = format!("realsense-depth-quality-{}.csv", host_time_ms() as u64);
+        self.quality.save_csv(std::path::Path::new(&path))?;
+        Ok(path)
+    }
+
+    /// Writes the summary of every device session as JSON in the working directory
+    fn export_stats(&self) -> anyhow::Result<String> {
+        let summaries: BTreeMap<_, _> = self
+            .sessions
+            .iter()
+            .map(|session| (session.serial_number.clone(), session.stats.summary()))
+            .collect();
+        let path = format!("realsense-stats-{}.json", host_time_ms() as u64);
+        std::fs::write(&path, serde_json::to_string_pretty(&summaries)?)?;
+        Ok(path)
+    }
+}
+
+/// Values of the visual preset option offered by a device, from its range
+fn visual_presets(device: &realsense_rust::device::Device) -> Vec<f32> {
+    let option = realsense_rust::kind::Rs2Option::VisualPreset;
+    device
+        .sensors()
+        .iter()
+        .filter(|sensor| sensor.supports_option(option))
+        .find_map(|sensor| sensor.get_option_range(option))
+        .map(|range| visual_presets_in_range(range.min, range.max, range.step))
+        .unwrap_or_default()
+}
+
+/// Pixel format of the infrared streams
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum InfraredFormat {
+    #[default]
+    Y8,
+    /// Unrectified 16 bits, only offered in some resolutions and frame rates
+    Y16,
+}
+
+impl InfraredFormat {
+    const ALL: [Self; 2] = [Self::Y8, Self::Y16];
 
-        let new_serial_number = CString::new(new_serial_number).expect("Failed to create CString");
-        self.start_pipeline(&new_serial_number, pipeline);
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Y8 => "Y8",
+            Self::Y16 => "Y16",
+        }
+    }
+
+    /// Format, width and frame rate requested, 0 for any
+    fn profile(&self) -> (realsense_rust::kind::Rs2Format, usize, usize) {
+        match self {
+            Self::Y8 => (realsense_rust::kind::Rs2Format::Y8, 640, 30),
+            Self::Y16 => (realsense_rust::kind::Rs2Format::Y16, 0, 0),
+        }
+    }
+}
+
+/// Stages of a device coming back
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Recovery {
+    /// Reset requested at the given time, waiting for the device to disappear
+    Reset(Instant),
+    /// Waiting for the device to re-enumerate
+    Gone,
+    /// Back, but it failed to start at the given time
+    Failed(Instant),
+}
+
+/// A device opened in the viewer, with its own pipeline and stream settings
+struct DeviceSession {
+    serial_number: String,
+    name: String,
+    warning: Option<String>,
+    pipeline: Option<realsense_rust::pipeline::ActivePipeline>,
+    /// Restart streaming when the device comes back
+    recovery: Option<Recovery>,
+    depth_stream_enabled: bool,
+    color_stream_enabled: bool,
+    infrared_1_stream_enabled: bool,
+    infrared_2_stream_enabled: bool,
+    infrared_format: InfraredFormat,
+    accel_stream_enabled: bool,
+    gyro_stream_enabled: bool,
+    global_time_enabled: bool,
+    auto_exposure_enabled: bool,
+    emitter_enabled: bool,
+    emitter_on_off: bool,
+    emitter_always_on: bool,
+    inter_cam_sync_mode: InterCamSyncMode,
+    /// Why the device rejected the sync mode
+    sync_mode_message: Option<String>,
+    /// Applied to the device, `None` when left as the device has it
+    visual_preset: Option<f32>,
+    /// Values the device offers for the visual preset
+    visual_presets: Vec<f32>,
+    advanced_mode: Option<bool>,
+    preset_path: String,
+    preset_message: Option<String>,
+    stats: SessionStats,
+    buffer: FrameBuffer,
+    /// Streams received but not shown
+    hidden_streams: BTreeSet<String>,
+    sync_global_time: SyncAnalyzer,
+    sync_device_time: SyncAnalyzer,
+    extrinsics: ExtrinsicsCache,
+}
+
+impl DeviceSession {
+    fn new(device: &realsense_rust::device::Device) -> Self {
+        Self {
+            serial_number: get_serial_number(device),
+            name: match_info(device, realsense_rust::kind::Rs2CameraInfo::Name),
+            warning: None,
+            pipeline: None,
+            recovery: None,
+            depth_stream_enabled: true,
+            color_stream_enabled: true,
+            infrared_1_stream_enabled: true,
+            infrared_2_stream_enabled: true,
+            infrared_format: InfraredFormat::default(),
+            accel_stream_enabled: true,
+            gyro_stream_enabled: true,
+            global_time_enabled: true,
+            auto_exposure_enabled: true,
+            emitter_enabled: true,
+            emitter_on_off: false,
+            emitter_always_on: false,
+            inter_cam_sync_mode: InterCamSyncMode::Default,
+            sync_mode_message: None,
+            visual_preset: None,
+            visual_presets: Vec::new(),
+            advanced_mode: None,
+            preset_path: "preset.json".to_string(),
+            preset_message: None,
+            stats: SessionStats::default(),
+            buffer: FrameBuffer::default(),
+            hidden_streams: BTreeSet::new(),
+            sync_global_time: SyncAnalyzer::default(),
+            sync_device_time: SyncAnalyzer::default(),
+            extrinsics: ExtrinsicsCache::default(),
+        }
+    }
+
+    /// Starts streaming, failures are left in the warning of the device
+    fn start(&mut self, realsense_ctx: &realsense_rust::context::Context) {
+        if self.pipeline.is_some() {
+            return;
+        }
+        self.warning = None;
+        let result = realsense_rust::pipeline::InactivePipeline::try_from(realsense_ctx)
+            .map_err(anyhow::Error::from)
+            .and_then(|pipeline| {
+                let serial_number = CString::new(self.serial_number.clone())?;
+                self.start_pipeline(&serial_number, pipeline)
+            });
+        if let Err(e) = result {
+            self.warning = Some(format!("Failed to start: {e}"));
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(pipeline) = self.pipeline.take() {
+            pipeline.stop();
+        }
     }
 
     fn update_current_pipeline(&mut self) {
@@ -160,7 +764,9 @@ impl MyApp {
             let pipeline = pipeline.stop();
 
             let serial_number = CString::new(serial_number).expect("Failed to create CString");
-            self.start_pipeline(&serial_number, pipeline);
+            if let Err(e) = self.start_pipeline(&serial_number, pipeline) {
+                self.warning = Some(format!("Failed to start: {e}"));
+            }
         }
     }
 
@@ -168,7 +774,7 @@ impl MyApp {
         &mut self,
         serial_number: &CString,
         pipeline: realsense_rust::pipeline::InactivePipeline,
-    ) {
+    ) -> anyhow::Result<()> {
         if !self.depth_stream_enabled
             && !self.color_stream_enabled
             && !self.infrared_1_stream_enabled
@@ -178,17 +784,20 @@ impl MyApp {
         {
             self.warning = Some("We need at least one stream to start the pipeline".to_string());
             self.pipeline = None;
-            return;
+            return Ok(());
         }
 
         let config = self.create_config(serial_number);
-        let pipeline = pipeline
-            .start(Some(config))
-            .expect("Failed to start pipeline");
+        let pipeline = pipeline.start(Some(config))?;
+        self.extrinsics.clear();
 
+        // Devices without advanced mode fail the query
+        self.advanced_mode = advanced_mode::is_enabled(pipeline.profile().device()).ok();
+        self.visual_presets = visual_presets(pipeline.profile().device());
         self.pipeline = Some(pipeline);
 
         self.update_all_options_in_sensors();
+        Ok(())
     }
 
     /// Config is consumed by start(), we need to create one each time
@@ -235,15 +844,16 @@ impl MyApp {
         }
 
         // Index start at 1, madness
+        let (infrared_format, infrared_width, infrared_fps) = self.infrared_format.profile();
         if self.infrared_1_stream_enabled {
             config
                 .enable_stream(
                     realsense_rust::kind::Rs2StreamKind::Infrared,
                     Some(1),
-                    640,
+                    infrared_width,
                     0,
-                    realsense_rust::kind::Rs2Format::Y8,
-                    30,
+                    infrared_format,
+                    infrared_fps,
                 )
                 .expect("Failed to enable IR1 stream");
         } else {
@@ -257,10 +867,10 @@ impl MyApp {
                 .enable_stream(
                     realsense_rust::kind::Rs2StreamKind::Infrared,
                     Some(2),
-                    640,
+                    infrared_width,
                     0,
-                    realsense_rust::kind::Rs2Format::Y8,
-                    30,
+                    infrared_format,
+                    infrared_fps,
                 )
                 .expect("Failed to enable IR1 stream");
         } else {
@@ -341,13 +951,72 @@ impl MyApp {
                 }
             }
         }
+        if let Some(value) = self.visual_preset {
+            self.update_visual_preset(value);
+        }
+        self.update_inter_cam_sync_mode();
+    }
+
+    /// Sets the visual preset in the sensors supporting it, and keeps it
+    /// only if none of them rejects it
+    fn update_visual_preset(&mut self, value: f32) {
+        let Some(pipeline) = &self.pipeline else {
+            return;
+        };
+        let option = realsense_rust::kind::Rs2Option::VisualPreset;
+        let mut result = Ok(());
+        for mut sensor in pipeline.profile().device().sensors() {
+            if sensor.supports_option(option) {
+                result = result.and(sensor.set_option(option, value));
+            }
+        }
+        match result {
+            Ok(()) => self.visual_preset = Some(value),
+            Err(e) => {
+                let name = visual_preset_name(value);
+                self.preset_message = Some(format!("Failed to set preset {name}: {e}"));
+                self.visual_preset = None;
+            }
+        }
+    }
+
+    /// Sets the sync mode in the sensors supporting it. Some devices and
+    /// firmwares reject some modes, that is shown under the mode.
+    fn update_inter_cam_sync_mode(&mut self) {
+        let Some(pipeline) = &self.pipeline else {
+            return;
+        };
+        let option = realsense_rust::kind::Rs2Option::InterCamSyncMode;
+        let val = self.inter_cam_sync_mode.value();
+        self.sync_mode_message = None;
+        for mut sensor in pipeline.profile().device().sensors() {
+            if !sensor.supports_option(option) {
+                continue;
+            }
+            let supported = sensor
+                .get_option_range(option)
+                .is_some_and(|range| (range.min..=range.max).contains(&val));
+            let result = if supported {
+                sensor.set_option(option, val).map_err(|e| e.to_string())
+            } else {
+                Err("out of range".to_string())
+            };
+            if let Err(e) = result {
+                let mode = self.inter_cam_sync_mode.as_str();
+                self.sync_mode_message = Some(format!("Failed to set sync mode {mode}: {e}"));
+            }
+        }
     }
 
     fn update_sensors(&mut self, option: realsense_rust::kind::Rs2Option, val: bool) {
+        let val = if val { 1.0 } else { 0.0 };
+        self.update_sensors_value(option, val);
+    }
+
+    fn update_sensors_value(&mut self, option: realsense_rust::kind::Rs2Option, val: f32) {
         if let Some(pipeline) = &self.pipeline {
             for mut sensor in pipeline.profile().device().sensors() {
                 if sensor.supports_option(option) {
-                    let val = if val { 1.0 } else { 0.0 };
                     match sensor.set_option(option, val) {
                         Ok(_) => (),
                         Err(e) => println!("Error while setting {:?} to {}: {}", option, val, e),
@@ -357,11 +1026,61 @@ impl MyApp {
         }
     }
 
+    /// Distance between the stereo imagers, in meters
+    fn stereo_baseline(&self) -> Option<f32> {
+        let pipeline = self.pipeline.as_ref()?;
+        let option = realsense_rust::kind::Rs2Option::StereoBaseline;
+        let baseline = pipeline
+            .profile()
+            .device()
+            .sensors()
+            .iter()
+            .find_map(|sensor| sensor.get_option(option))?;
+        // Given in millimeters
+        Some(baseline / 1000.0)
+    }
+
+    fn enable_advanced_mode(&mut self) {
+        // The device resets, so the pipeline can't be used anymore
+        if let Some(pipeline) = self.pipeline.take() {
+            let result = advanced_mode::toggle(pipeline.profile().device(), true);
+            self.preset_message = Some(match result {
+                Ok(_) => "Device is resetting, start it again when it's back".to_string(),
+                Err(e) => format!("Failed to enable advanced mode: {e}"),
+            });
+        }
+    }
+
+    fn load_preset(&mut self) -> anyhow::Result<()> {
+        let pipeline = self
+            .pipeline
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("The device isn't streaming"))?;
+        let json = std::fs::read_to_string(&self.preset_path)?;
+        advanced_mode::load_json(pipeline.profile().device(), &json)?;
+        // Loading parameters leaves the device in a custom preset
+        self.visual_preset = None;
+        Ok(())
+    }
+
+    fn save_preset(&self) -> anyhow::Result<()> {
+        let pipeline = self
+            .pipeline
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("The device isn't streaming"))?;
+        let json = advanced_mode::serialize_json(pipeline.profile().device())?;
+        std::fs::write(&self.preset_path, json)?;
+        Ok(())
+    }
+
     fn get_frames(&mut self) -> Option<realsense_rust::frame::CompositeFrame> {
         if let Some(pipeline) = &mut self.pipeline {
             let timeout = Duration::from_millis(20);
             match pipeline.wait(Some(timeout)) {
-                Ok(frames) => Some(frames),
+                Ok(frames) => {
+                    self.warning = None;
+                    Some(frames)
+                }
                 Err(e) => {
                     self.warning = Some(format!("{e}"));
                     None
@@ -372,206 +1091,652 @@ impl MyApp {
         }
     }
 
+    fn record_stats(&mut self, frames: &realsense_rust::frame::CompositeFrame) {
+        let host_time = host_time_ms();
+        let mut timestamps: Vec<(String, f64, bool)> = Vec::new();
+        for frame in frames.frames_of_type::<realsense_rust::frame::DepthFrame>() {
+            timestamps.push(self.record_frame_stats(&frame, host_time));
+        }
+        for frame in frames.frames_of_type::<realsense_rust::frame::ColorFrame>() {
+            timestamps.push(self.record_frame_stats(&frame, host_time));
+        }
+        for frame in frames.frames_of_type::<realsense_rust::frame::InfraredFrame>() {
+            timestamps.push(self.record_frame_stats(&frame, host_time));
+        }
+        for frame in frames.frames_of_type::<realsense_rust::frame::GyroFrame>() {
+            timestamps.push(self.record_frame_stats(&frame, host_time));
+        }
+        for frame in frames.frames_of_type::<realsense_rust::frame::AccelFrame>() {
+            timestamps.push(self.record_frame_stats(&frame, host_time));
+        }
+
+        // Keep both time domains apart to compare them, by the domain of
+        // every frame, as some streams can't follow the toggle
+        let (global, device): (Vec<_>, Vec<_>) =
+            timestamps.into_iter().partition(|(_, _, global)| *global);
+        for (sync, timestamps) in [
+            (&mut self.sync_global_time, global),
+            (&mut self.sync_device_time, device),
+        ] {
+            let timestamps: Vec<_> = timestamps
+                .into_iter()
+                .map(|(name, timestamp, _)| (name, timestamp))
+                .collect();
+            if !timestamps.is_empty() {
+                sync.record(host_time, &timestamps);
+            }
+        }
+    }
+
+    /// Records the frame in the stats and returns its stream name, timestamp
+    /// and whether the timestamp is in the host clock
+    fn record_frame_stats<T: FrameEx>(&mut self, frame: &T, host_time: f64) -> (String, f64, bool) {
+        let global =
+            frame.timestamp_domain() == realsense_rust::kind::Rs2TimestampDomain::GlobalTime;
+        // Latency only makes sense when the frame is in the host clock
+        let latency = global.then(|| host_time - frame.timestamp());
+        let name = stream_name(frame);
+        self.stats
+            .record(&name, frame.frame_number(), frame.timestamp(), latency);
+        (name, frame.timestamp(), global)
+    }
+
+    /// Shows or hides the streams received, regardless of the ones enabled
+    fn visibility_ui(&mut self, ui: &mut egui::Ui) {
+        let snapshot = match self.buffer.latest() {
+            Some(snapshot) => snapshot,
+            None => return,
+        };
+        ui.horizontal(|_ui| {});
+        ui.label("Visible");
+        for frame in &snapshot.frames {
+            ui.horizontal(|ui| {
+                ui.label(&frame.stream);
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                    let mut visible = !self.hidden_streams.contains(&frame.stream);
+                    if ui.checkbox(&mut visible, "").clicked() {
+                        if visible {
+                            self.hidden_streams.remove(&frame.stream);
+                        } else {
+                            self.hidden_streams.insert(frame.stream.clone());
+                        }
+                    }
+                });
+            });
+        }
+    }
+
+    /// Stream and sensor options of the device, shown in the left panel
+    fn settings_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|_ui| {});
+        ui.label("Streams");
+        ui.horizontal(|ui| {
+            ui.label("Depth");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.depth_stream_enabled, "").clicked() {
+                    self.update_current_pipeline();
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.color_stream_enabled, "").clicked() {
+                    self.update_current_pipeline();
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Infrared 1");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui
+                    .checkbox(&mut self.infrared_1_stream_enabled, "")
+                    .clicked()
+                {
+                    self.update_current_pipeline();
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Infrared 2");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui
+                    .checkbox(&mut self.infrared_2_stream_enabled, "")
+                    .clicked()
+                {
+                    self.update_current_pipeline();
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Infrared Format");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                let mut changed = false;
+                egui::ComboBox::from_id_salt(("infrared_format", &self.serial_number))
+                    .selected_text(self.infrared_format.as_str())
+                    .width(55.0)
+                    .show_ui(ui, |ui| {
+                        for format in InfraredFormat::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.infrared_format,
+                                    format,
+                                    format.as_str(),
+                                )
+                                .changed();
+                        }
+                    });
+                if changed {
+                    self.update_current_pipeline();
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Gyro");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.gyro_stream_enabled, "").clicked() {
+                    self.update_current_pipeline();
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Accel");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.accel_stream_enabled, "").clicked() {
+                    self.update_current_pipeline();
+                }
+            });
+        });
+        ui.horizontal(|_ui| {});
+        ui.horizontal(|_ui| {});
+        ui.horizontal(|ui| {
+            ui.label("Sensor Options");
+            let separator = egui::Separator::default();
+            ui.add(separator.horizontal());
+        });
+        ui.horizontal(|ui| {
+            ui.label("Global Time");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.global_time_enabled, "").clicked() {
+                    self.update_sensors(
+                        realsense_rust::kind::Rs2Option::GlobalTimeEnabled,
+                        self.global_time_enabled,
+                    );
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Auto Exposure");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.auto_exposure_enabled, "").clicked() {
+                    self.update_sensors(
+                        realsense_rust::kind::Rs2Option::EnableAutoExposure,
+                        self.auto_exposure_enabled,
+                    );
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Emitter");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.emitter_enabled, "").clicked() {
+                    self.update_sensors(
+                        realsense_rust::kind::Rs2Option::EmitterEnabled,
+                        self.emitter_enabled,
+                    );
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Emitter On Off");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.emitter_on_off, "").clicked() {
+                    self.update_sensors(
+                        realsense_rust::kind::Rs2Option::EmitterOnOff,
+                        self.emitter_on_off,
+                    );
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Emitter Always");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                if ui.checkbox(&mut self.emitter_always_on, "").clicked() {
+                    self.update_sensors(
+                        realsense_rust::kind::Rs2Option::EmitterAlwaysOn,
+                        self.emitter_always_on,
+                    );
+                }
+            });
+        });
+        ui.horizontal(|ui| {
+            ui.label("Sync Mode");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                let mut changed = false;
+                egui::ComboBox::from_id_salt(("sync_mode", &self.serial_number))
+                    .selected_text(self.inter_cam_sync_mode.as_str())
+                    .width(55.0)
+                    .show_ui(ui, |ui| {
+                        for mode in InterCamSyncMode::ALL {
+                            changed |= ui
+                                .selectable_value(
+                                    &mut self.inter_cam_sync_mode,
+                                    mode,
+                                    mode.as_str(),
+                                )
+                                .changed();
+                        }
+                    });
+                if changed {
+                    self.update_inter_cam_sync_mode();
+                }
+            });
+        });
+        if let Some(msg) = &self.sync_mode_message {
+            ui.colored_label(egui::Color32::YELLOW, msg);
+        }
+        ui.horizontal(|ui| {
+            ui.label("Preset");
+            ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
+                let mut selected = None;
+                let text = self
+                    .visual_preset
+                    .map(visual_preset_name)
+                    .unwrap_or("Device");
+                egui::ComboBox::from_id_salt(("visual_preset", &self.serial_number))
+                    .selected_text(text)
+                    .width(55.0)
+                    .show_ui(ui, |ui| {
+                        for value in &self.visual_presets {
+                            let name = visual_preset_name(*value);
+                            if ui
+                                .selectable_label(self.visual_preset == Some(*value), name)
+                                .clicked()
+                            {
+                                selected = Some(*value);
+                            }
+                        }
+                    });
+                if let Some(value) = selected {
+                    self.update_visual_preset(value);
+                }
+            });
+        });
+
+        // Only D400 devices support advanced mode
+        if let Some(enabled) = self.advanced_mode {
+            ui.horizontal(|_ui| {});
+            ui.horizontal(|ui| {
+                ui.label("Advanced Mode");
+                let separator = egui::Separator::default();
+                ui.add(separator.horizontal());
+            });
+            if !enabled {
+                if ui
+                    .button("Enable")
+                    .on_hover_text("The device resets to switch mode")
+                    .clicked()
+                {
+                    self.enable_advanced_mode();
+                }
+            } else {
+                ui.add(egui::TextEdit::singleline(&mut self.preset_path).desired_width(120.0));
+                if ui.button("Load JSON preset").clicked() {
+                    self.preset_message = Some(match self.load_preset() {
+                        Ok(_) => "Preset loaded".to_string(),
+                        Err(e) => format!("Failed to load preset: {e}"),
+                    });
+                }
+                if ui.button("Save current as JSON").clicked() {
+                    self.preset_message = Some(match self.save_preset() {
+                        Ok(_) => "Preset saved".to_string(),
+                        Err(e) => format!("Failed to save preset: {e}"),
+                    });
+                }
+            }
+        }
+        if let Some(msg) = &self.preset_message {
+            ui.add(egui::Label::new(msg).wrap());
+        }
+    }
+}
+
+impl MyApp {
     fn central_panel(
         &mut self,
         egui_ctx: &egui::Context,
-        frames: Option<realsense_rust::frame::CompositeFrame>,
+        snapshots: Vec<Option<Arc<CompositeSnapshot>>>,
     ) {
         egui::CentralPanel::default().show(egui_ctx, |ui| {
-            // Draw all frames
-            if let Some(frames) = frames {
-                // Distribute all available space
-                let asize = ui.available_size();
-                let (width, height) = (asize[0].round(), asize[1].round());
-                // Account for each frame's margin too
-                let width = if frames.count() > 4 {
-                    width / 3.0 - 11.0
-                } else if frames.count() > 1 {
-                    width / 2.0 - 10.0
-                } else {
-                    width - 5.0
-                } as u32;
-                let height = (if frames.count() > 2 {
-                    height / 2.0
-                } else {
-                    height
-                } - 25.0) as u32;
-                let size = (width, height);
-
-                let mut frame_count = 1 as u8;
-                let columns = if frames.count() > 4 {
-                    3
-                } else if frames.count() > 1 {
-                    2
-                } else {
-                    1
+            // Visible frames of every device, named after the device if several
+            let several = snapshots.iter().flatten().count() > 1;
+            let mut tiles = Vec::new();
+            for (session, snapshot) in self.sessions.iter().zip(&snapshots) {
+                let Some(snapshot) = snapshot else {
+                    continue;
                 };
-
-                egui::Grid::new("frames").show(ui, |ui| {
-                    // Depth frames (either 0 or 1)
-                    let depth_frames = frames.frames_of_type::<realsense_rust::frame::DepthFrame>();
-                    for depth_frame in depth_frames {
-                        let img = depth_frame_to_rgb_img(&depth_frame);
-                        self.add_image_frame_item(egui_ctx, ui, img, size, depth_frame);
-                        frame_count += 1;
-                    }
-
-                    // Color frames (either 0 or 1)
-                    let color_frames = frames.frames_of_type::<realsense_rust::frame::ColorFrame>();
-                    for color_frame in color_frames {
-                        let img = color_frame_to_rgb_img(&color_frame);
-                        self.add_image_frame_item(egui_ctx, ui, img, size, color_frame);
-                        if frame_count % columns == 0 {
-                            ui.end_row();
-                        }
-                        frame_count += 1;
+                for frame in &snapshot.frames {
+                    if session.hidden_streams.contains(&frame.stream) {
+                        continue;
                     }
+                    let title = if several {
+                        format!(
+                            "{} ({} {})",
+                            frame.stream, session.name, session.serial_number
+                        )
+                    } else {
+                        frame.stream.clone()
+                    };
+                    let key = (session.serial_number.clone(), frame.stream.clone());
+                    tiles.push((key, title, frame));
+                }
+            }
+            if tiles.is_empty() {
+                return;
+            }
 
-                    // IR frames (0, 1 or 2)
-                    let ir_frames = frames.frames_of_type::<realsense_rust::frame::InfraredFrame>();
-                    for ir_frame in ir_frames {
-                        let img = infrared_frame_to_rgb_img(&ir_frame);
-                        self.add_image_frame_item(egui_ctx, ui, img, size, ir_frame);
-                        if frame_count % columns == 0 {
-                            ui.end_row();
-                        }
-                        frame_count += 1;
-                    }
+            // A maximized tile takes the whole panel, until restored
+            if ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.maximized = None;
+            }
+            let maximized = tiles
+                .iter()
+                .find(|tile| Some(&tile.0) == self.maximized.as_ref());
+            if let Some((key, title, frame)) = maximized {
+                let rect = ui.available_rect_before_wrap();
+                self.add_frame_item(egui_ctx, ui, key, title, frame, rect);
+                return;
+            }
 
-                    // Gyro frames (either 0 or 1)
-                    let gyro_frames = frames.frames_of_type::<realsense_rust::frame::GyroFrame>();
-                    for gyro_frame in gyro_frames {
-                        let rot_velocity = gyro_frame.rotational_velocity();
-                        self.add_motion_frame_item(
-                            ui,
-                            *rot_velocity,
-                            size,
-                            0.5,
-                            gyro_frame,
-                            "radians/s",
-                        );
-                        if frame_count % columns == 0 {
-                            ui.end_row();
-                        }
-                        frame_count += 1;
-                    }
+            let mut ids: Vec<String> = tiles.iter().map(|tile| tile_id(&tile.0)).collect();
+            self.layout.sort(&mut ids);
+            tiles.sort_by_key(|tile| ids.iter().position(|id| *id == tile_id(&tile.0)));
 
-                    // Accel frames (either 0 or 1)
-                    let accel_frames = frames.frames_of_type::<realsense_rust::frame::AccelFrame>();
-                    for accel_frame in accel_frames {
-                        let accel = accel_frame.acceleration();
-                        self.add_motion_frame_item(ui, *accel, size, 0.1, accel_frame, "m/s²");
-                        if frame_count % columns == 0 {
-                            ui.end_row();
+            if self.layout.mode == LayoutMode::Tabs {
+                let selected = tiles
+                    .iter()
+                    .position(|tile| Some(tile_id(&tile.0)) == self.layout.selected_tab)
+                    .unwrap_or(0);
+                ui.horizontal(|ui| {
+                    for (index, (key, title, _)) in tiles.iter().enumerate() {
+                        if ui.selectable_label(index == selected, title).clicked() {
+                            self.layout.selected_tab = Some(tile_id(key));
                         }
-                        frame_count += 1;
                     }
                 });
+                tiles = vec![tiles.swap_remove(selected)];
+            }
+
+            // Images keep their aspect ratio as far as possible
+            let area = ui.available_rect_before_wrap();
+            let spacing = ui.spacing().item_spacing.x;
+            let aspect_ratio = tiles
+                .iter()
+                .find_map(|(_, _, frame)| image_size(frame))
+                .map(|size| size.x / size.y)
+                .unwrap_or(4.0 / 3.0);
+            let area_size = glam::Vec2::new(area.width(), area.height());
+            let cells = self
+                .layout
+                .cells(tiles.len(), area_size, spacing, aspect_ratio);
+            for ((key, title, frame), cell) in tiles.iter().zip(cells) {
+                let min = area.min + egui::vec2(cell.min.x, cell.min.y);
+                let rect = egui::Rect::from_min_size(min, egui::vec2(cell.size.x, cell.size.y));
+                self.add_frame_item(egui_ctx, ui, key, title, frame, rect);
             }
         });
     }
 
-    fn add_image_frame_item<T: realsense_rust::frame::FrameEx>(
+    /// Adds the tile of a frame in an area of the panel. The title drags it
+    /// to reorder the tiles and double-click maximizes it.
+    fn add_frame_item(
         &mut self,
         egui_ctx: &egui::Context,
         ui: &mut egui::Ui,
-        img: image::RgbImage,
-        size: (u32, u32),
-        frame: T,
+        key: &TileKey,
+        title: &str,
+        frame: &FrameSnapshot,
+        rect: egui::Rect,
     ) {
-        let img = image::DynamicImage::ImageRgb8(img);
-        let img = img
-            .resize_exact(size.0, size.1, image::imageops::FilterType::Lanczos3)
-            .to_rgb8();
-        let img = egui::ColorImage::from_rgb([size.0 as usize, size.1 as usize], img.as_raw());
-        egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            ui.vertical(|ui| {
-                let texture = egui_ctx.load_texture("unnamed", img, Default::default());
-                ui.image(&texture);
-                self.add_timestamp_line(ui, size.0 as f32, frame);
-            });
+        let canvas = egui::Frame::canvas(ui.style());
+        let margin = canvas.total_margin().sum();
+        let spacing = ui.spacing().item_spacing.y;
+        // Title and timestamp lines above and below the content
+        let width = (rect.width() - margin.x).max(0.0);
+        let height = (rect.height() - margin.y - 2.0 * (LINE_HEIGHT + spacing)).max(0.0);
+        let size = (width as u32, height as u32);
+
+        let tile = ui.scope_builder(egui::UiBuilder::new().max_rect(rect), |ui| {
+            canvas
+                .show(ui, |ui| {
+                    ui.vertical(|ui| {
+                        let id = egui::Id::new(("tile", key));
+                        ui.dnd_drag_source(id, key.clone(), |ui| {
+                            ui.allocate_ui_with_layout(
+                                egui::vec2(width, LINE_HEIGHT),
+                                egui::Layout::left_to_right(egui::Align::Center),
+                                |ui| {
+                                    let label =
+                                        egui::Label::new(egui::RichText::new(title).strong());
+                                    ui.add(label.wrap_mode(egui::TextWrapMode::Truncate));
+                                },
+                            );
+                        });
+                        match &frame.data {
+                            SnapshotData::Gyro(rot_velocity) => self.add_motion_frame_item(
+                                ui,
+                                *rot_velocity,
+                                size,
+                                0.5,
+                                frame,
+                                "radians/s",
+                            ),
+                            SnapshotData::Accel(accel) => {
+                                self.add_motion_frame_item(ui, *accel, size, 0.1, frame, "m/s²")
+                            }
+                            _ => self.add_image_frame_item(egui_ctx, ui, size, frame, key),
+                        }
+                    })
+                    .inner
+                })
+                .inner
+        });
+        let response = tile.inner;
+
+        // Dropping a tile on another one takes its place
+        if let Some(dragged) = tile.response.dnd_release_payload::<TileKey>() {
+            self.layout.move_tile(&tile_id(&dragged), &tile_id(key));
+        }
+
+        let maximized = self.maximized.as_ref() == Some(key);
+        if response.double_clicked() {
+            self.maximized = if maximized { None } else { Some(key.clone()) };
+        }
+        response.context_menu(|ui| {
+            if ui
+                .button(if maximized { "Restore" } else { "Maximize" })
+                .clicked()
+            {
+                self.maximized = if maximized { None } else { Some(key.clone()) };
+                ui.close_menu();
+            }
+            if ui.button("Reset zoom").clicked() {
+                self.tile_views.remove(key);
+                ui.close_menu();
+            }
         });
     }
 
-    fn add_timestamp_line<T: realsense_rust::frame::FrameEx>(
+    /// Draws an image that zooms with the mouse wheel and pans by dragging.
+    /// Dragging with the right button selects the ROI of the depth quality.
+    fn add_image_frame_item(
         &mut self,
+        egui_ctx: &egui::Context,
         ui: &mut egui::Ui,
-        width: f32,
-        frame: T,
-    ) {
+        size: (u32, u32),
+        frame: &FrameSnapshot,
+        key: &TileKey,
+    ) -> egui::Response {
+        let img = frame.to_rgb_image().expect("Frame is not an image");
+        let image_size = glam::Vec2::new(img.width() as f32, img.height() as f32);
+        let img =
+            egui::ColorImage::from_rgb([img.width() as usize, img.height() as usize], img.as_raw());
+        let mut view = self.tile_views.get(key).copied().unwrap_or_default();
+        // Sharp pixels once zoomed in
+        let options = if view.zoom > 1.0 {
+            egui::TextureOptions::NEAREST
+        } else {
+            egui::TextureOptions::LINEAR
+        };
+        let texture = egui_ctx.load_texture("unnamed", img, options);
+        let (rect, response) = ui.allocate_exact_size(
+            egui::vec2(size.0 as f32, size.1 as f32),
+            egui::Sense::click_and_drag(),
+        );
+        if let Some(pointer) = response.hover_pos() {
+            let scroll = ui.input(|i| i.smooth_scroll_delta.y);
+            if scroll != 0.0 {
+                view.zoom_at((scroll / 200.0).exp(), tile_position(rect, pointer));
+            }
+        }
+        if response.dragged_by(egui::PointerButton::Primary) {
+            let delta = response.drag_delta() / rect.size();
+            view.pan(glam::Vec2::new(delta.x, delta.y));
+        }
+        let selecting_roi =
+            self.depth_quality_window_open && matches!(frame.data, SnapshotData::Depth { .. });
+        if selecting_roi && response.dragged_by(egui::PointerButton::Secondary) {
+            if let Some(pointer) = response.interact_pointer_pos() {
+                let pixel = view.pixel_at(tile_position(rect, pointer), image_size);
+                let pixel = pixel.clamp(glam::Vec2::ZERO, image_size - 1.0);
+                let pixel = (pixel.x as usize, pixel.y as usize);
+                let start = *self.roi_drag_start.get_or_insert(pixel);
+                self.quality_roi = Some((key.clone(), Roi::from_corners(start, pixel)));
+            }
+        }
+        // The measurements of the previous ROI don't compare
+        if response.drag_stopped() && self.roi_drag_start.take().is_some() {
+            self.quality.clear();
+        }
+
+        let (min, max) = view.visible();
+        let uv = egui::Rect::from_min_max(egui::pos2(min.x, min.y), egui::pos2(max.x, max.y));
+        let painter = ui.painter_at(rect);
+        painter.image(texture.id(), rect, uv, egui::Color32::WHITE);
+        draw_pixel_grid(&painter, rect, &view, image_size);
+        if let Some((_, roi)) = self
+            .quality_roi
+            .as_ref()
+            .filter(|(roi_key, _)| selecting_roi && roi_key == key)
+        {
+            let to_screen = |x: usize, y: usize| {
+                let position = (glam::Vec2::new(x as f32, y as f32) / image_size - min) * view.zoom;
+                rect.min + egui::vec2(position.x * rect.width(), position.y * rect.height())
+            };
+            let roi_rect = egui::Rect::from_min_max(
+                to_screen(roi.x, roi.y),
+                to_screen(roi.x + roi.width, roi.y + roi.height),
+            );
+            let stroke = egui::Stroke::new(2.0, egui::Color32::YELLOW);
+            painter.rect_stroke(roi_rect, 0.0, stroke, egui::StrokeKind::Inside);
+        }
+
+        // Zoom and value of the pixel under the cursor
+        if let Some(pointer) = response.hover_pos().filter(|_| view.zoom > 1.0) {
+            let pixel = view.pixel_at(tile_position(rect, pointer), image_size);
+            let (x, y) = (pixel.x as usize, pixel.y as usize);
+            painter.text(
+                rect.left_top() + egui::vec2(4.0, 4.0),
+                egui::Align2::LEFT_TOP,
+                format!("{:.1}x  ({x}, {y}) {}", view.zoom, pixel_value(frame, x, y)),
+                egui::FontId::monospace(12.0),
+                egui::Color32::WHITE,
+            );
+        }
+
+        self.add_timestamp_line(ui, size.0 as f32, frame);
+        self.tile_views.insert(key.clone(), view);
+        response
+    }
+
+    fn add_timestamp_line(&mut self, ui: &mut egui::Ui, width: f32, frame: &FrameSnapshot) {
         ui.allocate_ui_with_layout(
-            egui::Vec2::new(width, 15.0),
+            egui::Vec2::new(width, LINE_HEIGHT),
             egui::Layout::left_to_right(egui::Align::Max),
             |ui| {
-                let ts = frame.timestamp();
-                let ts_domain = frame.timestamp_domain().as_str();
+                let ts = frame.timestamp;
+                let ts_domain = &frame.timestamp_domain;
                 let label = egui::Label::new(format!("{ts_domain}: {ts:.2}"));
                 ui.add(label.wrap_mode(egui::TextWrapMode::Truncate));
             },
         );
     }
 
-    fn add_motion_frame_item<T: realsense_rust::frame::FrameEx>(
+    fn add_motion_frame_item(
         &mut self,
         ui: &mut egui::Ui,
         data: [f32; 3],
         size: (u32, u32),
         scale: f32,
-        frame: T,
+        frame: &FrameSnapshot,
         units: &str,
-    ) {
-        egui::Frame::canvas(ui.style()).show(ui, |ui| {
-            ui.vertical(|ui| {
-                // Account for motion values
-                let size = (size.0, size.1 - 18);
-                let (area, _response) = ui.allocate_at_least(
-                    egui::vec2(size.0 as f32, size.1 as f32),
-                    egui::Sense::hover(),
-                );
-                let painter = ui.painter();
-                painter.rect_filled(area, 0.0, egui::Color32::BLACK);
-                let colors = [
-                    egui::Color32::RED,
-                    egui::Color32::GREEN,
-                    egui::Color32::BLUE,
-                ];
-                let bar_width = size.0 as f32 / 7.0;
-                let bar_max_height = size.1 as f32 / 2.0;
-                let mut left_corner =
-                    egui::Pos2::new(area.min.x + bar_width, area.min.y + bar_max_height);
-                for (component, color) in data.into_iter().zip(colors.into_iter()) {
-                    let height = component * bar_max_height * scale;
-                    // Clamp to limits of area's height
-                    let height = height.clamp(-bar_max_height, bar_max_height);
-                    let right_corner =
-                        egui::Pos2::new(left_corner.x + bar_width, left_corner.y + height);
-                    painter.rect_filled(
-                        egui::Rect::from_two_pos(left_corner, right_corner),
-                        2.0,
-                        color,
-                    );
-                    left_corner.x = left_corner.x + bar_width * 2.0;
-                }
-                // Horizontal line at origin
-                let thickness = 0.5;
-                let y = area.min.y + size.1 as f32 / 2.0;
-                let left_corner = egui::Pos2::new(area.min.x, y - thickness / 2.0);
-                let right_corner = egui::Pos2::new(area.max.x, y + thickness / 2.0);
+    ) -> egui::Response {
+        let spacing = ui.spacing().item_spacing.y;
+        ui.vertical(|ui| {
+            // Account for motion values
+            let size = (
+                size.0,
+                size.1.saturating_sub((LINE_HEIGHT + spacing) as u32),
+            );
+            let (area, response) = ui.allocate_at_least(
+                egui::vec2(size.0 as f32, size.1 as f32),
+                egui::Sense::click(),
+            );
+            let painter = ui.painter();
+            painter.rect_filled(area, 0.0, egui::Color32::BLACK);
+            let colors = [
+                egui::Color32::RED,
+                egui::Color32::GREEN,
+                egui::Color32::BLUE,
+            ];
+            let bar_width = size.0 as f32 / 7.0;
+            let bar_max_height = size.1 as f32 / 2.0;
+            let mut left_corner =
+                egui::Pos2::new(area.min.x + bar_width, area.min.y + bar_max_height);
+            for (component, color) in data.into_iter().zip(colors.into_iter()) {
+                let height = component * bar_max_height * scale;
+                // Clamp to limits of area's height
+                let height = height.clamp(-bar_max_height, bar_max_height);
+                let right_corner =
+                    egui::Pos2::new(left_corner.x + bar_width, left_corner.y + height);
                 painter.rect_filled(
                     egui::Rect::from_two_pos(left_corner, right_corner),
-                    0.0,
-                    egui::Color32::DARK_GRAY,
+                    2.0,
+                    color,
                 );
-
-                self.add_components_line(ui, size.0 as f32, data, units);
-                self.add_timestamp_line(ui, size.0 as f32, frame);
-            });
-        });
+                left_corner.x += bar_width * 2.0;
+            }
+            // Horizontal line at origin
+            let thickness = 0.5;
+            let y = area.min.y + size.1 as f32 / 2.0;
+            let left_corner = egui::Pos2::new(area.min.x, y - thickness / 2.0);
+            let right_corner = egui::Pos2::new(area.max.x, y + thickness / 2.0);
+            painter.rect_filled(
+                egui::Rect::from_two_pos(left_corner, right_corner),
+                0.0,
+                egui::Color32::DARK_GRAY,
+            );
+
+            self.add_components_line(ui, size.0 as f32, data, units);
+            self.add_timestamp_line(ui, size.0 as f32, frame);
+            response
+        })
+        .inner
     }
 
     fn add_components_line(&mut self, ui: &mut egui::Ui, width: f32, data: [f32; 3], units: &str) {
         ui.allocate_ui_with_layout(
-            egui::Vec2::new(width, 15.0),
+            egui::Vec2::new(width, LINE_HEIGHT),
             egui::Layout::left_to_right(egui::Align::Max),
             |ui| {
                 let label = egui::Label::new(format!(
@@ -583,136 +1748,197 @@ impl MyApp {
         );
     }
 
+    fn playback_panel(&mut self, egui_ctx: &egui::Context) {
+        // Space pauses, arrows step and Ctrl+S takes a snapshot, unless typing
+        if !egui_ctx.wants_keyboard_input() {
+            let (space, left, right, snapshot) = egui_ctx.input_mut(|i| {
+                (
+                    i.key_pressed(egui::Key::Space),
+                    i.key_pressed(egui::Key::ArrowLeft),
+                    i.key_pressed(egui::Key::ArrowRight),
+                    i.consume_key(egui::Modifiers::COMMAND, egui::Key::S),
+                )
+            });
+            if snapshot {
+                self.take_snapshot();
+            }
+            if space {
+                self.set_paused(!self.paused);
+            }
+            if self.paused && (left || right) {
+                self.step(right);
+            }
+        }
+
+        egui::TopBottomPanel::top("playback_panel").show(egui_ctx, |ui| {
+            ui.horizontal(|ui| {
+                let label = if self.paused { "▶ Live" } else { "⏸ Pause" };
+                if ui.button(label).on_hover_text("Space").clicked() {
+                    self.set_paused(!self.paused);
+                }
+                ui.add_enabled_ui(self.paused, |ui| {
+                    if ui.button("⏴").on_hover_text("Previous frame (←)").clicked() {
+                        self.step(false);
+                    }
+                    if ui.button("⏵").on_hover_text("Next frame (→)").clicked() {
+                        self.step(true);
+                    }
+                    // Time relative to the newest frame
+                    if let Some((oldest, newest)) = self.buffer_range() {
+                        let mut offset = (self.cursor - newest) / 1000.0;
+                        let slider =
+                            egui::Slider::new(&mut offset, (oldest - newest) / 1000.0..=0.0)
+                                .suffix(" s")
+                                .fixed_decimals(2);
+                        if ui.add(slider).changed() {
+                            self.cursor = newest + offset * 1000.0;
+                        }
+                    }
+                });
+
+                ui.separator();
+                let (count, size) = self.sessions.iter().fold((0, 0), |(count, size), session| {
+                    (
+                        count + session.buffer.len(),
+                        size + session.buffer.size_bytes(),
+                    )
+                });
+                ui.label(format!("Buffer: {count} frames, {} MB", size / 1_000_000));
+                let duration = egui::DragValue::new(&mut self.buffer_duration)
+                    .range(1.0..=60.0)
+                    .suffix(" s");
+                if ui.add(duration).on_hover_text("Buffer length").changed() {
+                    for session in &mut self.sessions {
+                        session.buffer.set_duration(self.buffer_duration);
+                    }
+                }
+                let memory = egui::DragValue::new(&mut self.buffer_memory)
+                    .range(100..=16_000)
+                    .speed(10)
+                    .suffix(" MB");
+                let memory = ui.add(memory);
+                if memory.on_hover_text("Buffer memory per device").changed() {
+                    for session in &mut self.sessions {
+                        session.buffer.set_max_bytes(self.buffer_memory * 1_000_000);
+                    }
+                }
+                if ui.button("Save buffer").clicked() {
+                    self.export_message = Some(match self.save_buffers() {
+                        Ok(dir) => format!("Saved to {dir}"),
+                        Err(e) => format!("Failed to save buffer: {e}"),
+                    });
+                }
+                if ui.button("📷 Snapshot").on_hover_text("Ctrl+S").clicked() {
+                    self.take_snapshot();
+                }
+                if let Some(msg) = &self.export_message {
+                    ui.label(msg);
+                }
+            });
+        });
+    }
+
+    fn take_snapshot(&mut self) {
+        self.export_message = Some(match self.save_snapshot() {
+            Ok(dir) => format!("Saved to {dir}"),
+            Err(e) => format!("Failed to save snapshot: {e}"),
+        });
+    }
+
     fn left_panel(&mut self, egui_ctx: &egui::Context) {
         egui::SidePanel::left("left_panel")
             .exact_width(130.0)
             .show(egui_ctx, |ui| {
+                match self.sessions.get_mut(self.selected_session) {
+                    Some(session) => {
+                        session.settings_ui(ui);
+                        session.visibility_ui(ui);
+                    }
+                    None => {
+                        ui.horizontal(|_ui| {});
+                        ui.label("No device started");
+                    }
+                }
                 ui.horizontal(|_ui| {});
-                ui.label("Streams");
-                ui.horizontal(|ui| {
-                    ui.label("Depth");
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.depth_stream_enabled, "").clicked() {
-                            self.update_current_pipeline();
-                        }
-                    });
-                });
                 ui.horizontal(|ui| {
-                    ui.label("Color");
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.color_stream_enabled, "").clicked() {
-                            self.update_current_pipeline();
-                        }
-                    });
-                });
-                ui.horizontal(|ui| {
-                    ui.label("Infrared 1");
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui
-                            .checkbox(&mut self.infrared_1_stream_enabled, "")
-                            .clicked()
-                        {
-                            self.update_current_pipeline();
-                        }
-                    });
+                    ui.label("Tools");
+                    let separator = egui::Separator::default();
+                    ui.add(separator.horizontal());
                 });
                 ui.horizontal(|ui| {
-                    ui.label("Infrared 2");
+                    ui.label("Sync Analysis");
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui
-                            .checkbox(&mut self.infrared_2_stream_enabled, "")
-                            .clicked()
-                        {
-                            self.update_current_pipeline();
-                        }
+                        ui.checkbox(&mut self.sync_window_open, "");
                     });
                 });
                 ui.horizontal(|ui| {
-                    ui.label("Gyro");
+                    ui.label("Inter-Cam Sync");
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.gyro_stream_enabled, "").clicked() {
-                            self.update_current_pipeline();
-                        }
+                        ui.checkbox(&mut self.inter_cam_window_open, "");
                     });
                 });
                 ui.horizontal(|ui| {
-                    ui.label("Accel");
+                    ui.label("Depth Quality");
                     ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.accel_stream_enabled, "").clicked() {
-                            self.update_current_pipeline();
-                        }
+                        ui.checkbox(&mut self.depth_quality_window_open, "");
                     });
                 });
                 ui.horizontal(|_ui| {});
-                ui.horizontal(|_ui| {});
                 ui.horizontal(|ui| {
-                    ui.label("Sensor Options");
+                    ui.label("Layout");
                     let separator = egui::Separator::default();
                     ui.add(separator.horizontal());
                 });
-                ui.horizontal(|ui| {
-                    ui.label("Global Time");
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.global_time_enabled, "").clicked() {
-                            self.update_sensors(
-                                realsense_rust::kind::Rs2Option::GlobalTimeEnabled,
-                                self.global_time_enabled,
-                            );
+                egui::ComboBox::from_id_salt("layout_mode")
+                    .selected_text(self.layout.mode.as_str())
+                    .show_ui(ui, |ui| {
+                        for mode in LayoutMode::ALL {
+                            ui.selectable_value(&mut self.layout.mode, mode, mode.as_str());
                         }
                     });
-                });
+                ui.horizontal(|_ui| {});
                 ui.horizontal(|ui| {
-                    ui.label("Auto Exposure");
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.auto_exposure_enabled, "").clicked() {
-                            self.update_sensors(
-                                realsense_rust::kind::Rs2Option::EnableAutoExposure,
-                                self.auto_exposure_enabled,
-                            );
-                        }
-                    });
+                    ui.label("Point Cloud");
+                    let separator = egui::Separator::default();
+                    ui.add(separator.horizontal());
                 });
-                ui.horizontal(|ui| {
-                    ui.label("Emitter");
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.emitter_enabled, "").clicked() {
-                            self.update_sensors(
-                                realsense_rust::kind::Rs2Option::EmitterEnabled,
-                                self.emitter_enabled,
-                            );
+                egui::ComboBox::from_id_salt("point_cloud_format")
+                    .selected_text(self.point_cloud_format.as_str())
+                    .show_ui(ui, |ui| {
+                        for format in PointCloudFormat::ALL {
+                            let format_str = format.as_str();
+                            ui.selectable_value(&mut self.point_cloud_format, format, format_str);
                         }
                     });
-                });
-                ui.horizontal(|ui| {
-                    ui.label("Emitter On Off");
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.emitter_on_off, "").clicked() {
-                            self.update_sensors(
-                                realsense_rust::kind::Rs2Option::EmitterOnOff,
-                                self.emitter_on_off,
+                egui::ComboBox::from_id_salt("point_cloud_texture")
+                    .selected_text(format!("Texture: {}", self.point_cloud_texture.as_str()))
+                    .show_ui(ui, |ui| {
+                        for texture in TextureSource::ALL {
+                            let texture_str = texture.as_str();
+                            ui.selectable_value(
+                                &mut self.point_cloud_texture,
+                                texture,
+                                texture_str,
                             );
                         }
                     });
-                });
-                ui.horizontal(|ui| {
-                    ui.label("Emitter Always");
-                    ui.with_layout(egui::Layout::right_to_left(egui::Align::Min), |ui| {
-                        if ui.checkbox(&mut self.emitter_always_on, "").clicked() {
-                            self.update_sensors(
-                                realsense_rust::kind::Rs2Option::EmitterAlwaysOn,
-                                self.emitter_always_on,
-                            );
-                        }
+                if ui.button("Export").clicked() {
+                    self.export_message = Some(match self.export_point_clouds() {
+                        Ok(paths) => format!("Saved {paths}"),
+                        Err(e) => format!("Failed to export point cloud: {e}"),
                     });
-                });
+                }
             });
     }
 
     fn right_panel(
         &mut self,
         egui_ctx: &egui::Context,
-        frames: &Option<realsense_rust::frame::CompositeFrame>,
+        snapshots: &[Option<Arc<CompositeSnapshot>>],
     ) {
+        let snapshot = snapshots
+            .get(self.selected_session)
+            .and_then(Option::as_ref);
         egui::SidePanel::right("right_panel")
             .min_width(130.0)
             .max_width(280.0)
@@ -722,25 +1948,28 @@ impl MyApp {
 
                 // General Info
                 ui.label("General Info");
-                let frames_count = if let Some(frames) = frames {
-                    frames.count()
+                if let Some(session) = self.sessions.get(self.selected_session) {
+                    ui.label(format!("Device: {}", session.serial_number));
+                }
+                let frames_count = if let Some(snapshot) = snapshot {
+                    snapshot.frames.len()
                 } else {
                     0
                 };
                 ui.label(format!("Frames received: {frames_count}"));
-                let streams_count = if let Some(pipeline) = &self.pipeline {
+                let streams_count = if let Some(pipeline) = self.selected_pipeline() {
                     pipeline.profile().streams().len()
                 } else {
                     0
                 };
                 ui.label(format!("Streams: {streams_count}"));
-                let sensors_count = if let Some(pipeline) = &self.pipeline {
+                let sensors_count = if let Some(pipeline) = self.selected_pipeline() {
                     pipeline.profile().device().sensors().len()
                 } else {
                     0
                 };
                 ui.label(format!("Sensors: {sensors_count}"));
-                if let Some(pipeline) = &self.pipeline {
+                if let Some(pipeline) = self.selected_pipeline() {
                     for sensor in pipeline.profile().device().sensors() {
                         let name = sensor.info(realsense_rust::kind::Rs2CameraInfo::Name);
                         if let Some(name) = name {
@@ -751,6 +1980,41 @@ impl MyApp {
                 }
                 ui.horizontal(|_ui| {});
 
+                // Streams Stats
+                ui.horizontal(|ui| {
+                    ui.label("Streams Stats");
+                    let separator = egui::Separator::default();
+                    ui.add(separator.horizontal());
+                });
+                self.stats_table(ui);
+                ui.horizontal(|_ui| {});
+
+                // Frame Metadata, of the frames shown
+                ui.horizontal(|ui| {
+                    ui.label("Frame Metadata");
+                    let separator = egui::Separator::default();
+                    ui.add(separator.horizontal());
+                });
+                if let Some(snapshot) = snapshot {
+                    for frame in &snapshot.frames {
+                        ui.collapsing(&frame.stream, |ui| {
+                            egui::Grid::new(("frame_metadata", &frame.stream))
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    ui.label("Frame Number");
+                                    ui.label(format!("{}", frame.frame_number));
+                                    ui.end_row();
+                                    for (key, value) in &frame.metadata {
+                                        ui.label(key);
+                                        ui.label(format!("{value}"));
+                                        ui.end_row();
+                                    }
+                                });
+                        });
+                    }
+                }
+                ui.horizontal(|_ui| {});
+
                 // Streams Info
                 ui.horizontal(|ui| {
                     ui.label("Streams Info");
@@ -758,7 +2022,7 @@ impl MyApp {
                     ui.add(separator.horizontal());
                 });
 
-                if let Some(pipeline) = &self.pipeline {
+                if let Some(pipeline) = self.selected_pipeline() {
                     egui::ScrollArea::vertical()
                         .auto_shrink([false, true])
                         .show(ui, |ui| {
@@ -930,6 +2194,8 @@ impl MyApp {
                                                 realsense_rust::kind::Rs2Option::EmitterEnabled,
                                                 realsense_rust::kind::Rs2Option::EmitterOnOff,
                                                 realsense_rust::kind::Rs2Option::EmitterAlwaysOn,
+                                                realsense_rust::kind::Rs2Option::InterCamSyncMode,
+                                                realsense_rust::kind::Rs2Option::VisualPreset,
                                             ] {
                                                 ui.label(format!("{:?}", option));
                                                 if let Some(value) = sensor.get_option(option) {
@@ -948,35 +2214,304 @@ impl MyApp {
             });
     }
 
-    fn bottom_panel(
-        &mut self,
-        egui_ctx: &egui::Context,
-        devices: Vec<realsense_rust::device::Device>,
-    ) {
-        egui::TopBottomPanel::bottom("bottom_panel").show(egui_ctx, |ui| {
-            // Select device
-            ui.horizontal(|ui| {
-                ui.label("Select device: ");
-                let selected_dev_repr = if usize::from(self.dev_index) < devices.len() {
-                    get_dev_repr(self.dev_index, &devices[self.dev_index as usize])
-                } else {
-                    String::default()
+    fn stats_table(&mut self, ui: &mut egui::Ui) {
+        egui::Grid::new("streams_stats")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.label(egui::RichText::new("Stream").strong());
+                ui.label(egui::RichText::new("FPS").strong());
+                ui.label("");
+                ui.label(egui::RichText::new("Jitter").strong())
+                    .on_hover_text("Deviation of the inter-frame interval [ms]");
+                ui.label(egui::RichText::new("Drops").strong())
+                    .on_hover_text("Frames missing from the frame number sequence");
+                ui.label(egui::RichText::new("Latency").strong())
+                    .on_hover_text("Host time minus frame timestamp [ms], needs Global Time");
+                ui.end_row();
+
+                let streams = self
+                    .sessions
+                    .get(self.selected_session)
+                    .map(|session| &session.stats.streams);
+                for (name, stats) in streams.into_iter().flatten() {
+                    ui.label(name);
+                    ui.label(format!("{:.1}", stats.fps()));
+                    sparkline(
+                        ui,
+                        stats.fps_history.iter().copied(),
+                        egui::vec2(50.0, 14.0),
+                    );
+                    ui.label(format!("{:.2}", stats.jitter()));
+                    ui.label(format!("{}", stats.dropped));
+                    if let Some(latency) = stats.latency() {
+                        ui.label(format!("{latency:.1}"));
+                    } else {
+                        ui.label("N/A");
+                    }
+                    ui.end_row();
+                }
+            });
+        ui.horizontal(|ui| {
+            if ui.button("Export JSON").clicked() {
+                self.stats_export = Some(match self.export_stats() {
+                    Ok(path) => format!("Exported to {path}"),
+                    Err(e) => format!("Failed to export stats: {e}"),
+                });
+            }
+            if ui.button("Reset").clicked() {
+                if let Some(session) = self.sessions.get_mut(self.selected_session) {
+                    session.stats.clear();
+                }
+                self.stats_export = None;
+            }
+        });
+        if let Some(msg) = &self.stats_export {
+            ui.add(egui::Label::new(msg).wrap_mode(egui::TextWrapMode::Truncate));
+        }
+    }
+
+    fn sync_window(&mut self, egui_ctx: &egui::Context) {
+        let mut open = self.sync_window_open;
+        let session = self.sessions.get_mut(self.selected_session);
+        egui::Window::new("Sync Analysis")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(egui_ctx, |ui| {
+                let session = match session {
+                    Some(session) => session,
+                    None => {
+                        ui.label("No device started");
+                        return;
+                    }
                 };
-                egui::ComboBox::from_label("")
-                    .selected_text(&selected_dev_repr)
-                    .show_ui(ui, |ui| {
-                        for (i, dev) in devices.iter().enumerate() {
-                            let dev_repr = get_dev_repr(i as u8, dev);
-                            if ui
-                                .selectable_label(dev_repr == selected_dev_repr, dev_repr)
-                                .clicked()
-                            {
-                                self.dev_index = i as u8;
+                ui.label(format!("Device: {}", session.serial_number));
+                ui.label(
+                    "Offsets between stream timestamps in each frameset, second minus first \
+                     [ms]. Drift is the slope of the offset over time [ms/s].",
+                );
+                if ui.button("Reset").clicked() {
+                    session.sync_global_time.clear();
+                    session.sync_device_time.clear();
+                }
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    for (global_time, sync) in [
+                        (true, &session.sync_global_time),
+                        (false, &session.sync_device_time),
+                    ] {
+                        let title = if global_time {
+                            "Global Time On"
+                        } else {
+                            "Global Time Off"
+                        };
+                        let title = if global_time == session.global_time_enabled {
+                            format!("{title} (current)")
+                        } else {
+                            title.to_string()
+                        };
+                        ui.horizontal(|ui| {
+                            ui.label(egui::RichText::new(title).strong());
+                            let separator = egui::Separator::default();
+                            ui.add(separator.horizontal());
+                        });
+                        if sync.pairs.is_empty() {
+                            ui.label("No data");
+                            continue;
+                        }
+                        egui::Grid::new(format!("sync_{global_time}"))
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for header in [
+                                    "Pair",
+                                    "Mean",
+                                    "Std",
+                                    "Min",
+                                    "Max",
+                                    "Drift",
+                                    "Histogram",
+                                    "Offset",
+                                ] {
+                                    ui.label(egui::RichText::new(header).strong());
+                                }
+                                ui.end_row();
+                                for ((first, second), pair) in &sync.pairs {
+                                    ui.label(format!("{second} - {first}"));
+                                    add_pair_stats_row(ui, pair);
+                                    ui.end_row();
+                                }
+                            });
+                    }
+                });
+            });
+        self.sync_window_open = open;
+    }
+
+    fn depth_quality_window(&mut self, egui_ctx: &egui::Context) {
+        let mut open = self.depth_quality_window_open;
+        egui::Window::new("Depth Quality")
+            .open(&mut open)
+            .default_width(420.0)
+            .show(egui_ctx, |ui| {
+                ui.label(
+                    "Point the camera at a flat target and drag with the right button on the \
+                     depth image to select the ROI.",
+                );
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.ground_truth_enabled, "Ground truth");
+                    let ground_truth = egui::DragValue::new(&mut self.ground_truth)
+                        .range(0.1..=20.0)
+                        .speed(0.001)
+                        .suffix(" m");
+                    ui.add_enabled(self.ground_truth_enabled, ground_truth);
+                });
+                if let Some((key, roi)) = &self.quality_roi {
+                    ui.label(format!(
+                        "ROI: {}x{} at ({}, {}) of {}",
+                        roi.width,
+                        roi.height,
+                        roi.x,
+                        roi.y,
+                        tile_id(key)
+                    ));
+                }
+                ui.horizontal(|ui| {
+                    if ui.button("Reset").clicked() {
+                        self.quality.clear();
+                    }
+                    if ui.button("Export CSV").clicked() {
+                        self.quality_export_message = Some(match self.export_depth_quality() {
+                            Ok(path) => format!("Saved {path}"),
+                            Err(e) => format!("Failed to export depth quality: {e}"),
+                        });
+                    }
+                });
+                if let Some(msg) = &self.quality_export_message {
+                    ui.label(msg);
+                }
+                if let Some(msg) = &self.quality_message {
+                    ui.label(msg);
+                }
+                let Some(latest) = self.quality.latest().copied() else {
+                    ui.label("No data");
+                    return;
+                };
+                type Metric = fn(&DepthQuality) -> Option<f32>;
+                let metrics: [(&str, Metric, &str); 7] = [
+                    ("Fill rate", |q| Some(q.fill_rate), "%"),
+                    ("Distance", |q| Some(q.distance * 1000.0), "mm"),
+                    ("Angle", |q| Some(q.angle), "°"),
+                    ("Plane fit RMS", |q| Some(q.plane_fit_rms * 1000.0), "mm"),
+                    (
+                        "Plane fit RMS",
+                        |q| Some(q.plane_fit_rms / q.distance * 100.0),
+                        "%",
+                    ),
+                    ("Subpixel RMS", |q| q.subpixel_rms, "px"),
+                    ("Z accuracy", |q| q.z_accuracy, "%"),
+                ];
+                egui::Grid::new("depth_quality")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for header in ["Metric", "Value", "History"] {
+                            ui.label(egui::RichText::new(header).strong());
+                        }
+                        ui.end_row();
+                        for (name, metric, unit) in metrics {
+                            ui.label(name);
+                            ui.label(match metric(&latest) {
+                                Some(value) => format!("{value:.2} {unit}"),
+                                None => "-".to_string(),
+                            });
+                            let history = self.quality.recent(metric);
+                            sparkline(ui, history.into_iter(), egui::vec2(160.0, 24.0));
+                            ui.end_row();
+                        }
+                    });
+            });
+        self.depth_quality_window_open = open;
+    }
+
+    fn inter_cam_window(&mut self, egui_ctx: &egui::Context) {
+        let mut open = self.inter_cam_window_open;
+        let reference = self.inter_cam_reference();
+        egui::Window::new("Inter-Camera Sync")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(egui_ctx, |ui| {
+                ui.label(
+                    "Skew is the timestamp of each device minus the reference one [ms]. \
+                     Enable Global Time in every device so they share the host clock. The \
+                     frame counter offset stays constant while the devices are locked.",
+                );
+                ui.horizontal(|ui| {
+                    ui.label("Reference:");
+                    egui::ComboBox::from_id_salt("inter_cam_reference")
+                        .selected_text(reference.clone().unwrap_or_default())
+                        .show_ui(ui, |ui| {
+                            for session in &self.sessions {
+                                let serial_number = Some(session.serial_number.clone());
+                                let label = format!(
+                                    "{} ({})",
+                                    session.serial_number,
+                                    session.inter_cam_sync_mode.as_str()
+                                );
+                                if ui
+                                    .selectable_label(reference == serial_number, label)
+                                    .clicked()
+                                {
+                                    self.inter_cam_reference = serial_number;
+                                    self.sync_verifier.clear();
+                                }
                             }
+                        });
+                    if ui.button("Reset").clicked() {
+                        self.sync_verifier.clear();
+                    }
+                });
+                if self.sync_verifier.devices.is_empty() {
+                    ui.label("Start at least two devices");
+                    return;
+                }
+                egui::Grid::new("inter_cam_sync")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for header in [
+                            "Device", "Mode", "Mean", "Std", "Min", "Max", "Counter", "Jumps",
+                            "Skew",
+                        ] {
+                            ui.label(egui::RichText::new(header).strong());
+                        }
+                        ui.end_row();
+                        for (serial_number, device) in &self.sync_verifier.devices {
+                            ui.label(serial_number);
+                            let mode = self
+                                .sessions
+                                .iter()
+                                .find(|session| session.serial_number == *serial_number)
+                                .map(|session| session.inter_cam_sync_mode.as_str())
+                                .unwrap_or("N/A");
+                            ui.label(mode);
+                            ui.label(format!("{:.3}", device.skew.mean));
+                            ui.label(format!("{:.3}", device.skew.std_dev()));
+                            ui.label(format!("{:.3}", device.skew.min));
+                            ui.label(format!("{:.3}", device.skew.max));
+                            match device.counter_offset {
+                                Some(offset) => ui.label(offset.to_string()),
+                                None => ui.label("N/A"),
+                            };
+                            ui.label(device.counter_jumps.to_string());
+                            let skews = device.history.iter().map(|skew| *skew as f32);
+                            sparkline(ui, skews, egui::vec2(80.0, 20.0));
+                            ui.end_row();
                         }
                     });
             });
+        self.inter_cam_window_open = open;
+    }
 
+    fn bottom_panel(&mut self, egui_ctx: &egui::Context) {
+        // Applied once the devices table is drawn, as it borrows the devices
+        let mut action = None;
+        egui::TopBottomPanel::bottom("bottom_panel").show(egui_ctx, |ui| {
             // Devices table
             egui::Grid::new("devices").striped(true).show(ui, |ui| {
                 // Header
@@ -985,10 +2520,24 @@ impl MyApp {
                 ui.label(egui::RichText::new("Serial Number").strong());
                 ui.label(egui::RichText::new("Firmware Version").strong());
                 ui.label(egui::RichText::new("Recommended").strong());
+                ui.label(egui::RichText::new("Status").strong());
+                ui.label("");
+                ui.label("");
                 ui.end_row();
 
-                for (index, device) in devices.iter().enumerate() {
-                    ui.label(index.to_string());
+                for (index, device) in self.device_manager.devices().iter().enumerate() {
+                    let serial_number = get_serial_number(device);
+                    let session_index = self.session_index(&serial_number);
+                    let selected = session_index == Some(self.selected_session);
+                    if ui
+                        .selectable_label(selected, index.to_string())
+                        .on_hover_text("Show in the side panels")
+                        .clicked()
+                    {
+                        if let Some(session_index) = session_index {
+                            self.selected_session = session_index;
+                        }
+                    }
                     ui.label(match_info(
                         &device,
                         realsense_rust::kind::Rs2CameraInfo::Name,
@@ -1005,19 +2554,119 @@ impl MyApp {
                         &device,
                         realsense_rust::kind::Rs2CameraInfo::RecommendedFirmwareVersion,
                     ));
+
+                    let session = session_index.map(|session_index| &self.sessions[session_index]);
+                    let streaming = session.is_some_and(|session| session.pipeline.is_some());
+                    // Frames coming clear the warning, not a rejected sync mode
+                    let warning = session.and_then(|session| {
+                        session
+                            .warning
+                            .as_ref()
+                            .or(session.sync_mode_message.as_ref())
+                    });
+                    match warning {
+                        Some(warning) => {
+                            ui.colored_label(egui::Color32::YELLOW, warning);
+                        }
+                        None if streaming => {
+                            ui.label("Streaming");
+                        }
+                        None => {
+                            ui.label("Stopped");
+                        }
+                    }
+
+                    if streaming {
+                        if ui.button("Stop").clicked() {
+                            action = Some(DeviceAction::Stop(serial_number.clone()));
+                        }
+                    } else {
+                        let start = ui
+                            .add_enabled(is_realsense(device), egui::Button::new("Start"))
+                            .on_disabled_hover_text("Not an Intel RealSense");
+                        if start.clicked() {
+                            action = Some(DeviceAction::Start(index));
+                        }
+                    }
+
+                    // Recovery of wedged cameras
+                    ui.add_enabled_ui(is_realsense(device), |ui| {
+                        ui.menu_button("Recover", |ui| {
+                            if ui.button("Hardware reset").clicked() {
+                                action = Some(DeviceAction::HardwareReset(index));
+                                ui.close_menu();
+                            }
+                            if ui
+                                .add_enabled(streaming, egui::Button::new("Restart pipeline"))
+                                .clicked()
+                            {
+                                action = Some(DeviceAction::Restart(serial_number.clone()));
+                                ui.close_menu();
+                            }
+                            if ui
+                                .add_enabled(session.is_some(), egui::Button::new("Reconnect"))
+                                .clicked()
+                            {
+                                action = Some(DeviceAction::Reconnect(serial_number.clone()));
+                                ui.close_menu();
+                            }
+                        });
+                    });
                     ui.end_row();
                 }
             });
 
+            // Connections and disconnections
+            let events = self.device_manager.events();
+            egui::CollapsingHeader::new(format!("Event Log ({})", events.len()))
+                .id_salt("event_log")
+                .show(ui, |ui| {
+                    egui::ScrollArea::vertical()
+                        .max_height(80.0)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            for event in events {
+                                ui.label(format!(
+                                    "{:>8.1} s  {:<12} {} ({})",
+                                    event.time, event.kind, event.name, event.serial_number
+                                ));
+                            }
+                        });
+                });
+
             if let Some(msg) = &self.warning {
                 ui.colored_label(egui::Color32::YELLOW, msg);
             } else {
                 ui.label("");
             }
         });
+
+        match action {
+            Some(DeviceAction::Start(device_index)) => self.start_device(device_index),
+            Some(DeviceAction::Stop(serial_number)) => self.stop_device(&serial_number),
+            Some(DeviceAction::HardwareReset(device_index)) => self.hardware_reset(device_index),
+            Some(DeviceAction::Restart(serial_number)) => {
+                if let Some(index) = self.session_index(&serial_number) {
+                    self.sessions[index].update_current_pipeline();
+                }
+            }
+            Some(DeviceAction::Reconnect(serial_number)) => self.reconnect_device(&serial_number),
+            None => (),
+        }
     }
 }
 
+/// Actions requested from the devices table
+enum DeviceAction {
+    /// Index in the list of devices
+    Start(usize),
+    /// Serial number
+    Stop(String),
+    HardwareReset(usize),
+    Restart(String),
+    Reconnect(String),
+}
+
 /// Gets info from a device or returns "N/A"
 fn match_info(
     device: &realsense_rust::device::Device,
@@ -1029,92 +2678,194 @@ fn match_info(
     }
 }
 
-///
-fn get_serial_number(device: &realsense_rust::device::Device) -> String {
-    match_info(&device, realsense_rust::kind::Rs2CameraInfo::SerialNumber)
+/// Gets the stream name of a frame, e.g. "Infrared:1"
+fn stream_name<T: FrameEx>(frame: &T) -> String {
+    let profile = frame.stream_profile();
+    format!("{}:{}", profile.kind(), profile.index())
 }
 
-///
-fn get_dev_repr(index: u8, dev: &realsense_rust::device::Device) -> String {
-    let name = match_info(dev, realsense_rust::kind::Rs2CameraInfo::Name);
-    let serial_number = match_info(&dev, realsense_rust::kind::Rs2CameraInfo::SerialNumber);
-    format!("{index}: {name} ({serial_number})")
+/// Gets host time in milliseconds, the same clock as the global time domain
+fn host_time_ms() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("System time before Unix epoch")
+        .as_secs_f64()
+        * 1000.0
 }
 
-///
-fn color_frame_to_rgb_img(color_frame: &realsense_rust::frame::ColorFrame) -> image::RgbImage {
-    let mut img = image::RgbImage::new(color_frame.width() as u32, color_frame.height() as u32);
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        match color_frame.get_unchecked(x as usize, y as usize) {
-            realsense_rust::frame::PixelKind::Bgr8 { b, g, r } => {
-                *pixel = image::Rgb([*r, *g, *b]);
-            }
-            _ => panic!("Color type is wrong!"),
-        }
+/// Gets the (frame counter, timestamp) of the main image stream in a frameset
+fn latest_frame_sync_info(frames: &realsense_rust::frame::CompositeFrame) -> Option<(u64, f64)> {
+    if let Some(frame) = frames
+        .frames_of_type::<realsense_rust::frame::DepthFrame>()
+        .first()
+    {
+        return Some(frame_sync_info(frame));
     }
-    img
+    if let Some(frame) = frames
+        .frames_of_type::<realsense_rust::frame::InfraredFrame>()
+        .first()
+    {
+        return Some(frame_sync_info(frame));
+    }
+    frames
+        .frames_of_type::<realsense_rust::frame::ColorFrame>()
+        .first()
+        .map(frame_sync_info)
 }
 
-///
-fn infrared_frame_to_rgb_img(frame: &realsense_rust::frame::InfraredFrame) -> image::RgbImage {
-    let mut img = image::RgbImage::new(frame.width() as u32, frame.height() as u32);
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        match frame.get_unchecked(x as usize, y as usize) {
-            realsense_rust::frame::PixelKind::Y8 { y } => {
-                *pixel = image::Rgb([*y, *y, *y]);
-            }
-            _ => panic!("Color type is wrong!"),
-        }
+/// Gets the hardware frame counter, or the frame number if not available, and
+/// the timestamp of a frame
+fn frame_sync_info<T: FrameEx>(frame: &T) -> (u64, f64) {
+    let counter = frame
+        .metadata(realsense_rust::kind::Rs2FrameMetadata::FrameCounter)
+        .map(|counter| counter as u64)
+        .unwrap_or_else(|| frame.frame_number());
+    (counter, frame.timestamp())
+}
+
+/// Draws a small line chart of the given values
+fn sparkline(ui: &mut egui::Ui, values: impl Iterator<Item = f32> + Clone, size: egui::Vec2) {
+    let (area, _response) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(area, 0.0, egui::Color32::BLACK);
+    let count = values.clone().count();
+    if count < 2 {
+        return;
     }
-    img
+    let min = values.clone().fold(f32::INFINITY, f32::min);
+    let max = values.clone().fold(f32::NEG_INFINITY, f32::max);
+    let range = (max - min).max(f32::EPSILON);
+    let step = area.width() / (count - 1) as f32;
+    let points = values
+        .enumerate()
+        .map(|(i, value)| {
+            let t = (value - min) / range;
+            egui::Pos2::new(area.min.x + i as f32 * step, area.max.y - t * area.height())
+        })
+        .collect();
+    painter.add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.0, egui::Color32::LIGHT_GREEN),
+    ));
 }
 
-///
-fn depth_frame_to_rgb_img(frame: &realsense_rust::frame::DepthFrame) -> image::RgbImage {
-    let max_value = 4000.0; // 4m
-    let mut img = image::RgbImage::new(frame.width() as u32, frame.height() as u32);
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        match frame.get_unchecked(x as usize, y as usize) {
-            realsense_rust::frame::PixelKind::Z16 { depth } => {
-                let normalized = *depth as f32 / max_value;
-                *pixel = jet_colormap(normalized);
-            }
-            _ => panic!("Depth type is wrong!"),
-        }
+/// Draws a bar chart of the given bin counts
+fn histogram(ui: &mut egui::Ui, counts: &[u32], size: egui::Vec2) -> egui::Response {
+    let (area, response) = ui.allocate_exact_size(size, egui::Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(area, 0.0, egui::Color32::BLACK);
+    let max = counts.iter().copied().max().unwrap_or(0);
+    if max == 0 {
+        return response;
+    }
+    let bar_width = area.width() / counts.len() as f32;
+    for (i, count) in counts.iter().enumerate() {
+        let height = *count as f32 / max as f32 * area.height();
+        let left_corner = egui::Pos2::new(area.min.x + i as f32 * bar_width, area.max.y);
+        let right_corner = egui::Pos2::new(left_corner.x + bar_width, area.max.y - height);
+        painter.rect_filled(
+            egui::Rect::from_two_pos(left_corner, right_corner),
+            0.0,
+            egui::Color32::LIGHT_BLUE,
+        );
     }
-    img
+    response
 }
 
-/// Implement the classic jet color map
-/// Blue -> Cyan -> Yellow -> Red -> Black
-fn jet_colormap(value: f32) -> image::Rgb<u8> {
-    let v = value.clamp(0.0, 1.0);
-
-    let (r, g, b) = if v < 0.25 {
-        lerp_color(v, 0.00, (0, 0, 255), 0.25, (0, 255, 255)) // Blue → Cyan
-    } else if v < 0.5 {
-        lerp_color(v, 0.25, (0, 255, 255), 0.5, (255, 255, 0)) // Cyan → Yellow
-    } else if v < 0.75 {
-        lerp_color(v, 0.5, (255, 255, 0), 0.75, (255, 0, 0)) // Green → Yellow
+/// Adds the columns of a pair of streams to the sync analysis grid
+fn add_pair_stats_row(ui: &mut egui::Ui, pair: &PairStats) {
+    ui.label(format!("{:.2}", pair.offsets.mean));
+    ui.label(format!("{:.2}", pair.offsets.std_dev()));
+    ui.label(format!("{:.2}", pair.offsets.min));
+    ui.label(format!("{:.2}", pair.offsets.max));
+    if let Some(drift) = pair.drift() {
+        ui.label(format!("{drift:.3}"));
     } else {
-        lerp_color(v, 0.8, (255, 0, 0), 1.00, (0, 0, 0)) // Dark Red → Black
-    };
+        ui.label("N/A");
+    }
+    let hist = pair.histogram(20);
+    histogram(ui, &hist.counts, egui::vec2(80.0, 20.0))
+        .on_hover_text(format!("{:.2} .. {:.2} ms", hist.min, hist.max));
+    let offsets = pair.history.iter().map(|(_, offset)| *offset as f32);
+    sparkline(ui, offsets, egui::vec2(80.0, 20.0));
+}
+
+///
+fn get_serial_number(device: &realsense_rust::device::Device) -> String {
+    match_info(&device, realsense_rust::kind::Rs2CameraInfo::SerialNumber)
+}
 
-    image::Rgb([r, g, b])
+/// Checks whether a device is an Intel RealSense camera
+fn is_realsense(device: &realsense_rust::device::Device) -> bool {
+    match_info(device, realsense_rust::kind::Rs2CameraInfo::Name).starts_with("Intel RealSense")
 }
 
-/// Linearly interpolates between two colors based on value position.
-fn lerp_color(
-    value: f32,
-    v_min: f32,
-    c_min: (u8, u8, u8),
-    v_max: f32,
-    c_max: (u8, u8, u8),
-) -> (u8, u8, u8) {
-    let t = ((value - v_min) / (v_max - v_min)).clamp(0.0, 1.0);
-    (
-        (c_min.0 as f32 + t * (c_max.0 as f32 - c_min.0 as f32)) as u8,
-        (c_min.1 as f32 + t * (c_max.1 as f32 - c_min.1 as f32)) as u8,
-        (c_min.2 as f32 + t * (c_max.2 as f32 - c_min.2 as f32)) as u8,
-    )
+/// Tile id kept in the layout, e.g. "123456789/Depth:0"
+fn tile_id(key: &TileKey) -> String {
+    format!("{}/{}", key.0, key.1)
+}
+
+/// Size of the image of a frame
+fn image_size(frame: &FrameSnapshot) -> Option<glam::Vec2> {
+    let (width, height) = frame.image_size()?;
+    Some(glam::Vec2::new(width as f32, height as f32))
+}
+
+/// Position in a tile as a fraction of its size
+fn tile_position(rect: egui::Rect, pos: egui::Pos2) -> glam::Vec2 {
+    let position = (pos - rect.min) / rect.size();
+    glam::Vec2::new(position.x, position.y)
+}
+
+/// Draws the borders of the image pixels once they are big enough
+fn draw_pixel_grid(
+    painter: &egui::Painter,
+    rect: egui::Rect,
+    view: &TileView,
+    image_size: glam::Vec2,
+) {
+    let scale = glam::Vec2::new(rect.width(), rect.height()) * view.zoom;
+    let pixel_size = scale / image_size;
+    if pixel_size.min_element() < GRID_MIN_PIXEL_SIZE {
+        return;
+    }
+    let (min, max) = view.visible();
+    let stroke = egui::Stroke::new(1.0, egui::Color32::from_black_alpha(96));
+    let (first, last) = ((min * image_size).ceil(), (max * image_size).floor());
+    for col in first.x as u32..=last.x as u32 {
+        let x = rect.min.x + (col as f32 / image_size.x - min.x) * scale.x;
+        painter.vline(x, rect.y_range(), stroke);
+    }
+    for row in first.y as u32..=last.y as u32 {
+        let y = rect.min.y + (row as f32 / image_size.y - min.y) * scale.y;
+        painter.hline(rect.x_range(), y, stroke);
+    }
+}
+
+/// Value of a pixel, depth in meters
+fn pixel_value(frame: &FrameSnapshot, x: usize, y: usize) -> String {
+    match &frame.data {
+        SnapshotData::Depth {
+            width,
+            depth_units,
+            data,
+            ..
+        } => match data.get(y * width + x) {
+            Some(depth) => format!("{:.3} m", *depth as f32 * depth_units),
+            None => String::new(),
+        },
+        SnapshotData::Color { width, data, .. } => match data.get((y * width + x) * 3..) {
+            Some([r, g, b, ..]) => format!("RGB {r} {g} {b}"),
+            _ => String::new(),
+        },
+        SnapshotData::Infrared { width, data, .. } => match data.get(y * width + x) {
+            Some(value) => format!("{value}"),
+            None => String::new(),
+        },
+        SnapshotData::Infrared16 { width, data, .. } => match data.get(y * width + x) {
+            Some(value) => format!("{value}"),
+            None => String::new(),
+        },
+        SnapshotData::Gyro(_) | SnapshotData::Accel(_) => String::new(),
+    }
 }
@@ -0,0 +1,339 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Per-stream frame rate, jitter, dropped frames and latency statistics.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Number of inter-frame intervals used for the live fps and jitter
+const WINDOW_SIZE: usize = 30;
+
+/// Number of samples kept for the sparklines
+pub const HISTORY_SIZE: usize = 120;
+
+/// Running mean, deviation and limits (Welford's algorithm)
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct RunningStats {
+    pub count: u64,
+    pub mean: f64,
+    #[serde(skip)]
+    m2: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl RunningStats {
+    pub fn push(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    pub fn std_dev(&self) -> f64 {
+        if self.count < 2 {
+            0.0
+        } else {
+            (self.m2 / (self.count - 1) as f64).sqrt()
+        }
+    }
+}
+
+/// Statistics of a single stream
+#[derive(Clone, Debug, Default)]
+pub struct StreamStats {
+    last_frame_number: Option<u64>,
+    last_timestamp: Option<f64>,
+    /// Time streaming, without the gaps between restarts, in milliseconds
+    active_ms: f64,
+    intervals: VecDeque<f64>,
+    pub received: u64,
+    pub dropped: u64,
+    pub interval_stats: RunningStats,
+    pub latency_stats: RunningStats,
+    pub fps_history: VecDeque<f32>,
+    pub jitter_history: VecDeque<f32>,
+    pub latency_history: VecDeque<f32>,
+}
+
+impl StreamStats {
+    /// Records a frame. Timestamps are in milliseconds, latency is only
+    /// available when the frame timestamp is in the global time domain.
+    pub fn record(&mut self, frame_number: u64, timestamp: f64, latency: Option<f64>) {
+        self.received += 1;
+
+        match self.last_frame_number {
+            // A new pipeline restarts the counters, that is not a drop
+            Some(last) if frame_number > last => self.dropped += frame_number - last - 1,
+            Some(last) if frame_number < last => self.restart(),
+            _ => (),
+        }
+        self.last_frame_number = Some(frame_number);
+
+        if let Some(last) = self.last_timestamp {
+            let interval = timestamp - last;
+            if interval > 0.0 {
+                self.active_ms += interval;
+                self.intervals.push_back(interval);
+                if self.intervals.len() > WINDOW_SIZE {
+                    self.intervals.pop_front();
+                }
+                self.interval_stats.push(interval);
+                let (fps, jitter) = (self.fps(), self.jitter());
+                push_history(&mut self.fps_history, fps as f32);
+                push_history(&mut self.jitter_history, jitter as f32);
+            }
+        }
+        self.last_timestamp = Some(timestamp);
+
+        if let Some(latency) = latency {
+            self.latency_stats.push(latency);
+            push_history(&mut self.latency_history, latency as f32);
+        }
+    }
+
+    /// Measured frame rate over the last intervals
+    pub fn fps(&self) -> f64 {
+        let mean = mean(&self.intervals);
+        if mean > 0.0 {
+            1000.0 / mean
+        } else {
+            0.0
+        }
+    }
+
+    /// Standard deviation of the last inter-frame intervals in milliseconds
+    pub fn jitter(&self) -> f64 {
+        if self.intervals.len() < 2 {
+            return 0.0;
+        }
+        let mean = mean(&self.intervals);
+        let variance = self
+            .intervals
+            .iter()
+            .map(|interval| (interval - mean).powi(2))
+            .sum::<f64>()
+            / (self.intervals.len() - 1) as f64;
+        variance.sqrt()
+    }
+
+    /// Last measured latency in milliseconds
+    pub fn latency(&self) -> Option<f64> {
+        self.latency_history.back().map(|latency| *latency as f64)
+    }
+
+    pub fn summary(&self) -> StreamSummary {
+        let expected = self.received + self.dropped;
+        StreamSummary {
+            frames_received: self.received,
+            frames_dropped: self.dropped,
+            drop_rate: if expected > 0 {
+                self.dropped as f64 / expected as f64
+            } else {
+                0.0
+            },
+            duration_ms: self.active_ms,
+            mean_fps: if self.interval_stats.mean > 0.0 {
+                1000.0 / self.interval_stats.mean
+            } else {
+                0.0
+            },
+            jitter_ms: self.interval_stats.std_dev(),
+            interval_ms: self.interval_stats.clone(),
+            latency_ms: if self.latency_stats.count > 0 {
+                Some(self.latency_stats.clone())
+            } else {
+                None
+            },
+        }
+    }
+
+    fn restart(&mut self) {
+        self.last_timestamp = None;
+        self.intervals.clear();
+    }
+}
+
+/// Statistics of every stream in a session, keyed by stream name
+#[derive(Debug, Default)]
+pub struct SessionStats {
+    pub streams: BTreeMap<String, StreamStats>,
+}
+
+impl SessionStats {
+    pub fn record(
+        &mut self,
+        stream: &str,
+        frame_number: u64,
+        timestamp: f64,
+        latency: Option<f64>,
+    ) {
+        self.streams.entry(stream.to_string()).or_default().record(
+            frame_number,
+            timestamp,
+            latency,
+        );
+    }
+
+    pub fn clear(&mut self) {
+        self.streams.clear();
+    }
+
+    pub fn summary(&self) -> SessionSummary {
+        SessionSummary {
+            streams: self
+                .streams
+                .iter()
+                .map(|(name, stats)| (name.clone(), stats.summary()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamSummary {
+    pub frames_received: u64,
+    pub frames_dropped: u64,
+    pub drop_rate: f64,
+    pub duration_ms: f64,
+    pub mean_fps: f64,
+    pub jitter_ms: f64,
+    pub interval_ms: RunningStats,
+    pub latency_ms: Option<RunningStats>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionSummary {
+    pub streams: BTreeMap<String, StreamSummary>,
+}
+
+impl SessionSummary {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
+
+fn push_history(history: &mut VecDeque<f32>, value: f32) {
+    history.push_back(value);
+    if history.len() > HISTORY_SIZE {
+        history.pop_front();
+    }
+}
+
+fn mean(values: &VecDeque<f64>) -> f64 {
+    if values.is_empty() {
+        0.0
+    } else {
+        values.iter().sum::<f64>() / values.len() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn running_stats() {
+        let mut stats = RunningStats::default();
+        for value in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.push(value);
+        }
+        assert_eq!(stats.count, 8);
+        assert_eq!(stats.mean, 5.0);
+        assert_eq!((stats.min, stats.max), (2.0, 9.0));
+        // Sample deviation, sqrt(32 / 7)
+        assert!((stats.std_dev() - (32.0f64 / 7.0).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn running_stats_single_value() {
+        let mut stats = RunningStats::default();
+        stats.push(-3.0);
+        assert_eq!((stats.min, stats.max, stats.mean), (-3.0, -3.0, -3.0));
+        assert_eq!(stats.std_dev(), 0.0);
+    }
+
+    #[test]
+    fn steady_stream() {
+        let mut stats = StreamStats::default();
+        for frame in 0..100 {
+            stats.record(frame, frame as f64 * 33.0, Some(5.0));
+        }
+        assert_eq!(stats.received, 100);
+        assert_eq!(stats.dropped, 0);
+        assert!((stats.fps() - 1000.0 / 33.0).abs() < 1e-9);
+        assert!(stats.jitter() < 1e-9);
+        assert_eq!(stats.latency(), Some(5.0));
+        assert_eq!(stats.fps_history.len(), 99);
+        let summary = stats.summary();
+        assert_eq!(summary.duration_ms, 99.0 * 33.0);
+        assert_eq!(summary.latency_ms.map(|latency| latency.count), Some(100));
+    }
+
+    #[test]
+    fn counts_dropped_frames() {
+        let mut stats = StreamStats::default();
+        for frame in [1, 2, 5, 6, 10] {
+            stats.record(frame, frame as f64 * 33.0, None);
+        }
+        assert_eq!(stats.received, 5);
+        assert_eq!(stats.dropped, 5);
+        let summary = stats.summary();
+        assert_eq!(summary.drop_rate, 0.5);
+        assert!(summary.latency_ms.is_none());
+    }
+
+    #[test]
+    fn measures_jitter() {
+        let mut stats = StreamStats::default();
+        let mut timestamp = 0.0;
+        for frame in 0..=WINDOW_SIZE as u64 {
+            stats.record(frame, timestamp, None);
+            timestamp += if frame % 2 == 0 { 30.0 } else { 40.0 };
+        }
+        // Alternating 30 and 40 ms
+        assert!((stats.fps() - 1000.0 / 35.0).abs() < 1e-9);
+        let expected = (25.0 * WINDOW_SIZE as f64 / (WINDOW_SIZE - 1) as f64).sqrt();
+        assert!((stats.jitter() - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn restart_is_not_a_drop() {
+        let mut stats = StreamStats::default();
+        for frame in 0..10 {
+            stats.record(100 + frame, frame as f64 * 10.0, None);
+        }
+        // Stopped for 10 s, then the counters start over
+        for frame in 0..10 {
+            stats.record(frame, 10_000.0 + frame as f64 * 10.0, None);
+        }
+        assert_eq!(stats.received, 20);
+        assert_eq!(stats.dropped, 0);
+        let summary = stats.summary();
+        assert_eq!(summary.duration_ms, 180.0);
+        assert!((summary.mean_fps - 100.0).abs() < 1e-9);
+    }
+}
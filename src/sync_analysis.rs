@@ -0,0 +1,237 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Timestamp offsets between the streams of a composite frame.
+
+use crate::stream_stats::RunningStats;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Number of offsets kept per pair for the histogram and the drift
+const HISTORY_SIZE: usize = 300;
+
+/// Offsets between two streams, always measured as `second - first`
+#[derive(Clone, Debug, Default)]
+pub struct PairStats {
+    pub offsets: RunningStats,
+    /// Pairs of (seconds since the analysis started, offset in ms)
+    pub history: VecDeque<(f64, f64)>,
+}
+
+impl PairStats {
+    fn push(&mut self, time: f64, offset: f64) {
+        self.offsets.push(offset);
+        self.history.push_back((time, offset));
+        if self.history.len() > HISTORY_SIZE {
+            self.history.pop_front();
+        }
+    }
+
+    /// Slope of the offset over time in milliseconds per second, from a
+    /// least squares fit of the recent history
+    pub fn drift(&self) -> Option<f64> {
+        let n = self.history.len() as f64;
+        if n < 2.0 {
+            return None;
+        }
+        let mean_t = self.history.iter().map(|(t, _)| t).sum::<f64>() / n;
+        let mean_o = self.history.iter().map(|(_, o)| o).sum::<f64>() / n;
+        let (mut cov, mut var) = (0.0, 0.0);
+        for (t, o) in &self.history {
+            cov += (t - mean_t) * (o - mean_o);
+            var += (t - mean_t).powi(2);
+        }
+        if var > 0.0 {
+            Some(cov / var)
+        } else {
+            None
+        }
+    }
+
+    /// Distribution of the recent offsets
+    pub fn histogram(&self, bins: usize) -> Histogram {
+        let offsets = self.history.iter().map(|(_, offset)| *offset);
+        Histogram::new(offsets, bins)
+    }
+}
+
+/// Counts of values in equally sized bins between `min` and `max`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram {
+    pub min: f64,
+    pub max: f64,
+    pub counts: Vec<u32>,
+}
+
+impl Histogram {
+    pub fn new(values: impl Iterator<Item = f64> + Clone, bins: usize) -> Self {
+        let min = values.clone().fold(f64::INFINITY, f64::min);
+        let max = values.clone().fold(f64::NEG_INFINITY, f64::max);
+        let mut counts = vec![0; bins];
+        if bins == 0 || min > max {
+            return Self {
+                min: 0.0,
+                max: 0.0,
+                counts,
+            };
+        }
+        let width = (max - min) / bins as f64;
+        for value in values {
+            let bin = if width > 0.0 {
+                (((value - min) / width) as usize).min(bins - 1)
+            } else {
+                bins / 2
+            };
+            counts[bin] += 1;
+        }
+        Self { min, max, counts }
+    }
+}
+
+/// Offsets of every pair of streams seen together in a composite frame
+#[derive(Debug, Default)]
+pub struct SyncAnalyzer {
+    start: Option<f64>,
+    pub pairs: BTreeMap<(String, String), PairStats>,
+}
+
+impl SyncAnalyzer {
+    /// Records the timestamps (in ms) of the streams of a composite frame
+    /// received at the given host time (in ms)
+    pub fn record(&mut self, host_time: f64, timestamps: &[(String, f64)]) {
+        let start = *self.start.get_or_insert(host_time);
+        let time = (host_time - start) / 1000.0;
+        let mut timestamps = timestamps.to_vec();
+        timestamps.sort_by(|a, b| a.0.cmp(&b.0));
+        for (i, (first, first_ts)) in timestamps.iter().enumerate() {
+            for (second, second_ts) in &timestamps[i + 1..] {
+                self.pairs
+                    .entry((first.clone(), second.clone()))
+                    .or_default()
+                    .push(time, second_ts - first_ts);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.start = None;
+        self.pairs.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamps(streams: &[(&str, f64)]) -> Vec<(String, f64)> {
+        streams
+            .iter()
+            .map(|(name, timestamp)| (name.to_string(), *timestamp))
+            .collect()
+    }
+
+    #[test]
+    fn known_offset() {
+        let mut analyzer = SyncAnalyzer::default();
+        for frame in 0..100 {
+            let t = 1000.0 + frame as f64 * 33.0;
+            // Given out of order, stored by name
+            let streams = timestamps(&[("Infrared", t + 0.5), ("Depth", t), ("Color", t + 2.0)]);
+            analyzer.record(t, &streams);
+        }
+        assert_eq!(analyzer.pairs.len(), 3);
+        let pair = &analyzer.pairs[&("Color".to_string(), "Depth".to_string())];
+        assert_eq!(pair.offsets.count, 100);
+        assert!((pair.offsets.mean + 2.0).abs() < 1e-9);
+        assert!(pair.offsets.std_dev() < 1e-9);
+        let pair = &analyzer.pairs[&("Depth".to_string(), "Infrared".to_string())];
+        assert!((pair.offsets.mean - 0.5).abs() < 1e-9);
+        // Without drift
+        assert!(pair.drift().unwrap().abs() < 1e-9);
+        // Seconds since the first frame
+        assert!((pair.history.back().unwrap().0 - 99.0 * 0.033).abs() < 1e-9);
+    }
+
+    #[test]
+    fn drift_slope() {
+        let mut analyzer = SyncAnalyzer::default();
+        for frame in 0..200 {
+            let t = frame as f64 * 50.0;
+            // 0.2 ms more every second
+            let offset = 1.0 + 0.2 * t / 1000.0;
+            analyzer.record(t, &timestamps(&[("A", t), ("B", t + offset)]));
+        }
+        let pair = &analyzer.pairs[&("A".to_string(), "B".to_string())];
+        assert!((pair.drift().unwrap() - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn drift_needs_time() {
+        let mut pair = PairStats::default();
+        assert_eq!(pair.drift(), None);
+        pair.push(1.0, 3.0);
+        pair.push(1.0, 4.0);
+        assert_eq!(pair.drift(), None);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut pair = PairStats::default();
+        for i in 0..HISTORY_SIZE + 10 {
+            pair.push(i as f64, 0.0);
+        }
+        assert_eq!(pair.history.len(), HISTORY_SIZE);
+        assert_eq!(pair.history.front().unwrap().0, 10.0);
+        assert_eq!(pair.offsets.count, HISTORY_SIZE as u64 + 10);
+    }
+
+    #[test]
+    fn histogram_bins() {
+        let values = [0.0, 0.5, 1.0, 2.5, 3.9, 4.0];
+        let histogram = Histogram::new(values.iter().copied(), 4);
+        assert_eq!((histogram.min, histogram.max), (0.0, 4.0));
+        // The maximum goes in the last bin
+        assert_eq!(histogram.counts, vec![2, 1, 1, 2]);
+    }
+
+    #[test]
+    fn histogram_of_constant_values() {
+        let histogram = Histogram::new([1.5; 3].iter().copied(), 5);
+        assert_eq!(histogram.counts, vec![0, 0, 3, 0, 0]);
+    }
+
+    #[test]
+    fn empty_histogram() {
+        let histogram = Histogram::new(std::iter::empty(), 4);
+        assert_eq!(histogram.counts, vec![0; 4]);
+        assert_eq!(
+            Histogram::new([1.0].iter().copied(), 0).counts,
+            Vec::<u32>::new()
+        );
+    }
+
+    #[test]
+    fn clear_restarts_the_time() {
+        let mut analyzer = SyncAnalyzer::default();
+        analyzer.record(1000.0, &timestamps(&[("A", 0.0), ("B", 1.0)]));
+        analyzer.clear();
+        assert!(analyzer.pairs.is_empty());
+        analyzer.record(5000.0, &timestamps(&[("A", 0.0), ("B", 1.0)]));
+        let pair = &analyzer.pairs[&("A".to_string(), "B".to_string())];
+        assert_eq!(pair.history[0], (0.0, 1.0));
+    }
+}
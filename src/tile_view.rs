@@ -0,0 +1,158 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Zoom and pan of the image tiles.
+
+use glam::Vec2;
+
+pub const MAX_ZOOM: f32 = 64.0;
+
+/// Size of an image pixel on screen from which the pixel grid is drawn
+pub const GRID_MIN_PIXEL_SIZE: f32 = 8.0;
+
+/// Visible part of an image, in normalized coordinates where (0, 0) is the
+/// top-left corner and (1, 1) the bottom-right one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TileView {
+    pub zoom: f32,
+    pub center: Vec2,
+}
+
+impl Default for TileView {
+    fn default() -> Self {
+        Self {
+            zoom: 1.0,
+            center: Vec2::splat(0.5),
+        }
+    }
+}
+
+impl TileView {
+    /// Top-left and bottom-right corners of the visible part
+    pub fn visible(&self) -> (Vec2, Vec2) {
+        let half = Vec2::splat(0.5 / self.zoom);
+        (self.center - half, self.center + half)
+    }
+
+    /// Zooms keeping the image point under the cursor still. The cursor is
+    /// given as a fraction of the tile.
+    pub fn zoom_at(&mut self, factor: f32, cursor: Vec2) {
+        let (min, _) = self.visible();
+        let point = min + cursor / self.zoom;
+        self.zoom = (self.zoom * factor).clamp(1.0, MAX_ZOOM);
+        self.center = point - cursor / self.zoom + Vec2::splat(0.5 / self.zoom);
+        self.clamp();
+    }
+
+    /// Moves the image with the cursor, given as a fraction of the tile
+    pub fn pan(&mut self, delta: Vec2) {
+        self.center -= delta / self.zoom;
+        self.clamp();
+    }
+
+    /// Image pixel under the cursor, given as a fraction of the tile
+    pub fn pixel_at(&self, cursor: Vec2, image_size: Vec2) -> Vec2 {
+        let (min, _) = self.visible();
+        ((min + cursor / self.zoom) * image_size).floor()
+    }
+
+    /// Keeps the visible part inside the image
+    fn clamp(&mut self) {
+        let half = 0.5 / self.zoom;
+        self.center = self
+            .center
+            .clamp(Vec2::splat(half), Vec2::splat(1.0 - half));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec2, b: Vec2) {
+        assert!(a.distance(b) < 1e-5, "{a} != {b}");
+    }
+
+    #[test]
+    fn whole_image_by_default() {
+        let view = TileView::default();
+        assert_eq!(view.visible(), (Vec2::ZERO, Vec2::ONE));
+        let size = Vec2::new(640.0, 480.0);
+        assert_eq!(view.pixel_at(Vec2::ZERO, size), Vec2::ZERO);
+        assert_eq!(
+            view.pixel_at(Vec2::splat(0.5), size),
+            Vec2::new(320.0, 240.0)
+        );
+        assert_eq!(
+            view.pixel_at(Vec2::splat(0.999), size),
+            Vec2::new(639.0, 479.0)
+        );
+    }
+
+    #[test]
+    fn zoom_keeps_the_point_under_the_cursor() {
+        let size = Vec2::new(640.0, 480.0);
+        let cursor = Vec2::new(0.3, 0.6);
+        let mut view = TileView::default();
+        let before = view.pixel_at(cursor, size);
+        view.zoom_at(4.0, cursor);
+        assert_eq!(view.zoom, 4.0);
+        assert_eq!(view.pixel_at(cursor, size), before);
+        let (min, max) = view.visible();
+        assert_close(max - min, Vec2::splat(0.25));
+        // The cursor is at the same fraction of the visible part
+        assert_close(min + cursor * 0.25, before / size);
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut view = TileView::default();
+        view.zoom_at(0.5, Vec2::splat(0.5));
+        assert_eq!(view, TileView::default());
+        view.zoom_at(1000.0, Vec2::splat(0.5));
+        assert_eq!(view.zoom, MAX_ZOOM);
+    }
+
+    #[test]
+    fn zooming_at_a_corner_stays_inside() {
+        let mut view = TileView::default();
+        view.zoom_at(2.0, Vec2::ZERO);
+        assert_eq!(view.visible(), (Vec2::ZERO, Vec2::splat(0.5)));
+        view.zoom_at(0.5, Vec2::ONE);
+        assert_eq!(view.visible(), (Vec2::ZERO, Vec2::ONE));
+    }
+
+    #[test]
+    fn pan_follows_the_cursor_inside_the_image() {
+        let mut view = TileView::default();
+        view.zoom_at(4.0, Vec2::splat(0.5));
+        // Dragging half a tile to the left shows what is to the right
+        view.pan(Vec2::new(-0.5, 0.0));
+        assert_close(view.center, Vec2::new(0.625, 0.5));
+        // No further than the edge
+        view.pan(Vec2::new(-10.0, 10.0));
+        let (min, max) = view.visible();
+        assert_close(max, Vec2::new(1.0, 0.25));
+        assert_close(min, Vec2::new(0.75, 0.0));
+
+        // Without zoom there is nothing to pan
+        let mut view = TileView::default();
+        view.pan(Vec2::new(0.3, -0.2));
+        assert_eq!(view, TileView::default());
+    }
+}
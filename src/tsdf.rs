@@ -0,0 +1,655 @@
+// RealSense Tools in Rust
+// Copyright (C) 2025 Carlos Perez-Lopez
+//
+// This project is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>
+//
+// You can contact the author via carlospzlz@gmail.com
+
+//! Depth frames fused in a truncated signed distance volume, and the surface
+//! extracted from it with marching cubes.
+
+use crate::camera_model::Intrinsics;
+use crate::frame_snapshot::{CompositeSnapshot, SnapshotData};
+use crate::mesh::Mesh;
+use crate::point_cloud::{PointCloud, TextureSource};
+use anyhow::{anyhow, Result};
+use glam::{Affine3A, Vec2, Vec3};
+use std::collections::HashMap;
+
+pub const DEFAULT_VOXEL_SIZE: f32 = 0.01;
+
+/// Corners of the volume scanned by default, in meters, in the frame of the
+/// depth camera
+pub const DEFAULT_BOUNDS: (Vec3, Vec3) = (Vec3::new(-0.5, -0.5, 0.2), Vec3::new(0.5, 0.5, 1.2));
+
+/// Voxels of a volume at most, of 20 bytes each
+pub const MAX_VOXELS: usize = 1 << 23;
+
+/// Limit of the weights, so the volume keeps following changes
+const MAX_WEIGHT: f32 = 64.0;
+
+/// Distances are truncated at this many voxels
+const TRUNCATION_VOXELS: f32 = 4.0;
+
+/// Corners of a cube, indexed by `x + 2 * y + 4 * z`, walked around every
+/// face counter-clockwise as seen from outside
+const CUBE_FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2],
+    [1, 3, 7, 5],
+    [0, 1, 5, 4],
+    [2, 6, 7, 3],
+    [0, 2, 3, 1],
+    [4, 5, 7, 6],
+];
+
+/// Grid of signed distances to the nearest surface, positive in front of it
+/// and negative behind it, averaged over the integrated frames
+#[derive(Clone, Debug)]
+pub struct TsdfVolume {
+    /// Position of the first voxel, in meters
+    pub origin: Vec3,
+    /// In meters
+    pub voxel_size: f32,
+    /// Distances are clamped to this, in meters
+    pub truncation: f32,
+    dims: [usize; 3],
+    /// Normalized by the truncation, in [-1, 1]
+    distances: Vec<f32>,
+    weights: Vec<f32>,
+    colors: Vec<Vec3>,
+    colored: bool,
+}
+
+impl Default for TsdfVolume {
+    fn default() -> Self {
+        let (min, max) = DEFAULT_BOUNDS;
+        Self::new(min, max, DEFAULT_VOXEL_SIZE)
+    }
+}
+
+impl TsdfVolume {
+    /// Empty volume spanning the box between two corners. The voxels are
+    /// made larger when there would be more than [`MAX_VOXELS`].
+    pub fn new(min: Vec3, max: Vec3, voxel_size: f32) -> Self {
+        let dims_of = |voxel_size: f32| {
+            let size = ((max - min) / voxel_size).ceil().max(Vec3::ONE);
+            [
+                size.x as usize + 1,
+                size.y as usize + 1,
+                size.z as usize + 1,
+            ]
+        };
+        let mut voxel_size = voxel_size;
+        let mut dims = dims_of(voxel_size);
+        let len = |dims: [usize; 3]| dims[0] * dims[1] * dims[2];
+        if len(dims) > MAX_VOXELS {
+            voxel_size *= (len(dims) as f32 / MAX_VOXELS as f32).cbrt();
+            dims = dims_of(voxel_size);
+            // Rounded up to whole voxels
+            while len(dims) > MAX_VOXELS {
+                voxel_size *= 1.01;
+                dims = dims_of(voxel_size);
+            }
+        }
+        let len = len(dims);
+        Self {
+            origin: min,
+            voxel_size,
+            truncation: voxel_size * TRUNCATION_VOXELS,
+            dims,
+            distances: vec![1.0; len],
+            weights: vec![0.0; len],
+            colors: vec![Vec3::ZERO; len],
+            colored: false,
+        }
+    }
+
+    /// Number of voxels along every axis
+    pub fn dims(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    /// Forgets the integrated frames
+    pub fn reset(&mut self) {
+        self.distances.fill(1.0);
+        self.weights.fill(0.0);
+        self.colors.fill(Vec3::ZERO);
+        self.colored = false;
+    }
+
+    /// Fuses a depth frame taken from `pose`, which moves points from the
+    /// camera to the volume. `colors`, when given, has one RGB per pixel.
+    pub fn integrate(
+        &mut self,
+        depth: &[u16],
+        depth_units: f32,
+        intrinsics: &Intrinsics,
+        colors: Option<&[[u8; 3]]>,
+        pose: &Affine3A,
+    ) {
+        let to_camera = pose.inverse();
+        let [width, height] = [intrinsics.width as f32, intrinsics.height as f32];
+        self.colored |= colors.is_some();
+        // Voxels further than this are behind every measured surface
+        let Some(far) = depth
+            .iter()
+            .max()
+            .filter(|max| **max > 0)
+            .map(|max| *max as f32 * depth_units + self.truncation)
+        else {
+            return;
+        };
+        let view = view_bounds(intrinsics);
+        let step = to_camera.transform_vector3(Vec3::X * self.voxel_size);
+        for z in 0..self.dims[2] {
+            for y in 0..self.dims[1] {
+                // Only the voxels of the row inside the view are projected
+                let start = to_camera.transform_point3(self.position(0, y, z));
+                let Some((first, last)) = row_in_view(start, step, far, view, self.dims[0]) else {
+                    continue;
+                };
+                for x in first..=last {
+                    let point = start + step * x as f32;
+                    if point.z <= 0.0 {
+                        continue;
+                    }
+                    let pixel = intrinsics.project(point).round();
+                    if pixel.x < 0.0 || pixel.y < 0.0 || pixel.x >= width || pixel.y >= height {
+                        continue;
+                    }
+                    let pixel_index = pixel.y as usize * intrinsics.width + pixel.x as usize;
+                    let measured = depth[pixel_index];
+                    if measured == 0 {
+                        continue;
+                    }
+                    let distance = measured as f32 * depth_units - point.z;
+                    // Occluded, nothing is known behind the surface
+                    if distance < -self.truncation {
+                        continue;
+                    }
+                    let distance = (distance / self.truncation).min(1.0);
+
+                    let index = self.index(x, y, z);
+                    let weight = self.weights[index];
+                    let new_weight = weight + 1.0;
+                    self.distances[index] =
+                        (self.distances[index] * weight + distance) / new_weight;
+                    if let Some(colors) = colors {
+                        let [r, g, b] = colors[pixel_index];
+                        let color = Vec3::new(r as f32, g as f32, b as f32);
+                        self.colors[index] = (self.colors[index] * weight + color) / new_weight;
+                    }
+                    self.weights[index] = new_weight.min(MAX_WEIGHT);
+                }
+            }
+        }
+    }
+
+    /// Fuses the first depth frame of a composite, colored from another
+    /// stream of it
+    pub fn integrate_composite(
+        &mut self,
+        snapshot: &CompositeSnapshot,
+        texture: TextureSource,
+        pose: &Affine3A,
+    ) -> Result<()> {
+        let depth = snapshot
+            .frames
+            .iter()
+            .find(|frame| matches!(frame.data, SnapshotData::Depth { .. }))
+            .ok_or_else(|| anyhow!("There is no depth frame"))?;
+        let SnapshotData::Depth {
+            depth_units, data, ..
+        } = &depth.data
+        else {
+            unreachable!();
+        };
+        let intrinsics = depth
+            .intrinsics
+            .as_ref()
+            .ok_or_else(|| anyhow!("Missing intrinsics of {}", depth.stream))?;
+        // The cloud has a color for every pixel with depth, in order
+        let colors = PointCloud::from_composite(snapshot, texture)?
+            .colors
+            .map(|colors| {
+                let mut colors = colors.into_iter();
+                data.iter()
+                    .map(|depth| match depth {
+                        0 => [0; 3],
+                        _ => colors.next().unwrap_or_default(),
+                    })
+                    .collect::<Vec<_>>()
+            });
+        self.integrate(data, *depth_units, intrinsics, colors.as_deref(), pose);
+        Ok(())
+    }
+
+    /// Surface where the distance crosses zero, with marching cubes. Cubes
+    /// with a voxel never observed are skipped.
+    pub fn extract_mesh(&self) -> Mesh {
+        let mut vertices = Vec::new();
+        let mut colors = Vec::new();
+        let mut triangles = Vec::new();
+        // Vertex on every edge of the grid crossing the surface, by the
+        // index of its first voxel and its axis
+        let mut edge_vertices: HashMap<(usize, usize), u32> = HashMap::new();
+
+        for z in 0..self.dims[2] - 1 {
+            for y in 0..self.dims[1] - 1 {
+                for x in 0..self.dims[0] - 1 {
+                    let corners: [usize; 8] = std::array::from_fn(|corner| {
+                        self.index(x + (corner & 1), y + (corner >> 1 & 1), z + (corner >> 2))
+                    });
+                    if corners.iter().any(|index| self.weights[*index] == 0.0) {
+                        continue;
+                    }
+                    let inside = corners.map(|index| self.distances[index] < 0.0);
+                    if inside.iter().all(|inside| *inside) || !inside.iter().any(|inside| *inside) {
+                        continue;
+                    }
+
+                    // Vertex on the edge between two corners
+                    let mut vertex_on = |a: usize, b: usize| {
+                        let (a, b) = (a.min(b), a.max(b));
+                        let axis = (a ^ b).trailing_zeros() as usize;
+                        *edge_vertices.entry((corners[a], axis)).or_insert_with(|| {
+                            let (da, db) = (self.distances[corners[a]], self.distances[corners[b]]);
+                            let t = da / (da - db);
+                            let position = |corner: usize| {
+                                self.position(
+                                    x + (corner & 1),
+                                    y + (corner >> 1 & 1),
+                                    z + (corner >> 2),
+                                )
+                            };
+                            vertices.push(position(a).lerp(position(b), t));
+                            let color = self.colors[corners[a]].lerp(self.colors[corners[b]], t);
+                            colors.push(color.round().to_array().map(|c| c as u8));
+                            vertices.len() as u32 - 1
+                        })
+                    };
+
+                    // Every face crossed by the surface contributes segments
+                    // of its contour, which chain into closed loops
+                    let mut next = HashMap::new();
+                    for face in CUBE_FACES {
+                        let mut entries = Vec::new();
+                        let mut exits = Vec::new();
+                        for i in 0..4 {
+                            let (a, b) = (face[i], face[(i + 1) % 4]);
+                            if inside[a] != inside[b] {
+                                let vertex = vertex_on(a, b);
+                                if inside[b] {
+                                    entries.push((i, vertex));
+                                } else {
+                                    exits.push((i, vertex));
+                                }
+                            }
+                        }
+                        if entries.is_empty() {
+                            continue;
+                        }
+                        // Order the entries after the exits around the face
+                        let first_exit = exits[0].0;
+                        entries.sort_by_key(|(i, _)| (*i + 4 - first_exit) % 4);
+                        exits.sort_by_key(|(i, _)| (*i + 4 - first_exit) % 4);
+                        // On a face with two opposite corners inside, the
+                        // average of the corners decides if they're joined
+                        let joined = entries.len() == 2 && {
+                            let sum: f32 = face.iter().map(|c| self.distances[corners[*c]]).sum();
+                            sum < 0.0
+                        };
+                        for (k, (_, exit)) in exits.iter().enumerate() {
+                            // Joined corners cut the outside ones off with
+                            // the next entry, else the previous entry closes
+                            // the corner of the exit
+                            let entry = if joined {
+                                entries[k].1
+                            } else {
+                                entries[(k + entries.len() - 1) % entries.len()].1
+                            };
+                            next.insert(*exit, entry);
+                        }
+                    }
+
+                    while let Some(&start) = next.keys().next() {
+                        let mut polygon = vec![start];
+                        let mut current = next.remove(&start).unwrap();
+                        while current != start {
+                            polygon.push(current);
+                            current = match next.remove(&current) {
+                                Some(vertex) => vertex,
+                                None => break,
+                            };
+                        }
+                        // Loops go clockwise seen from the front
+                        for i in 1..polygon.len().saturating_sub(1) {
+                            triangles.push([polygon[0], polygon[i + 1], polygon[i]]);
+                        }
+                    }
+                }
+            }
+        }
+
+        Mesh {
+            vertices,
+            colors: self.colored.then_some(colors),
+            triangles,
+        }
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + self.dims[0] * (y + self.dims[1] * z)
+    }
+
+    fn position(&self, x: usize, y: usize, z: usize) -> Vec3 {
+        self.origin + Vec3::new(x as f32, y as f32, z as f32) * self.voxel_size
+    }
+}
+
+/// Bounds of the rays through the image at a depth of 1, sampled along its
+/// border, so they contain the distorted edges too
+fn view_bounds(intrinsics: &Intrinsics) -> (Vec2, Vec2) {
+    let [width, height] = [intrinsics.width as f32, intrinsics.height as f32];
+    let columns = (0..=intrinsics.width).flat_map(|col| {
+        let x = col as f32 - 0.5;
+        [Vec2::new(x, -0.5), Vec2::new(x, height - 0.5)]
+    });
+    let rows = (0..=intrinsics.height).flat_map(|row| {
+        let y = row as f32 - 0.5;
+        [Vec2::new(-0.5, y), Vec2::new(width - 0.5, y)]
+    });
+    columns
+        .chain(rows)
+        .fold((Vec2::INFINITY, Vec2::NEG_INFINITY), |(min, max), pixel| {
+            let ray = intrinsics.deproject(pixel, 1.0).truncate();
+            (min.min(ray), max.max(ray))
+        })
+}
+
+/// First and last voxels of a row, at `start + x * step` in the frame of
+/// the camera, that may be in the view and closer than `far`. It's one voxel
+/// wider on each side, so rounding doesn't leave any out.
+fn row_in_view(
+    start: Vec3,
+    step: Vec3,
+    far: f32,
+    (min, max): (Vec2, Vec2),
+    len: usize,
+) -> Option<(usize, usize)> {
+    // Half-spaces where `normal.dot(point) + offset >= 0`
+    let half_spaces = [
+        (Vec3::Z, 0.0),
+        (-Vec3::Z, far),
+        (Vec3::new(1.0, 0.0, -min.x), 0.0),
+        (Vec3::new(-1.0, 0.0, max.x), 0.0),
+        (Vec3::new(0.0, 1.0, -min.y), 0.0),
+        (Vec3::new(0.0, -1.0, max.y), 0.0),
+    ];
+    let (mut first, mut last) = (0.0, (len - 1) as f32);
+    for (normal, offset) in half_spaces {
+        let (value, slope) = (normal.dot(start) + offset, normal.dot(step));
+        if slope.abs() < f32::EPSILON {
+            if value < 0.0 {
+                return None;
+            }
+        } else if slope > 0.0 {
+            first = f32::max(first, -value / slope - 1.0);
+        } else {
+            last = f32::min(last, -value / slope + 1.0);
+        }
+    }
+    (first <= last).then(|| (first.floor() as usize, last.ceil() as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera_model::DistortionModel;
+    use glam::Quat;
+    use std::collections::HashSet;
+
+    const DEPTH_UNITS: f32 = 0.001;
+
+    fn intrinsics() -> Intrinsics {
+        Intrinsics {
+            width: 160,
+            height: 120,
+            ppx: 79.5,
+            ppy: 59.5,
+            fx: 120.0,
+            fy: 120.0,
+            model: DistortionModel::None,
+            coeffs: [0.0; 5],
+        }
+    }
+
+    /// Depth map of the first hit along the ray of every pixel, which is
+    /// given with a Z of 1
+    fn render(hit: impl Fn(Vec3) -> Option<f32>) -> Vec<u16> {
+        let intrinsics = intrinsics();
+        let mut depth = Vec::new();
+        for row in 0..intrinsics.height {
+            for col in 0..intrinsics.width {
+                let ray = intrinsics.deproject(Vec2::new(col as f32, row as f32), 1.0);
+                let z = hit(ray).filter(|z| *z > 0.0).unwrap_or(0.0);
+                depth.push((z / DEPTH_UNITS).round() as u16);
+            }
+        }
+        depth
+    }
+
+    /// Every triangle is proper, and every edge is walked once in each
+    /// direction at most, so the triangles agree on the orientation and no
+    /// edge joins more than two of them. Returns the edges without a twin.
+    fn check_topology(mesh: &Mesh) -> usize {
+        let mut edges = HashSet::new();
+        for [a, b, c] in &mesh.triangles {
+            assert!(a != b && b != c && c != a, "Degenerate triangle");
+            for edge in [(a, b), (b, c), (c, a)] {
+                assert!(edges.insert(edge), "Duplicated edge {edge:?}");
+            }
+        }
+        edges
+            .iter()
+            .filter(|(a, b)| !edges.contains(&(*b, *a)))
+            .count()
+    }
+
+    /// Normal of a triangle, scaled by twice its area. It's zero for the
+    /// triangles with a vertex on a voxel, which then repeats.
+    fn normal(mesh: &Mesh, [a, b, c]: [u32; 3]) -> Vec3 {
+        let [a, b, c] = [a, b, c].map(|index| mesh.vertices[index as usize]);
+        (b - a).cross(c - a)
+    }
+
+    #[test]
+    fn sphere() {
+        let center = Vec3::new(0.0, 0.0, 0.7);
+        let radius = 0.2;
+        // The sphere seen from the camera, in its frame
+        let depth = render(|ray| {
+            let b = ray.dot(center);
+            let discriminant =
+                b * b - ray.length_squared() * (center.length_squared() - radius * radius);
+            (discriminant >= 0.0).then(|| (b - discriminant.sqrt()) / ray.length_squared())
+        });
+        let mut volume =
+            TsdfVolume::new(center - Vec3::splat(0.3), center + Vec3::splat(0.3), 0.01);
+        // From the sides and the corners of a cube around it, turning the
+        // camera around the center
+        let sides = [Vec3::X, Vec3::Y, Vec3::Z]
+            .into_iter()
+            .flat_map(|axis| [axis, -axis]);
+        let corners = (0..8).map(|corner| {
+            Vec3::new(
+                if corner & 1 == 0 { -1.0 } else { 1.0 },
+                if corner & 2 == 0 { -1.0 } else { 1.0 },
+                if corner & 4 == 0 { -1.0 } else { 1.0 },
+            )
+            .normalize()
+        });
+        for direction in sides.chain(corners) {
+            let turn = Quat::from_rotation_arc(Vec3::Z, direction);
+            let pose = Affine3A::from_rotation_translation(turn, center - turn * center);
+            volume.integrate(&depth, DEPTH_UNITS, &intrinsics(), None, &pose);
+        }
+
+        let mesh = volume.extract_mesh();
+        assert!(
+            mesh.triangles.len() > 1000,
+            "{} triangles",
+            mesh.triangles.len()
+        );
+        assert!(mesh.colors.is_none());
+        for vertex in &mesh.vertices {
+            let error = (vertex.distance(center) - radius).abs();
+            assert!(error < 0.5 * volume.voxel_size, "{vertex} is {error} m off");
+        }
+        for triangle in &mesh.triangles {
+            let centroid = triangle
+                .map(|index| mesh.vertices[index as usize])
+                .iter()
+                .sum::<Vec3>()
+                / 3.0;
+            let outward = (centroid - center).normalize();
+            assert!(
+                normal(&mesh, *triangle).dot(outward) >= 0.0,
+                "{triangle:?} faces in"
+            );
+        }
+        assert_eq!(check_topology(&mesh), 0, "The sphere isn't closed");
+    }
+
+    #[test]
+    fn tilted_plane() {
+        // Facing the camera, with the normal towards it
+        let normal_to_camera = Vec3::new(0.3, -0.2, -1.0).normalize();
+        let offset = normal_to_camera.dot(Vec3::new(0.0, 0.0, 0.8));
+        let depth = render(|ray| Some(offset / normal_to_camera.dot(ray)));
+        let mut volume =
+            TsdfVolume::new(Vec3::new(-0.2, -0.2, 0.5), Vec3::new(0.2, 0.2, 1.1), 0.01);
+        volume.integrate(
+            &depth,
+            DEPTH_UNITS,
+            &intrinsics(),
+            None,
+            &Affine3A::IDENTITY,
+        );
+
+        let mesh = volume.extract_mesh();
+        assert!(
+            mesh.triangles.len() > 1000,
+            "{} triangles",
+            mesh.triangles.len()
+        );
+        for vertex in &mesh.vertices {
+            let error = (normal_to_camera.dot(*vertex) - offset).abs();
+            assert!(error < 0.5 * volume.voxel_size, "{vertex} is {error} m off");
+        }
+        for triangle in &mesh.triangles {
+            let facing = normal(&mesh, *triangle).dot(normal_to_camera);
+            assert!(facing >= 0.0, "{triangle:?} faces away");
+        }
+        // Open along the sides of the volume only
+        assert!(check_topology(&mesh) > 0);
+    }
+
+    #[test]
+    fn colors_follow_the_pixels() {
+        let depth = vec![(0.8 / DEPTH_UNITS) as u16; 160 * 120];
+        let colors = vec![[200, 100, 50]; 160 * 120];
+        let mut volume =
+            TsdfVolume::new(Vec3::new(-0.1, -0.1, 0.6), Vec3::new(0.1, 0.1, 1.0), 0.01);
+        volume.integrate(
+            &depth,
+            DEPTH_UNITS,
+            &intrinsics(),
+            Some(&colors),
+            &Affine3A::IDENTITY,
+        );
+        let mesh = volume.extract_mesh();
+        let colors = mesh.colors.unwrap();
+        assert_eq!(colors.len(), mesh.vertices.len());
+        assert!(colors.iter().all(|color| *color == [200, 100, 50]));
+
+        volume.reset();
+        assert!(volume.extract_mesh().vertices.is_empty());
+    }
+
+    #[test]
+    fn voxels_out_of_view_are_skipped() {
+        // A wall seen at an angle, from a camera moved and turned to see
+        // part of the volume only
+        let depth = render(|ray| Some(0.9 / ray.dot(Vec3::new(0.2, 0.0, 1.0).normalize())));
+        let pose = Affine3A::from_rotation_translation(
+            Quat::from_rotation_y(0.5) * Quat::from_rotation_x(-0.3),
+            Vec3::new(0.3, -0.1, 0.0),
+        );
+        let intrinsics = intrinsics();
+        let mut volume =
+            TsdfVolume::new(Vec3::new(-0.5, -0.5, 0.2), Vec3::new(0.5, 0.5, 1.2), 0.02);
+        volume.integrate(&depth, DEPTH_UNITS, &intrinsics, None, &pose);
+
+        // The voxels updated are those projecting on a measured pixel, not
+        // too far behind it
+        let to_camera = pose.inverse();
+        let mut observed = 0;
+        let [width, height, depth_len] = volume.dims();
+        for z in 0..depth_len {
+            for y in 0..height {
+                for x in 0..width {
+                    let point = to_camera.transform_point3(volume.position(x, y, z));
+                    let pixel = intrinsics.project(point).round();
+                    let inside = point.z > 0.0
+                        && pixel.cmpge(Vec2::ZERO).all()
+                        && pixel.x < intrinsics.width as f32
+                        && pixel.y < intrinsics.height as f32;
+                    let expected = inside && {
+                        let index = pixel.y as usize * intrinsics.width + pixel.x as usize;
+                        depth[index] as f32 * DEPTH_UNITS - point.z >= -volume.truncation
+                    };
+                    let weight = volume.weights[volume.index(x, y, z)];
+                    assert_eq!(weight > 0.0, expected, "Voxel {x}, {y}, {z} at {point}");
+                    observed += expected as usize;
+                }
+            }
+        }
+        let len = volume.weights.len();
+        assert!(
+            observed > len / 10 && observed < len / 2,
+            "{observed} of {len}"
+        );
+
+        // Nothing is seen looking away
+        volume.reset();
+        let away = Affine3A::from_rotation_y(std::f32::consts::PI);
+        volume.integrate(&depth, DEPTH_UNITS, &intrinsics, None, &away);
+        assert!(volume.weights.iter().all(|weight| *weight == 0.0));
+    }
+
+    #[test]
+    fn voxels_are_capped() {
+        let volume = TsdfVolume::new(Vec3::splat(-5.0), Vec3::splat(5.0), 0.001);
+        let [x, y, z] = volume.dims();
+        assert!(x * y * z <= MAX_VOXELS);
+        assert!(x * y * z > MAX_VOXELS / 2, "{x}x{y}x{z}");
+        assert!(volume.voxel_size > 0.04);
+        assert_eq!(volume.truncation, volume.voxel_size * TRUNCATION_VOXELS);
+
+        let volume = TsdfVolume::default();
+        assert_eq!(volume.voxel_size, DEFAULT_VOXEL_SIZE);
+        assert_eq!(volume.dims(), [101, 101, 101]);
+    }
+}